@@ -0,0 +1,313 @@
+//! Load benchmark of the host records: many hosts registering while clients list the whole
+//! store, against the rping server authenticating with API tokens, then with Kerberos
+//! through the fake acceptor so that the negotiation path runs without a KDC. The same
+//! load is then applied in process to the `HostStore` of the server and to the single
+//! `Mutex<HashMap>` it replaced, to compare both designs without the HTTP overhead.
+//!
+//! Run with `cargo bench --bench hosts`. The `BENCH_HOSTS`, `BENCH_WRITERS`,
+//! `BENCH_READERS` and `BENCH_SECONDS` variables tune the load.
+
+use std::collections::HashMap;
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use base64::Engine;
+use rocket::Shutdown;
+use rocket::config::{Config, LogLevel};
+use rocket::serde::json::serde_json;
+use rocket::tokio;
+use rocket::tokio::task::JoinSet;
+use rocket_krb5::{FakeAcceptor, SharedAcceptor};
+use rping::auth::{Authenticators, TokenStore, generate_token};
+use rping::launcher::build_server;
+use rping::store::HostStore;
+use rping::types::{Address, AddressSource, HostRecord, ServerOptions};
+
+/// Latencies of the requests of one kind.
+#[derive(Default)]
+struct Samples(Vec<Duration>);
+
+impl Samples {
+    fn report(&mut self, name: &str, elapsed: Duration) {
+        self.0.sort();
+        let percentile = |p: usize| {
+            self.0
+                .get((self.0.len() * p / 100).min(self.0.len().saturating_sub(1)))
+                .copied()
+                .unwrap_or_default()
+        };
+        println!(
+            "{:<18} {:>8} requests {:>10.0} req/s   p50 {:>8.2?}   p99 {:>8.2?}",
+            name,
+            self.0.len(),
+            self.0.len() as f64 / elapsed.as_secs_f64(),
+            percentile(50),
+            percentile(99)
+        );
+    }
+}
+
+/// Shape of the load applied to the records.
+#[derive(Clone, Copy)]
+struct Load {
+    hosts: usize,
+    writers: usize,
+    readers: usize,
+    seconds: u64,
+}
+
+fn setting(name: &str, default: usize) -> usize {
+    std::env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+fn hostname(i: usize) -> String {
+    format!("host{}.example.com", i)
+}
+
+/// Launches the rping server in process on an ephemeral port, with the given methods.
+async fn serve(auth: Authenticators) -> (Shutdown, u16) {
+    let port = TcpListener::bind("127.0.0.1:0")
+        .and_then(|l| l.local_addr())
+        .map(|a| a.port())
+        .expect("free port");
+    let config = Config {
+        port,
+        workers: 16,
+        log_level: LogLevel::Off,
+        ..Config::release_default()
+    };
+    let rocket = build_server(rocket::custom(config), auth, ServerOptions::default())
+        .ignite()
+        .await
+        .expect("valid rocket instance");
+    let shutdown = rocket.shutdown();
+    tokio::spawn(rocket.launch());
+
+    let deadline = Instant::now() + Duration::from_secs(30);
+    while TcpStream::connect(("127.0.0.1", port)).is_err() {
+        assert!(Instant::now() < deadline, "rping server did not start");
+        sleep(Duration::from_millis(50));
+    }
+    (shutdown, port)
+}
+
+async fn register(client: &reqwest::Client, url: &str, authorization: &str, hostname: String) {
+    let response = client
+        .post(url)
+        .header(reqwest::header::AUTHORIZATION, authorization)
+        .json(&rocket::serde::json::json!({ "hostname": hostname }))
+        .send()
+        .await
+        .expect("registration sent");
+    assert!(response.status().is_success(), "{}", response.status());
+}
+
+async fn list(client: &reqwest::Client, url: &str, authorization: &str) -> usize {
+    let body = client
+        .get(url)
+        .header(reqwest::header::AUTHORIZATION, authorization)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .expect("list answered")
+        .bytes()
+        .await
+        .expect("list read");
+    body.len()
+}
+
+/// Registers every host then applies the mixed load over HTTP, printing the latencies of
+/// each kind of request.
+async fn http_load(name: &str, auth: Authenticators, authorization: String, load: Load) {
+    let (shutdown, port) = serve(auth).await;
+    let url = Arc::new(format!("http://127.0.0.1:{}/api/v1/hosts", port));
+    let authorization = Arc::new(authorization);
+    let client = reqwest::Client::new();
+
+    // Seeding: every writer registers its share of the hosts.
+    let started = Instant::now();
+    let mut tasks = JoinSet::new();
+    for w in 0..load.writers {
+        let (client, url, authorization) = (client.clone(), url.clone(), authorization.clone());
+        tasks.spawn(async move {
+            let mut samples = Samples::default();
+            for i in (w..load.hosts).step_by(load.writers) {
+                let sent = Instant::now();
+                register(&client, &url, &authorization, hostname(i)).await;
+                samples.0.push(sent.elapsed());
+            }
+            samples
+        });
+    }
+    let mut seeding = Samples::default();
+    while let Some(samples) = tasks.join_next().await {
+        seeding.0.extend(samples.expect("writer finished").0);
+    }
+    seeding.report(&format!("{} seed", name), started.elapsed());
+
+    // Mixed load: registrations of known hosts while readers list the whole store.
+    let deadline = Instant::now() + Duration::from_secs(load.seconds);
+    let started = Instant::now();
+    let mut writes = JoinSet::new();
+    for w in 0..load.writers {
+        let (client, url, authorization) = (client.clone(), url.clone(), authorization.clone());
+        writes.spawn(async move {
+            let mut samples = Samples::default();
+            let mut i = w;
+            while Instant::now() < deadline {
+                let sent = Instant::now();
+                register(&client, &url, &authorization, hostname(i % load.hosts)).await;
+                samples.0.push(sent.elapsed());
+                i += load.writers;
+            }
+            samples
+        });
+    }
+    let mut reads = JoinSet::new();
+    for _ in 0..load.readers {
+        let (client, url, authorization) = (client.clone(), url.clone(), authorization.clone());
+        reads.spawn(async move {
+            let mut samples = Samples::default();
+            while Instant::now() < deadline {
+                let sent = Instant::now();
+                list(&client, &url, &authorization).await;
+                samples.0.push(sent.elapsed());
+            }
+            samples
+        });
+    }
+
+    let mut registrations = Samples::default();
+    while let Some(samples) = writes.join_next().await {
+        registrations.0.extend(samples.expect("writer finished").0);
+    }
+    let mut listings = Samples::default();
+    while let Some(samples) = reads.join_next().await {
+        listings.0.extend(samples.expect("reader finished").0);
+    }
+    let elapsed = started.elapsed();
+    registrations.report(&format!("{} register", name), elapsed);
+    listings.report(&format!("{} list", name), elapsed);
+    shutdown.notify();
+}
+
+/// Records of the hosts as kept in process, listed the way the listing route answers them.
+trait Records: Send + Sync {
+    fn insert(&self, hostname: String, record: HostRecord);
+    fn list(&self) -> usize;
+}
+
+/// The map the store replaced, listed by cloning it under the lock and serializing the
+/// copy, as its listing route did.
+impl Records for Mutex<HashMap<String, HostRecord>> {
+    fn insert(&self, hostname: String, record: HostRecord) {
+        self.lock().unwrap().insert(hostname, record);
+    }
+
+    fn list(&self) -> usize {
+        let hosts = self.lock().unwrap().clone();
+        serde_json::to_vec(&hosts).unwrap().len()
+    }
+}
+
+impl Records for HostStore {
+    fn insert(&self, hostname: String, record: HostRecord) {
+        self.upsert(&hostname, "bench", "bench", |_| record, |_, _| {});
+    }
+
+    fn list(&self) -> usize {
+        serde_json::to_vec(self).unwrap().len()
+    }
+}
+
+/// Registers known hosts from writer threads while reader threads list the whole map,
+/// printing the throughput of both.
+fn contend(name: &str, records: &dyn Records, load: Load) {
+    let record = |i: usize| HostRecord {
+        addresses: vec![Address {
+            ip: format!("10.0.{}.{}", i / 256 % 256, i % 256),
+            source: AddressSource::Observed,
+        }],
+        last_seen: i as u64,
+        ..Default::default()
+    };
+    for i in 0..load.hosts {
+        records.insert(hostname(i), record(i));
+    }
+
+    let (writes, reads) = (AtomicUsize::new(0), AtomicUsize::new(0));
+    let deadline = Instant::now() + Duration::from_secs(load.seconds);
+    std::thread::scope(|scope| {
+        for w in 0..load.writers {
+            let writes = &writes;
+            scope.spawn(move || {
+                let mut i = w;
+                while Instant::now() < deadline {
+                    records.insert(hostname(i % load.hosts), record(i));
+                    writes.fetch_add(1, Ordering::Relaxed);
+                    i += load.writers;
+                }
+            });
+        }
+        for _ in 0..load.readers {
+            let reads = &reads;
+            scope.spawn(move || {
+                while Instant::now() < deadline {
+                    records.list();
+                    reads.fetch_add(1, Ordering::Relaxed);
+                }
+            });
+        }
+    });
+
+    println!(
+        "{:<18} {:>10.0} writes/s {:>8.0} lists/s",
+        name,
+        writes.into_inner() as f64 / load.seconds as f64,
+        reads.into_inner() as f64 / load.seconds as f64
+    );
+}
+
+#[tokio::main]
+async fn main() {
+    let load = Load {
+        hosts: setting("BENCH_HOSTS", 5000),
+        writers: setting("BENCH_WRITERS", 32),
+        readers: setting("BENCH_READERS", 8),
+        seconds: setting("BENCH_SECONDS", 5) as u64,
+    };
+    println!(
+        "{} hosts, {} writers, {} readers, {}s",
+        load.hosts, load.writers, load.readers, load.seconds
+    );
+
+    // The token is bound to every host it registers.
+    let (token, entry) = generate_token("bench");
+    let bound: Vec<String> = (0..load.hosts).map(hostname).collect();
+    let tokens = TokenStore::parse(&format!("{} {}", entry, bound.join(","))).expect("token");
+    let auth = Authenticators {
+        tokens: Some(tokens),
+        ..Default::default()
+    };
+    http_load("token", auth, format!("Bearer {}", token), load).await;
+
+    let acceptor: SharedAcceptor = Arc::new(FakeAcceptor::new(Vec::new()));
+    let negotiation =
+        base64::engine::general_purpose::STANDARD.encode("fake:host/bench@EXAMPLE.COM");
+    let auth = Authenticators::kerberos(acceptor);
+    http_load("kerberos", auth, format!("Negotiate {}", negotiation), load).await;
+
+    println!(
+        "in process, {} writer threads, {} reader threads",
+        load.writers, load.readers
+    );
+    let mutex: Mutex<HashMap<String, HostRecord>> = Mutex::new(HashMap::new());
+    contend("Mutex<HashMap>", &mutex, load);
+    contend("HostStore", &HostStore::default(), load);
+}
@@ -4,49 +4,219 @@ use libgssapi::{
     context::{ClientCtx, CtxFlags},
     credential::{Cred, CredUsage},
     name::Name,
-    oid::{GSS_MECH_KRB5, GSS_MECH_SPNEGO, GSS_NT_KRB5_PRINCIPAL, OidSet},
+    oid::{
+        GSS_MECH_KRB5, GSS_MECH_SPNEGO, GSS_NT_HOSTBASED_SERVICE, GSS_NT_KRB5_PRINCIPAL, OidSet,
+    },
 };
 use reqwest::Url;
+use std::fmt::Debug;
+use std::time::Duration;
 
-/// Derives a Kerberos principal string from a given URL and realm.
+use crate::types::ServiceName;
+
+/// Derives the name of the Kerberos service to authenticate against.
+///
+/// An explicit service principal always wins. Otherwise the principal
+/// `HTTP/<host>@<realm>` is derived from the URL when a realm is given, and the
+/// host-based service name `HTTP@<host>` is used when it is not, leaving the realm
+/// resolution to the krb5 configuration.
 ///
 /// ### Parameters
 /// - `url`: The service URL as a string.
-/// - `realm`: The Kerberos realm as a string.
+/// - `realm`: The Kerberos realm as a string, possibly empty.
+/// - `service_principal`: Optional explicit service principal.
 ///
 /// ### Returns
-/// - `Option<String>`: The derived principal string in the format `HTTP/<domain>@<realm>`, or `None` if parsing fails.
+/// - `Option<ServiceName>`: The service name, or `None` if parsing fails.
 ///
 /// ### Example
 /// ```rust
-/// let principal = derive_principal_from_url("https://example.com".to_string(), "EXAMPLE.COM".to_string());
-/// assert_eq!(principal, Some("HTTP/example.com@EXAMPLE.COM".to_string()));
+/// let name = derive_service_name("https://example.com".to_string(), String::new(), None);
+/// assert_eq!(name, Some(ServiceName::HostBased("HTTP@example.com".to_string())));
 /// ```
-pub fn derive_principal_from_url(url: String, realm: String) -> Option<String> {
+pub fn derive_service_name(
+    url: String,
+    realm: String,
+    service_principal: Option<String>,
+) -> Option<ServiceName> {
+    if let Some(principal) = service_principal {
+        return Some(parse_service_principal(principal));
+    }
+
+    let host = host_from_url(url)?;
+    if realm.is_empty() {
+        Some(ServiceName::HostBased(format!("HTTP@{}", host)))
+    } else {
+        Some(ServiceName::Principal(format!("HTTP/{}@{}", host, realm)))
+    }
+}
+
+/// Parses a service principal given on the command line.
+/// Names without a `/` but with an `@` are host-based service names (`HTTP@host`),
+/// anything else is handled as a Kerberos principal.
+///
+/// ### Parameters
+/// - `principal`: The service principal as a string.
+///
+/// ### Returns
+/// - `ServiceName`: The parsed service name.
+///
+/// ### Example
+/// ```rust
+/// let name = parse_service_principal("HTTP/lb.example.com@EXAMPLE.COM".to_string());
+/// assert_eq!(name, ServiceName::Principal("HTTP/lb.example.com@EXAMPLE.COM".to_string()));
+/// ```
+pub fn parse_service_principal(principal: String) -> ServiceName {
+    if !principal.contains('/') && principal.contains('@') {
+        ServiceName::HostBased(principal)
+    } else {
+        ServiceName::Principal(principal)
+    }
+}
+
+/// Extracts the host part of a URL, either a domain or an IP address.
+///
+/// ### Parameters
+/// - `url`: The service URL as a string.
+///
+/// ### Returns
+/// - `Option<String>`: The host, or `None` if parsing fails.
+fn host_from_url(url: String) -> Option<String> {
     let parsed_url = Url::parse(url.as_str()).ok()?;
 
-    let domain = parsed_url
+    parsed_url
         .domain()
         .map(|s| s.to_string())
-        .or_else(|| Some(parsed_url.host().unwrap().to_string()));
+        .or_else(|| parsed_url.host().map(|h| h.to_string()))
+}
 
-    domain.and_then(|s| Some("HTTP/".to_string() + s.as_str() + "@" + realm.as_str()))
+/// Makes the krb5 library obtain and renew initiator tickets from a client keytab.
+///
+/// Tickets are kept in a memory credential cache owned by the process, so that an
+/// unattended agent never depends on, nor overwrites, the credential cache of a user.
+///
+/// ### Parameters
+/// - `keytab`: Path of the client keytab.
+///
+/// ### Returns
+/// - `Result<(), String>`: Ok if the keytab can be used, Err with error message otherwise.
+///
+/// ### Safety
+/// The environment of the process is written, which is only sound while no other thread
+/// may read it: call it from `main`, before the async runtime is built.
+///
+/// ### Example
+/// ```rust
+/// // SAFETY: the runtime is not started yet.
+/// unsafe { use_client_keytab("/etc/rping/client.keytab") }.unwrap();
+/// ```
+pub unsafe fn use_client_keytab(keytab: &str) -> Result<(), String> {
+    if !std::path::Path::new(keytab.trim_start_matches("FILE:")).exists() {
+        return Err(format!("Client keytab '{}' does not exist", keytab));
+    }
+
+    // SAFETY: forwarded to the caller, no other thread runs yet.
+    unsafe {
+        std::env::set_var("KRB5_CLIENT_KTNAME", keytab);
+        std::env::set_var("KRB5CCNAME", "MEMORY:rping");
+    }
+
+    Ok(())
+}
+
+/// Checks that a client keytab was selected with [`use_client_keytab`], the environment
+/// being only read once the runtime runs.
+///
+/// ### Parameters
+/// - `keytab`: Path of the client keytab.
+///
+/// ### Returns
+/// - `Result<(), String>`: Ok if the keytab is the selected one, Err with error message otherwise.
+pub fn check_client_keytab(keytab: &str) -> Result<(), String> {
+    match std::env::var("KRB5_CLIENT_KTNAME") {
+        Ok(selected) if selected == keytab => Ok(()),
+        _ => Err(format!(
+            "Client keytab '{}' is not selected, see use_client_keytab",
+            keytab
+        )),
+    }
+}
+
+/// Returns the remaining lifetime of the initiator credentials, obtaining them if needed.
+///
+/// ### Returns
+/// - `Option<Duration>`: The remaining lifetime, or `None` if no credentials can be acquired.
+///
+/// ### Example
+/// ```rust
+/// let lifetime = initiator_lifetime();
+/// ```
+pub fn initiator_lifetime() -> Option<Duration> {
+    Cred::acquire(None, None, CredUsage::Initiate, None)
+        .and_then(|creds| creds.lifetime())
+        .ok()
+}
+
+/// Client side of a SPNEGO negotiation, one instance per security context.
+pub trait Negotiator: Send {
+    /// Steps the security context over the last server token.
+    ///
+    /// ### Parameters
+    /// - `server_token`: The token sent by the server, `None` to start the negotiation.
+    ///
+    /// ### Returns
+    /// - `Result<Option<Vec<u8>>, String>`: Ok with the next token to send, `None` once the
+    ///   context is complete, Err with error message otherwise.
+    fn step(&mut self, server_token: Option<&[u8]>) -> Result<Option<Vec<u8>>, String>;
+}
+
+/// Source of negotiators, abstracting the GSSAPI library away from the client so that
+/// it can be exercised without a KDC.
+pub trait Mechanism: Debug + Send + Sync {
+    /// Starts a new security context with the given service.
+    ///
+    /// ### Parameters
+    /// - `service_name`: The Kerberos service name.
+    ///
+    /// ### Returns
+    /// - `Result<Box<dyn Negotiator>, String>`: Ok with a fresh negotiator, Err with error message otherwise.
+    fn negotiator(&self, service_name: &ServiceName) -> Result<Box<dyn Negotiator>, String>;
 }
 
-/// Creates a GSSAPI client context for the given service principal name.
+/// Mechanism backed by the system GSSAPI library, negotiating Kerberos through SPNEGO.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Gssapi;
+
+impl Mechanism for Gssapi {
+    fn negotiator(&self, service_name: &ServiceName) -> Result<Box<dyn Negotiator>, String> {
+        create_context(service_name)
+            .map(|ctx| Box::new(ctx) as Box<dyn Negotiator>)
+            .ok_or(String::from("Could not create kerberos client context."))
+    }
+}
+
+impl Negotiator for ClientCtx {
+    fn step(&mut self, server_token: Option<&[u8]>) -> Result<Option<Vec<u8>>, String> {
+        ClientCtx::step(self, server_token, None)
+            .map(|token| token.map(|t| t.to_vec()))
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Creates a GSSAPI client context for the given service name.
 ///
 /// ### Parameters
-/// - `service_name`: The Kerberos principal name of the service as a string.
+/// - `service_name`: The Kerberos service name.
 ///
 /// ### Returns
 /// - `Option<ClientCtx>`: A new GSSAPI client context, or `None` if creation fails.
 ///
 /// ### Example
 /// ```rust
-/// let ctx = create_context("HTTP/example.com@EXAMPLE.COM".to_string());
+/// let ctx = create_context(&ServiceName::Principal("HTTP/example.com@EXAMPLE.COM".to_string()));
 /// assert!(ctx.is_some());
 /// ```
-pub fn create_context(service_name: String) -> Option<ClientCtx> {
+pub fn create_context(service_name: &ServiceName) -> Option<ClientCtx> {
     let mechs = {
         let mut s = OidSet::new().ok()?;
         s.add(&GSS_MECH_SPNEGO).ok()?;
@@ -55,7 +225,11 @@ pub fn create_context(service_name: String) -> Option<ClientCtx> {
 
     let creds = Cred::acquire(None, None, CredUsage::Initiate, Some(&mechs)).ok()?;
 
-    let name = Name::new(service_name.as_bytes(), Some(&GSS_NT_KRB5_PRINCIPAL)).ok()?;
+    let name = match service_name {
+        ServiceName::Principal(p) => Name::new(p.as_bytes(), Some(&GSS_NT_KRB5_PRINCIPAL)),
+        ServiceName::HostBased(h) => Name::new(h.as_bytes(), Some(&GSS_NT_HOSTBASED_SERVICE)),
+    }
+    .ok()?;
     let cname = name.canonicalize(Some(&GSS_MECH_KRB5)).ok()?;
 
     Some(ClientCtx::new(
@@ -66,31 +240,34 @@ pub fn create_context(service_name: String) -> Option<ClientCtx> {
     ))
 }
 
-/// Generates a GSSAPI token for authentication, encoding it in base64.
+/// Generates a token for authentication, encoding it in base64.
 ///
 /// ### Parameters
-/// - `context`: Mutable reference to a GSSAPI client context.
+/// - `negotiator`: Mutable reference to the negotiator of the security context.
 /// - `server_token`: Optional server token as a byte vector.
 ///
 /// ### Returns
-/// - `Option<String>`: The base64-encoded token, or `None` if generation fails.
+/// - `Option<String>`: The base64-encoded token, or `None` if generation fails or the context is complete.
 ///
 /// ### Example
 /// ```rust
-/// let mut ctx = create_context("HTTP/example.com@EXAMPLE.COM".to_string()).unwrap();
-/// let token = generate_token(&mut ctx, None);
+/// let service = ServiceName::Principal("HTTP/example.com@EXAMPLE.COM".to_string());
+/// let mut negotiator = Gssapi.negotiator(&service).unwrap();
+/// let token = generate_token(negotiator.as_mut(), None);
 /// assert!(token.is_some());
 /// ```
-pub fn generate_token(context: &mut ClientCtx, server_token: Option<Vec<u8>>) -> Option<String> {
-    // we do an unwrap because we want a panic in case of a context error.
-    let token = match context.step(server_token.as_deref(), None) {
+pub fn generate_token(
+    negotiator: &mut dyn Negotiator,
+    server_token: Option<Vec<u8>>,
+) -> Option<String> {
+    let token = match negotiator.step(server_token.as_deref()) {
         Ok(opt) => opt,
         Err(e) => {
             println!("Step error: {}", e);
             None
         }
     }?;
-    let encoded_token = general_purpose::STANDARD.encode(&*token);
+    let encoded_token = general_purpose::STANDARD.encode(token);
 
     Some(encoded_token)
 }
@@ -1,149 +1,687 @@
 use std::collections::HashMap;
+use std::future::Future;
+use std::time::Duration;
 
-use reqwest::{Client, Response, StatusCode};
+use reqwest::{RequestBuilder, Response, StatusCode, header};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
 
 use crate::{
-    auth::{
-        create_context, derive_principal_from_url, generate_token, prepare_server_token_from_header,
+    auth::{Mechanism, derive_service_name, generate_token, prepare_server_token_from_header},
+    metadata::collect_metadata,
+    types::{
+        ClientOptions, ClusterStatus, Dns, DnsResponse, HealthReport, HostEvent, HostRecord,
+        ImportReport, RetryPolicy, ServiceName, StreamEvent,
     },
-    types::Dns,
 };
 
-/// Sends a DNS record to the server using Kerberos authentication.
+/// Failure of a single attempt, telling the retry loop whether it is worth trying again.
+enum AttemptError {
+    Retryable(String),
+    /// Refused for too many requests, not to be tried again before the given delay.
+    Throttled(String, Duration),
+    Fatal(String),
+}
+
+/// Error body answered by the rping server.
+#[derive(Deserialize)]
+struct ErrorBody {
+    message: String,
+    request_id: Option<String>,
+}
+
+/// Outcome of a registration, as answered by `/api/v1/hosts` or by the unversioned `/add` path.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Registration {
+    Result(DnsResponse),
+    Legacy { message: String },
+}
+
+/// Entry of the list of hosts, as answered by `/api/v1/hosts` or by the unversioned `/get` path.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Listed {
+    Record(HostRecord),
+    Address(String),
+}
+
+/// Sends a DNS record to the server, authenticating with Kerberos or the API token.
 ///
 /// ### Parameters
 /// - `hostname`: The DNS hostname to send.
-/// - `url`: The service URL as a string.
-/// - `realm`: The Kerberos realm as a string.
-/// - `maybe_retry`: Optional number of retries.
+/// - `options`: Options describing the server to reach.
 ///
 /// ### Returns
-/// - `Result<(), String>`: Ok if successful, Err with error message otherwise.
+/// - `Result<DnsResponse, String>`: Ok with the addresses saved by the server, Err with error message otherwise.
 ///
 /// ### Example
 /// ```rust
-/// send_dns("host1".to_string(), "https://example.com/api".to_string(), "EXAMPLE.COM".to_string(), None).await.unwrap();
+/// let options = ClientOptions { url: "https://example.com/api".to_string(), ..Default::default() };
+/// let response = send_dns("host1".to_string(), &options).await.unwrap();
+/// println!("Saved ip: {}", response.saved_ip);
 /// ```
-pub async fn send_dns(
-    hostname: String,
-    url: String,
-    realm: String,
-    maybe_retry: Option<usize>,
-) -> Result<(), String> {
-    let retry = maybe_retry.unwrap_or(5);
-    let service_principal = derive_principal_from_url(url.clone(), realm)
-        .ok_or("Could not parse principal from url.")?;
-    let mut context =
-        create_context(service_principal).ok_or("Could not create kerberos client context.")?;
+pub async fn send_dns(hostname: String, options: &ClientOptions) -> Result<DnsResponse, String> {
+    let url = options.url.clone();
 
     let client = reqwest::Client::new();
-    let mut map = HashMap::new();
-    map.insert("hostname", hostname);
+    let mut body = serde_json::json!({
+        "hostname": hostname,
+        "addresses": options.addresses,
+    });
+    if options.metadata {
+        body["metadata"] = serde_json::json!(collect_metadata(options.tags.clone()));
+    } else if !options.tags.is_empty() {
+        body["metadata"] = serde_json::json!({ "tags": options.tags });
+    }
 
-    let mut counter = 0;
-    let mut status: StatusCode = StatusCode::UNAUTHORIZED;
-    let mut server_tok: Option<Vec<u8>> = None;
+    let answer = with_retry(&options.retry, || {
+        authenticate(options, &url, || client.post(url.clone()).json(&body))
+    })
+    .await?;
 
-    loop {
-        let client_tok: Option<String> = generate_token(&mut context, server_tok);
+    match serde_json::from_str::<Registration>(&answer) {
+        Ok(Registration::Result(response)) => Ok(response),
+        Ok(Registration::Legacy { message }) => Ok(DnsResponse {
+            saved_ip: message
+                .strip_prefix("Saved ip: ")
+                .unwrap_or(&message)
+                .to_string(),
+            previous_ip: None,
+            changed: true,
+        }),
+        Err(e) => Err(format!("Parsing error: {}", e)),
+    }
+}
 
-        if client_tok.is_none() || status.is_success() {
-            break;
-        }
+/// Receives a list of DNS records from the server, authenticating with Kerberos or the API token.
+///
+/// ### Parameters
+/// - `options`: Options describing the server to reach.
+///
+/// ### Returns
+/// - `Result<Vec<Dns>, String>`: Ok with vector of DNS records, Err with error message otherwise.
+///
+/// ### Example
+/// ```rust
+/// let options = ClientOptions { url: "https://example.com/api".to_string(), ..Default::default() };
+/// let dns_list = receive_list(&options).await.unwrap();
+/// ```
+pub async fn receive_list(options: &ClientOptions) -> Result<Vec<Dns>, String> {
+    let map: HashMap<String, Listed> = fetch_json(options).await?;
 
-        let answer = client
-            .post(url.clone())
-            .header(
-                "Authorization",
-                "Negotiate ".to_string() + client_tok.unwrap().as_str(),
-            )
-            .json(&map)
-            .send()
-            .await
-            .map_err(|e| format!("Send error: {}", e))?;
+    Ok(map
+        .into_iter()
+        .map(|(hostname, entry)| match entry {
+            Listed::Record(record) => Dns::from_record(hostname, record),
+            Listed::Address(ip) => Dns::new(hostname, ip),
+        })
+        .collect())
+}
 
-        counter += 1;
-        status = answer.status();
+/// Fetches a JSON document from the server, authenticating with Kerberos or the API token.
+///
+/// ### Parameters
+/// - `options`: Options describing the server to reach, the URL naming the document.
+///
+/// ### Returns
+/// - `Result<T, String>`: Ok with the parsed document, Err with error message otherwise.
+///
+/// ### Example
+/// ```rust
+/// let options = ClientOptions { url: "https://example.com/api/v1/hosts".to_string(), ..Default::default() };
+/// let hosts: serde_json::Value = fetch_json(&options).await.unwrap();
+/// ```
+pub async fn fetch_json<T: DeserializeOwned>(options: &ClientOptions) -> Result<T, String> {
+    let url = options.url.clone();
 
-        if status == StatusCode::NOT_FOUND {
-            return Err(format!("The url: '{}' is not a valid endpoint", url));
-        }
+    let client = reqwest::Client::new();
 
-        let header_value = get_header(&answer)?;
-        server_tok = prepare_server_token_from_header(header_value);
-    }
+    let body = with_retry(&options.retry, || {
+        authenticate(options, &url, || client.get(url.clone()))
+    })
+    .await?;
+
+    serde_json::from_str::<T>(&body).map_err(|e| format!("Parsing error: {}", e))
+}
+
+/// Asks the server for its replication state. The cluster endpoint `/api/v1/cluster` is
+/// appended to the URL unless it already names it.
+///
+/// ### Parameters
+/// - `options`: Options describing the server to reach.
+///
+/// ### Returns
+/// - `Result<ClusterStatus, String>`: Ok with the state of the pulls of each peer, Err with error message otherwise.
+///
+/// ### Example
+/// ```rust
+/// let options = ClientOptions { url: "https://example.com".to_string(), ..Default::default() };
+/// let status = cluster_status(&options).await.unwrap();
+/// println!("{} knows {} hosts", status.node, status.hosts);
+/// ```
+pub async fn cluster_status(options: &ClientOptions) -> Result<ClusterStatus, String> {
+    let url = if options.url.ends_with("/api/v1/cluster") {
+        options.url.clone()
+    } else {
+        format!("{}/api/v1/cluster", options.url.trim_end_matches('/'))
+    };
+
+    fetch_json(&ClientOptions {
+        url,
+        ..options.clone()
+    })
+    .await
+}
+
+/// Exports a snapshot of the host records of the server, as an admin. The snapshot endpoint
+/// `/api/v1/admin/snapshot` is appended to the URL unless it already names it.
+///
+/// ### Parameters
+/// - `options`: Options describing the server to reach.
+///
+/// ### Returns
+/// - `Result<serde_json::Value, String>`: Ok with the snapshot as sent by the server, Err with error message otherwise.
+///
+/// ### Example
+/// ```rust
+/// let options = ClientOptions { url: "https://example.com".to_string(), ..Default::default() };
+/// let snapshot = export_snapshot(&options).await.unwrap();
+/// assert_eq!(snapshot["version"], 1);
+/// ```
+pub async fn export_snapshot(options: &ClientOptions) -> Result<serde_json::Value, String> {
+    fetch_json(&ClientOptions {
+        url: snapshot_url(options),
+        ..options.clone()
+    })
+    .await
+}
+
+/// Imports a snapshot on the server, as an admin. The server validates it before changing
+/// any record.
+///
+/// ### Parameters
+/// - `options`: Options describing the server to reach.
+/// - `snapshot`: The snapshot, as exported by a server.
+/// - `mode`: Either `merge` or `replace`.
+///
+/// ### Returns
+/// - `Result<ImportReport, String>`: Ok with the changes made, Err with error message otherwise.
+///
+/// ### Example
+/// ```rust
+/// let options = ClientOptions { url: "https://example.com".to_string(), ..Default::default() };
+/// let report = import_snapshot(&options, &snapshot, "merge").await.unwrap();
+/// println!("{} hosts created", report.created);
+/// ```
+pub async fn import_snapshot(
+    options: &ClientOptions,
+    snapshot: &serde_json::Value,
+    mode: &str,
+) -> Result<ImportReport, String> {
+    let url = snapshot_url(options);
+
+    let client = reqwest::Client::new();
+
+    let answer = with_retry(&options.retry, || {
+        authenticate(options, &url, || {
+            client
+                .post(url.clone())
+                .query(&[("mode", mode)])
+                .json(snapshot)
+        })
+    })
+    .await?;
 
-    if counter >= retry {
-        Err(String::from("Retry limit reached, aborting..."))
+    serde_json::from_str::<ImportReport>(&answer).map_err(|e| format!("Parsing error: {}", e))
+}
+
+/// Builds the URL of the snapshot endpoint of the server.
+fn snapshot_url(options: &ClientOptions) -> String {
+    if options.url.ends_with("/api/v1/admin/snapshot") {
+        options.url.clone()
     } else {
-        Ok(())
+        format!(
+            "{}/api/v1/admin/snapshot",
+            options.url.trim_end_matches('/')
+        )
     }
 }
 
-/// Receives a list of DNS records from the server using Kerberos authentication.
+/// Asks the server whether it is ready, without authenticating. The readiness endpoint
+/// `/readyz` is appended to the URL unless it already names a probe.
 ///
 /// ### Parameters
-/// - `url`: The service URL as a string.
-/// - `realm`: The Kerberos realm as a string.
-/// - `maybe_retry`: Optional number of retries.
+/// - `options`: Options describing the server to reach.
 ///
 /// ### Returns
-/// - `Result<Vec<Dns>, String>`: Ok with vector of DNS records, Err with error message otherwise.
+/// - `Result<HealthReport, String>`: Ok with the outcome of the checks, ready or not, Err with
+///   error message if the server cannot be reached.
 ///
 /// ### Example
 /// ```rust
-/// let dns_list = receive_list("https://example.com/api".to_string(), "EXAMPLE.COM".to_string(), None).await.unwrap();
+/// let options = ClientOptions { url: "https://example.com".to_string(), ..Default::default() };
+/// let report = check_health(&options).await.unwrap();
+/// assert_eq!(report.status, "ok");
 /// ```
-pub async fn receive_list(
-    url: String,
-    realm: String,
-    maybe_retry: Option<usize>,
-) -> Result<Vec<Dns>, String> {
-    let retry = maybe_retry.unwrap_or(5);
-    let service_principal = derive_principal_from_url(url.clone(), realm)
-        .ok_or("Could not parse principal from url.")?;
-    let mut context =
-        create_context(service_principal).ok_or("Could not create kerberos client context.")?;
+pub async fn check_health(options: &ClientOptions) -> Result<HealthReport, String> {
+    let url = if options.url.ends_with("/healthz") || options.url.ends_with("/readyz") {
+        options.url.clone()
+    } else {
+        format!("{}/readyz", options.url.trim_end_matches('/'))
+    };
 
     let client = reqwest::Client::new();
 
-    let mut counter = 0;
-    let mut status: StatusCode = StatusCode::UNAUTHORIZED;
-    let mut body: String = String::new();
-    let mut server_tok: Option<Vec<u8>> = None;
+    let body = with_retry(&options.retry, || async {
+        let answer = client
+            .get(url.clone())
+            .send()
+            .await
+            .map_err(classify_send_error)?;
+        if answer.status() == StatusCode::NOT_FOUND {
+            return Err(AttemptError::Fatal(format!(
+                "The url: '{}' is not a valid endpoint",
+                url
+            )));
+        }
+        get_body(answer).await.map_err(AttemptError::Retryable)
+    })
+    .await?;
+
+    serde_json::from_str::<HealthReport>(&body).map_err(|e| format!("Parsing error: {}", e))
+}
+
+/// Follows the stream of changes of the server, handing each of them to `handle` until the
+/// server closes the stream. Only connecting is retried, the caller resuming the stream with
+/// the returned event identifier.
+///
+/// ### Parameters
+/// - `options`: Options describing the server to reach.
+/// - `last_event_id`: The last event received before, to resume the stream after it.
+/// - `handle`: Closure called on every event of the stream.
+///
+/// ### Returns
+/// - `Result<Option<u64>, String>`: Ok with the last event received once the stream ends,
+///   Err with error message if the server cannot be reached.
+///
+/// ### Example
+/// ```rust
+/// let options = ClientOptions { url: "https://example.com/events".to_string(), ..Default::default() };
+/// let last_event_id = watch_events(&options, None, |event| println!("{:?}", event)).await.unwrap();
+/// ```
+pub async fn watch_events<F>(
+    options: &ClientOptions,
+    last_event_id: Option<u64>,
+    mut handle: F,
+) -> Result<Option<u64>, String>
+where
+    F: FnMut(StreamEvent),
+{
+    let url = options.url.clone();
+
+    let client = reqwest::Client::new();
 
-    loop {
-        let client_tok: Option<String> = generate_token(&mut context, server_tok);
+    let mut answer = with_retry(&options.retry, || {
+        authorized(options, &url, || {
+            let request = client
+                .get(url.clone())
+                .header("Accept", "text/event-stream");
+            match last_event_id {
+                Some(id) => request.header("Last-Event-ID", id.to_string()),
+                None => request,
+            }
+        })
+    })
+    .await?;
 
-        if client_tok.is_none() || status.is_success() {
-            break;
+    let mut last_id = last_event_id;
+    let mut buffer: Vec<u8> = Vec::new();
+    while let Ok(Some(chunk)) = answer.chunk().await {
+        buffer.extend_from_slice(&chunk);
+        while let Some(end) = buffer.windows(2).position(|w| w == b"\n\n") {
+            let block: Vec<u8> = buffer.drain(..end + 2).collect();
+            if let Some(event) = parse_sse(&String::from_utf8_lossy(&block)) {
+                if let StreamEvent::Change(change) = &event {
+                    last_id = Some(change.id);
+                }
+                handle(event);
+            }
         }
+    }
+
+    Ok(last_id)
+}
+
+/// Parses one Server-Sent Event, skipping the heartbeats and the events it does not know.
+///
+/// ### Parameters
+/// - `block`: The lines of the event.
+///
+/// ### Returns
+/// - `Option<StreamEvent>`: The event, if any.
+fn parse_sse(block: &str) -> Option<StreamEvent> {
+    let mut name = "message";
+    let mut data: Vec<&str> = Vec::new();
+    for line in block.lines() {
+        let (field, value) = line.split_once(':').unwrap_or((line, ""));
+        let value = value.strip_prefix(' ').unwrap_or(value);
+        match field {
+            "event" => name = value,
+            "data" => data.push(value),
+            _ => {}
+        }
+    }
+
+    match name {
+        "reset" => Some(StreamEvent::Reset),
+        _ if data.is_empty() => None,
+        _ => serde_json::from_str::<HostEvent>(&data.join("\n"))
+            .ok()
+            .map(StreamEvent::Change),
+    }
+}
+
+/// Resolves the Kerberos service name to authenticate against from the client options.
+///
+/// ### Parameters
+/// - `options`: Options describing the server to reach.
+///
+/// ### Returns
+/// - `Result<ServiceName, String>`: Ok with the service name, Err with error message otherwise.
+fn service_name_from_options(options: &ClientOptions) -> Result<ServiceName, String> {
+    derive_service_name(
+        options.url.clone(),
+        options.realm.clone(),
+        options.service_principal.clone(),
+    )
+    .ok_or(String::from("Could not parse principal from url."))
+}
+
+/// Runs an operation until it succeeds, fails fatally or exhausts the retry policy. The
+/// retries are told on the standard error, the standard output being kept for results.
+/// Throttled attempts wait at least as long as the server asked.
+///
+/// ### Parameters
+/// - `policy`: Retry policy bounding the number of attempts and the total duration.
+/// - `attempt`: Closure producing the future of one attempt.
+///
+/// ### Returns
+/// - `Result<T, String>`: Ok with the attempt result, Err with the last error message otherwise.
+async fn with_retry<T, F, Fut>(policy: &RetryPolicy, mut attempt: F) -> Result<T, String>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, AttemptError>>,
+{
+    let attempts = async {
+        let mut counter = 0;
+        loop {
+            counter += 1;
+            let (e, minimum) = match attempt().await {
+                Ok(value) => return Ok(value),
+                Err(AttemptError::Fatal(e)) => return Err(e),
+                Err(AttemptError::Retryable(e)) => (e, Duration::ZERO),
+                Err(AttemptError::Throttled(e, retry_after)) => (e, retry_after),
+            };
+            if counter >= policy.max_attempts {
+                return Err(format!(
+                    "Retry limit reached after {} attempts, aborting... ({})",
+                    counter, e
+                ));
+            }
+            let delay = policy.backoff(counter).max(minimum);
+            eprintln!("{}, retrying in {}ms", e, delay.as_millis());
+            tokio::time::sleep(delay).await;
+        }
+    };
+
+    match policy.deadline {
+        Some(deadline) => tokio::time::timeout(deadline, attempts)
+            .await
+            .map_err(|_| format!("Deadline of {:?} exceeded, aborting...", deadline))?,
+        None => attempts.await,
+    }
+}
+
+/// Sends one authenticated request and reads the body of the answer.
+///
+/// ### Parameters
+/// - `options`: Options describing the server to reach.
+/// - `url`: The service URL, used in error messages.
+/// - `build`: Closure building the request to authenticate.
+///
+/// ### Returns
+/// - `Result<String, AttemptError>`: Ok with the response body, Err with the attempt failure otherwise.
+async fn authenticate<F>(
+    options: &ClientOptions,
+    url: &str,
+    build: F,
+) -> Result<String, AttemptError>
+where
+    F: Fn() -> RequestBuilder,
+{
+    let answer = authorized(options, url, build).await?;
+    get_body(answer).await.map_err(AttemptError::Retryable)
+}
+
+/// Sends one authenticated request, presenting the API token when one is configured
+/// and negotiating with Kerberos otherwise.
+///
+/// ### Parameters
+/// - `options`: Options describing the server to reach.
+/// - `url`: The service URL, used in error messages.
+/// - `build`: Closure building the request to authenticate.
+///
+/// ### Returns
+/// - `Result<Response, AttemptError>`: Ok with the accepted response, Err with the attempt failure otherwise.
+async fn authorized<F>(
+    options: &ClientOptions,
+    url: &str,
+    build: F,
+) -> Result<Response, AttemptError>
+where
+    F: Fn() -> RequestBuilder,
+{
+    match &options.api_token {
+        Some(token) => bearer(token, url, build).await,
+        None => {
+            let service_name = service_name_from_options(options).map_err(AttemptError::Fatal)?;
+            negotiate(options.mechanism.as_ref(), &service_name, url, build).await
+        }
+    }
+}
+
+/// Sends one request authenticated by an API token.
+///
+/// ### Parameters
+/// - `token`: The API token.
+/// - `url`: The service URL, used in error messages.
+/// - `build`: Closure building the request to authenticate.
+///
+/// ### Returns
+/// - `Result<Response, AttemptError>`: Ok with the accepted response, Err with the attempt failure otherwise.
+async fn bearer<F>(token: &str, url: &str, build: F) -> Result<Response, AttemptError>
+where
+    F: Fn() -> RequestBuilder,
+{
+    let answer = build()
+        .bearer_auth(token)
+        .send()
+        .await
+        .map_err(classify_send_error)?;
 
-        let tok = client_tok.unwrap();
+    if matches!(
+        answer.status(),
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+    ) {
+        return Err(AttemptError::Fatal(explain(
+            "The server refused our API token.",
+            error_message(answer).await,
+        )));
+    }
 
-        let answer = send_get(&client, url.clone(), tok).await?;
+    check_status(answer, url).await
+}
+
+/// Performs one SPNEGO negotiation against the server, stepping a fresh client context
+/// until the server accepts the request.
+///
+/// ### Parameters
+/// - `mechanism`: The authentication mechanism providing the security context.
+/// - `service_name`: The Kerberos service name.
+/// - `url`: The service URL, used in error messages.
+/// - `build`: Closure building the request to authenticate.
+///
+/// ### Returns
+/// - `Result<Response, AttemptError>`: Ok with the accepted response, Err with the attempt failure otherwise.
+async fn negotiate<F>(
+    mechanism: &dyn Mechanism,
+    service_name: &ServiceName,
+    url: &str,
+    build: F,
+) -> Result<Response, AttemptError>
+where
+    F: Fn() -> RequestBuilder,
+{
+    let mut context = mechanism
+        .negotiator(service_name)
+        .map_err(AttemptError::Fatal)?;
 
-        counter += 1;
-        status = answer.status();
+    let mut server_tok: Option<Vec<u8>> = None;
 
-        if status == StatusCode::NOT_FOUND {
-            return Err(format!("The url: '{}' is not a valid endpoint", url));
+    while let Some(client_tok) = generate_token(context.as_mut(), server_tok) {
+        let answer = build()
+            .header(
+                "Authorization",
+                "Negotiate ".to_string() + client_tok.as_str(),
+            )
+            .send()
+            .await
+            .map_err(classify_send_error)?;
+
+        let answer = check_status(answer, url).await?;
+
+        let header_value = get_header(&answer);
+
+        if answer.status().is_success() {
+            // Let the context verify the mutual authentication token, if any.
+            if let Some(token) = header_value.ok().and_then(prepare_server_token_from_header) {
+                generate_token(context.as_mut(), Some(token));
+            }
+            return Ok(answer);
         }
 
-        let header_value = get_header(&answer)?;
-        body = get_body(answer).await?;
+        server_tok = prepare_server_token_from_header(header_value.map_err(AttemptError::Fatal)?);
+    }
+
+    Err(AttemptError::Fatal(format!(
+        "Kerberos negotiation with '{}' did not complete.",
+        service_name
+    )))
+}
 
-        server_tok = prepare_server_token_from_header(header_value);
+/// Classifies a transport error, connection failures and timeouts being worth a retry.
+///
+/// ### Parameters
+/// - `e`: The error returned by reqwest.
+///
+/// ### Returns
+/// - `AttemptError`: The classified error.
+fn classify_send_error(e: reqwest::Error) -> AttemptError {
+    let message = format!("Send error: {}", e);
+    if e.is_connect() || e.is_timeout() {
+        AttemptError::Retryable(message)
+    } else {
+        AttemptError::Fatal(message)
     }
+}
 
-    if counter >= retry {
-        return Err(String::from("Retry limit reached, aborting..."));
+/// Classifies a response status, server errors and throttled requests being worth a retry.
+/// The message the server gives for the failure, if any, is added to the error.
+///
+/// ### Parameters
+/// - `answer`: The response.
+/// - `url`: The service URL, used in error messages.
+///
+/// ### Returns
+/// - `Result<Response, AttemptError>`: Ok with the response if the negotiation can go on, Err otherwise.
+async fn check_status(answer: Response, url: &str) -> Result<Response, AttemptError> {
+    match answer.status() {
+        StatusCode::NOT_FOUND => Err(AttemptError::Fatal(format!(
+            "The url: '{}' is not a valid endpoint",
+            url
+        ))),
+        StatusCode::FORBIDDEN => Err(AttemptError::Fatal(explain(
+            "The server refused our kerberos credentials.",
+            error_message(answer).await,
+        ))),
+        StatusCode::TOO_MANY_REQUESTS => {
+            let retry_after = retry_after(&answer);
+            Err(AttemptError::Throttled(
+                explain("Too many requests", error_message(answer).await),
+                retry_after,
+            ))
+        }
+        s if s.is_server_error() => Err(AttemptError::Retryable(explain(
+            &format!("Server error: {}", s),
+            error_message(answer).await,
+        ))),
+        s if s.is_client_error() && s != StatusCode::UNAUTHORIZED => {
+            Err(AttemptError::Fatal(explain(
+                &format!("Request rejected: {}", s),
+                error_message(answer).await,
+            )))
+        }
+        _ => Ok(answer),
     }
+}
 
-    let map: HashMap<String, String> = serde_json::from_str::<HashMap<String, String>>(&body)
-        .map_err(|e| format!("Parsing error: {}", e))?;
+/// Reads the delay a throttled answer asks to wait, given in seconds by `Retry-After`.
+///
+/// ### Parameters
+/// - `answer`: The throttled response.
+///
+/// ### Returns
+/// - `Duration`: The delay, zero if the header is missing or is not a number of seconds.
+fn retry_after(answer: &Response) -> Duration {
+    answer
+        .headers()
+        .get(header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+        .map_or(Duration::ZERO, Duration::from_secs)
+}
 
-    Ok(map.into_iter().map(|i| Dns::new(i.0, i.1)).collect())
+/// Reads the message of an error answer, along with the request identifier to look for in
+/// the server logs.
+///
+/// ### Parameters
+/// - `answer`: The error response.
+///
+/// ### Returns
+/// - `Option<String>`: The message, None if the body is not an rping error.
+async fn error_message(answer: Response) -> Option<String> {
+    let body = answer.text().await.ok()?;
+    let error = serde_json::from_str::<ErrorBody>(&body).ok()?;
+    match error.request_id {
+        Some(id) => Some(format!("{} (request {})", error.message, id)),
+        None => Some(error.message),
+    }
+}
+
+/// Appends the message of the server, if any, to the description of a failure.
+///
+/// ### Parameters
+/// - `summary`: The description of the failure.
+/// - `message`: The message of the server.
+///
+/// ### Returns
+/// - `String`: The error message.
+fn explain(summary: &str, message: Option<String>) -> String {
+    match message {
+        Some(message) => format!("{}: {}", summary.trim_end_matches('.'), message),
+        None => summary.to_string(),
+    }
 }
 
 /// Extracts the WWW-Authenticate header from a response.
@@ -154,12 +692,8 @@ pub async fn receive_list(
 /// ### Returns
 /// - `Result<String, String>`: Ok with header value, Err with error message otherwise.
 fn get_header(answer: &Response) -> Result<String, String> {
-    let headers = {
-        let h = answer.headers();
-        h.clone()
-    };
-
-    let header = headers
+    let header = answer
+        .headers()
         .get("WWW-Authenticate")
         .ok_or("Fetching header error: Could not fetch WWW-Authenticate")?;
 
@@ -185,20 +719,366 @@ async fn get_body(answer: Response) -> Result<String, String> {
         .map_err(|e| format!("Error fetching body: {}", e))
 }
 
-/// Asynchronously sends a GET request with Kerberos token.
-///
-/// ### Parameters
-/// - `client`: Reference to a `Client` object.
-/// - `url`: The service URL as a string.
-/// - `token`: The Kerberos token as a string.
-///
-/// ### Returns
-/// - `Result<Response, String>`: Ok with response, Err with error message otherwise.
-async fn send_get(client: &Client, url: String, token: String) -> Result<Response, String> {
-    client
-        .get(url.clone())
-        .header("Authorization", "Negotiate ".to_string() + &token)
-        .send()
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+    use rocket::http::{Header, Status};
+    use rocket::request::{FromRequest, Outcome};
+    use rocket::response::stream::{Event, EventStream};
+    use rocket::serde::json::Json;
+    use rocket::{Request, State};
+    use rocket_krb5::{FakeAcceptor, KrbFairing, KrbToken, SharedAcceptor};
+
+    use super::{check_health, receive_list, send_dns, watch_events};
+    use crate::testing::launch;
+    use crate::{ClientOptions, FakeMechanism, RetryPolicy, StreamEvent};
+
+    /// Requests received by the test server, authenticated or not.
+    struct Hits(AtomicUsize);
+
+    /// Identifier of the last event the client received.
+    struct LastEventId(u64);
+
+    #[rocket::async_trait]
+    impl<'r> FromRequest<'r> for LastEventId {
+        type Error = ();
+        async fn from_request(request: &'r Request<'_>) -> Outcome<Self, ()> {
+            let id = request
+                .headers()
+                .get_one("Last-Event-ID")
+                .and_then(|id| id.parse().ok());
+            Outcome::Success(LastEventId(id.unwrap_or_default()))
+        }
+    }
+
+    #[rocket::get("/hosts")]
+    fn hosts(_token: KrbToken) -> Json<serde_json::Value> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        Json(serde_json::json!({
+            "h1": {"addresses": [
+                {"ip": "10.0.0.1", "source": "observed"},
+                {"ip": "192.168.1.5", "source": "asserted"}
+            ], "metadata": {"os": "Debian", "tags": {"site": "paris"}},
+            "last_seen": now.as_secs() - 120}
+        }))
+    }
+
+    #[rocket::get("/get")]
+    fn legacy_hosts(_token: KrbToken) -> Json<serde_json::Value> {
+        Json(serde_json::json!({"h1": "10.0.0.1"}))
+    }
+
+    #[rocket::post("/add")]
+    fn legacy_add(_token: KrbToken) -> Json<serde_json::Value> {
+        Json(serde_json::json!({"message": "Saved ip: 10.0.0.1"}))
+    }
+
+    #[rocket::get("/events")]
+    fn events(last: LastEventId, _token: KrbToken) -> EventStream![] {
+        EventStream! {
+            yield Event::comment("heartbeat");
+            yield Event::empty().event("reset");
+            for id in last.0 + 1..=last.0 + 2 {
+                yield Event::json(&serde_json::json!({
+                    "id": id, "event": "updated", "hostname": "h1",
+                    "old_ip": "10.0.0.1", "new_ip": "10.0.0.2",
+                    "principal": null, "timestamp": 0
+                }))
+                .id(id.to_string())
+                .event("updated");
+            }
+        }
+    }
+
+    #[rocket::get("/readyz")]
+    fn readyz() -> (Status, Json<serde_json::Value>) {
+        (
+            Status::ServiceUnavailable,
+            Json(serde_json::json!({"status": "unavailable", "checks": {"kerberos": "expired"}})),
+        )
+    }
+
+    #[rocket::post("/flaky")]
+    fn flaky(hits: &State<Hits>, _token: KrbToken) -> Result<Json<serde_json::Value>, Status> {
+        match hits.0.fetch_add(1, Ordering::SeqCst) {
+            0 | 1 => Err(Status::ServiceUnavailable),
+            _ => Ok(Json(serde_json::json!({
+                "saved_ip": "10.0.0.2",
+                "previous_ip": "10.0.0.1",
+                "changed": true
+            }))),
+        }
+    }
+
+    /// Answer refusing a request for too many requests.
+    #[derive(rocket::Responder)]
+    #[response(status = 429)]
+    struct Throttled(&'static str, Header<'static>);
+
+    #[rocket::post("/throttled")]
+    fn throttled(
+        hits: &State<Hits>,
+        _token: KrbToken,
+    ) -> Result<Json<serde_json::Value>, Throttled> {
+        match hits.0.fetch_add(1, Ordering::SeqCst) {
+            0 => Err(Throttled(
+                "Too many requests",
+                Header::new("Retry-After", "1"),
+            )),
+            _ => Ok(Json(serde_json::json!({
+                "saved_ip": "10.0.0.2",
+                "previous_ip": "10.0.0.2",
+                "changed": false
+            }))),
+        }
+    }
+
+    #[rocket::post("/invalid")]
+    fn invalid(_token: KrbToken) -> (Status, Json<serde_json::Value>) {
+        (
+            Status::BadRequest,
+            Json(serde_json::json!({
+                "code": 400,
+                "message": "Invalid address: nope",
+                "request_id": "4f2a"
+            })),
+        )
+    }
+
+    #[rocket::post("/down")]
+    fn down(hits: &State<Hits>, _token: KrbToken) -> Status {
+        hits.0.fetch_add(1, Ordering::SeqCst);
+        Status::InternalServerError
+    }
+
+    /// Launches the test server on an ephemeral port.
+    async fn serve(allowed: Vec<String>) -> (String, rocket::Shutdown) {
+        let acceptor: SharedAcceptor = Arc::new(FakeAcceptor::new(allowed));
+        let rocket = rocket::build()
+            .mount(
+                "/",
+                rocket::routes![
+                    hosts,
+                    legacy_hosts,
+                    legacy_add,
+                    events,
+                    readyz,
+                    flaky,
+                    throttled,
+                    invalid,
+                    down
+                ],
+            )
+            .manage(acceptor)
+            .manage(Hits(AtomicUsize::new(0)))
+            .attach(KrbFairing {});
+
+        launch(rocket).await
+    }
+
+    fn options(url: String, hello: bool, retry: RetryPolicy) -> ClientOptions {
+        ClientOptions {
+            url,
+            mechanism: Arc::new(FakeMechanism::new("alice@EXAMPLE.COM".to_string(), hello)),
+            retry,
+            ..Default::default()
+        }
+    }
+
+    fn fast_retry(max_attempts: usize) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            base_delay: Duration::from_millis(5),
+            max_delay: Duration::from_millis(20),
+            deadline: None,
+        }
+    }
+
+    #[rocket::async_test]
+    async fn negotiation_with_continuation_receives_list() {
+        let (url, shutdown) = serve(vec![]).await;
+
+        let dns = receive_list(&options(url + "/hosts", true, fast_retry(1)))
+            .await
+            .unwrap();
+
+        assert_eq!(dns.len(), 1);
+        assert_eq!(dns[0].hostname, "h1");
+        assert_eq!(dns[0].ip, "10.0.0.1, 192.168.1.5 (asserted)");
+        assert_eq!(dns[0].metadata.os.as_deref(), Some("Debian"));
+        assert!(dns[0].has_tags(&BTreeMap::from([("site".to_string(), "paris".to_string())])));
+        assert!(dns[0].age().unwrap().abs_diff(Duration::from_secs(120)) <= Duration::from_secs(1));
+        assert!(!dns[0].has_tags(&BTreeMap::from([("site".to_string(), "lyon".to_string())])));
+        shutdown.notify();
+    }
+
+    #[rocket::async_test]
+    async fn unversioned_payloads_are_understood() {
+        let (url, shutdown) = serve(vec![]).await;
+
+        let dns = receive_list(&options(url.clone() + "/get", false, fast_retry(1)))
+            .await
+            .unwrap();
+        let response = send_dns(
+            "h1".to_string(),
+            &options(url + "/add", false, fast_retry(1)),
+        )
         .await
-        .map_err(|e| format!("Send error: {}", e))
+        .unwrap();
+
+        assert_eq!(
+            (dns[0].hostname.as_str(), dns[0].ip.as_str()),
+            ("h1", "10.0.0.1")
+        );
+        assert_eq!(response.saved_ip, "10.0.0.1");
+        shutdown.notify();
+    }
+
+    #[rocket::async_test]
+    async fn event_stream_resumes_after_last_event() {
+        let (url, shutdown) = serve(vec![]).await;
+        let mut received = Vec::new();
+
+        let last = watch_events(
+            &options(url + "/events", false, fast_retry(1)),
+            Some(4),
+            |e| received.push(e),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(last, Some(6));
+        assert_eq!(received.len(), 3);
+        assert_eq!(received[0], StreamEvent::Reset);
+        let StreamEvent::Change(change) = &received[2] else {
+            panic!("{:?}", received[2]);
+        };
+        assert_eq!((change.id, change.hostname.as_str()), (6, "h1"));
+        assert_eq!(change.new_ip.as_deref(), Some("10.0.0.2"));
+        shutdown.notify();
+    }
+
+    #[rocket::async_test]
+    async fn health_reports_unready_servers() {
+        let (url, shutdown) = serve(vec![]).await;
+
+        let report = check_health(&options(url + "/", false, fast_retry(1)))
+            .await
+            .unwrap();
+
+        assert_eq!(report.status, "unavailable");
+        assert_eq!(report.checks["kerberos"], "expired");
+        shutdown.notify();
+    }
+
+    #[rocket::async_test]
+    async fn server_errors_are_retried() {
+        let (url, shutdown) = serve(vec![]).await;
+
+        let result = send_dns(
+            "h1".to_string(),
+            &options(url + "/flaky", false, fast_retry(3)),
+        )
+        .await;
+
+        let response = result.unwrap();
+        assert_eq!(response.saved_ip, "10.0.0.2");
+        assert_eq!(response.previous_ip.as_deref(), Some("10.0.0.1"));
+        assert!(response.changed);
+        shutdown.notify();
+    }
+
+    #[rocket::async_test]
+    async fn throttled_requests_wait_before_retrying() {
+        let (url, shutdown) = serve(vec![]).await;
+        let started = Instant::now();
+
+        let response = send_dns(
+            "h1".to_string(),
+            &options(url + "/throttled", false, fast_retry(2)),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.saved_ip, "10.0.0.2");
+        assert!(started.elapsed() >= Duration::from_secs(1));
+        shutdown.notify();
+    }
+
+    #[rocket::async_test]
+    async fn retry_limit_bounds_attempts() {
+        let (url, shutdown) = serve(vec![]).await;
+
+        let result = send_dns(
+            "h1".to_string(),
+            &options(url + "/down", false, fast_retry(3)),
+        )
+        .await;
+
+        let error = result.unwrap_err();
+        assert!(
+            error.starts_with("Retry limit reached after 3 attempts"),
+            "{}",
+            error
+        );
+        shutdown.notify();
+    }
+
+    #[rocket::async_test]
+    async fn forbidden_is_not_retried() {
+        let (url, shutdown) = serve(vec!["bob@EXAMPLE.COM".to_string()]).await;
+
+        let error = receive_list(&options(url + "/hosts", false, fast_retry(5)))
+            .await
+            .unwrap_err();
+
+        assert_eq!(error, "The server refused our kerberos credentials.");
+        shutdown.notify();
+    }
+
+    #[rocket::async_test]
+    async fn server_error_messages_are_surfaced() {
+        let (url, shutdown) = serve(vec![]).await;
+
+        let error = send_dns(
+            "h1".to_string(),
+            &options(url + "/invalid", false, fast_retry(5)),
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(
+            error,
+            "Request rejected: 400 Bad Request: Invalid address: nope (request 4f2a)"
+        );
+        shutdown.notify();
+    }
+
+    #[rocket::async_test]
+    async fn not_found_is_not_retried() {
+        let (url, shutdown) = serve(vec![]).await;
+
+        let error = receive_list(&options(url.clone() + "/nowhere", false, fast_retry(5)))
+            .await
+            .unwrap_err();
+
+        assert!(error.contains("is not a valid endpoint"), "{}", error);
+        shutdown.notify();
+    }
+
+    #[rocket::async_test]
+    async fn connection_errors_are_retried_until_deadline() {
+        let mut retry = fast_retry(usize::MAX);
+        retry.deadline = Some(Duration::from_millis(200));
+
+        let error = receive_list(&options("http://127.0.0.1:9".to_string(), false, retry))
+            .await
+            .unwrap_err();
+
+        assert!(error.starts_with("Deadline of"), "{}", error);
+    }
 }
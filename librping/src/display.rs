@@ -1,7 +1,7 @@
 use tabled::Table;
 use tabled::settings::Style;
 
-use crate::types::Dns;
+use crate::types::{ClusterStatus, Dns, HostEvent, OutputFormat};
 
 /// Displays a vector of DNS records in a modern table format on the console.
 ///
@@ -21,3 +21,84 @@ pub fn display_dns(dns: Vec<Dns>) {
 
     println!("{}", table);
 }
+
+/// Displays a vector of DNS records as a JSON array on the console, each record
+/// carrying its age in seconds.
+///
+/// ### Parameters
+/// - `dns`: A vector of `Dns` structs to display.
+///
+/// ### Example
+/// ```rust
+/// use crate::types::Dns;
+/// let dns_list = vec![Dns::new("host1".to_string(), "192.168.1.1".to_string())];
+/// display_json(dns_list);
+/// ```
+pub fn display_json(dns: Vec<Dns>) {
+    let records: Vec<serde_json::Value> = dns
+        .into_iter()
+        .map(|d| {
+            let age = d.age().map(|a| a.as_secs());
+            let mut record = serde_json::json!(d);
+            record["age"] = serde_json::json!(age);
+            record
+        })
+        .collect();
+
+    println!("{}", serde_json::Value::Array(records));
+}
+
+/// Displays a vector of DNS records in the requested format.
+///
+/// ### Parameters
+/// - `dns`: A vector of `Dns` structs to display.
+/// - `format`: The output format.
+pub fn display(dns: Vec<Dns>, format: OutputFormat) {
+    match format {
+        OutputFormat::Table => display_dns(dns),
+        OutputFormat::Json => display_json(dns),
+    }
+}
+
+/// Displays a change streamed by the server on one line, in the requested format.
+///
+/// ### Parameters
+/// - `event`: The change to display.
+/// - `format`: The output format.
+///
+/// ### Example
+/// ```rust
+/// // [42] updated h1: 10.0.0.1 -> 10.0.0.2 by host/h1@EXAMPLE.COM
+/// display_event(&event, OutputFormat::Table);
+/// ```
+pub fn display_event(event: &HostEvent, format: OutputFormat) {
+    match format {
+        OutputFormat::Table => println!(
+            "[{}] {} {}: {} -> {} by {}",
+            event.id,
+            event.event,
+            event.hostname,
+            event.old_ip.as_deref().unwrap_or("-"),
+            event.new_ip.as_deref().unwrap_or("-"),
+            event.principal.as_deref().unwrap_or("server")
+        ),
+        OutputFormat::Json => println!("{}", serde_json::json!(event)),
+    }
+}
+
+/// Displays the replication state of a server in the requested format.
+///
+/// ### Parameters
+/// - `status`: The replication state.
+/// - `format`: The output format.
+pub fn display_cluster(status: &ClusterStatus, format: OutputFormat) {
+    match format {
+        OutputFormat::Table => {
+            println!("Node {} with {} hosts", status.node, status.hosts);
+            let mut table = Table::new(&status.peers);
+            table.with(Style::modern());
+            println!("{}", table);
+        }
+        OutputFormat::Json => println!("{}", serde_json::json!(status)),
+    }
+}
@@ -0,0 +1,451 @@
+use std::future::Future;
+
+use hickory_resolver::TokioAsyncResolver;
+use tokio::task::JoinSet;
+
+use crate::types::ClientOptions;
+
+/// Path of the hosts API on the servers found through a DNS SRV name.
+const SRV_PATH: &str = "/api/v1/hosts";
+
+/// How the servers of the client options are contacted.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Strategy {
+    /// One after the other, in the order they are given.
+    #[default]
+    Ordered,
+    /// All at once.
+    Parallel,
+}
+
+impl Strategy {
+    /// Parses a strategy name as given on the command line.
+    ///
+    /// ### Parameters
+    /// - `value`: Either `ordered` or `parallel`.
+    ///
+    /// ### Returns
+    /// - `Result<Strategy, &'static str>`: Ok with the strategy, Err otherwise.
+    ///
+    /// ### Example
+    /// ```rust
+    /// assert_eq!(Strategy::parse("parallel"), Ok(Strategy::Parallel));
+    /// ```
+    pub fn parse(value: &str) -> Result<Strategy, &'static str> {
+        match value {
+            "ordered" => Ok(Strategy::Ordered),
+            "parallel" => Ok(Strategy::Parallel),
+            _ => Err("Unknown failover strategy"),
+        }
+    }
+}
+
+/// Outcome of a request to one of the servers.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerResult<T> {
+    /// URL the request was sent to.
+    pub url: String,
+    pub outcome: Result<T, String>,
+}
+
+/// A server found through a DNS SRV record.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SrvTarget {
+    pub priority: u16,
+    pub weight: u16,
+    pub port: u16,
+    /// Hostname of the server, with or without the trailing dot.
+    pub target: String,
+}
+
+/// Lists the URLs of the servers to reach: the URL of the options, the failover URLs, then
+/// the servers of the SRV name if one is given.
+///
+/// ### Parameters
+/// - `options`: Options describing the servers to reach.
+///
+/// ### Returns
+/// - `Result<Vec<String>, String>`: Ok with the URLs in the order they are tried, Err if the SRV
+///   name cannot be resolved or no server is given.
+///
+/// ### Example
+/// ```rust
+/// let options = ClientOptions { srv: Some("_rping._tcp.example.com".to_string()), ..Default::default() };
+/// let urls = server_urls(&options).await.unwrap();
+/// ```
+pub async fn server_urls(options: &ClientOptions) -> Result<Vec<String>, String> {
+    let mut urls: Vec<String> = std::iter::once(&options.url)
+        .chain(&options.failover_urls)
+        .filter(|url| !url.is_empty())
+        .cloned()
+        .collect();
+
+    if let Some(name) = &options.srv {
+        let resolver = TokioAsyncResolver::tokio_from_system_conf()
+            .map_err(|e| format!("Cannot read the DNS configuration: {}", e))?;
+        let records = resolver
+            .srv_lookup(name.as_str())
+            .await
+            .map_err(|e| format!("Cannot resolve {}: {}", name, e))?;
+        let targets = records
+            .iter()
+            .map(|srv| SrvTarget {
+                priority: srv.priority(),
+                weight: srv.weight(),
+                port: srv.port(),
+                target: srv.target().to_utf8(),
+            })
+            .collect();
+        for url in srv_urls(targets) {
+            if !urls.contains(&url) {
+                urls.push(url);
+            }
+        }
+    }
+
+    if urls.is_empty() {
+        return Err(String::from("No server to reach"));
+    }
+    Ok(urls)
+}
+
+/// Turns SRV records into server URLs, the lowest priority first. Records of equal priority
+/// are ordered at random, each being picked first in proportion to its weight as RFC 2782
+/// describes. A `.` target, telling that the service is not offered, is skipped.
+///
+/// ### Parameters
+/// - `targets`: The SRV records.
+///
+/// ### Returns
+/// - `Vec<String>`: The URLs of the hosts API of the servers, over HTTPS.
+///
+/// ### Example
+/// ```rust
+/// let urls = srv_urls(vec![SrvTarget { priority: 10, weight: 5, port: 8443, target: "a.example.com.".to_string() }]);
+/// assert_eq!(urls, vec!["https://a.example.com:8443/api/v1/hosts"]);
+/// ```
+pub fn srv_urls(mut targets: Vec<SrvTarget>) -> Vec<String> {
+    targets.retain(|t| !t.target.trim_end_matches('.').is_empty());
+    targets.sort_by_key(|t| t.priority);
+    targets
+        .chunk_by(|a, b| a.priority == b.priority)
+        .flat_map(|group| by_weight(group.to_vec()))
+        .map(|t| {
+            format!(
+                "https://{}:{}{}",
+                t.target.trim_end_matches('.'),
+                t.port,
+                SRV_PATH
+            )
+        })
+        .collect()
+}
+
+/// Orders SRV records of equal priority with the weighted selection of RFC 2782: records
+/// without weight come first in the running sums, so that they are rarely picked first.
+fn by_weight(mut group: Vec<SrvTarget>) -> Vec<SrvTarget> {
+    group.sort_by_key(|t| t.weight != 0);
+    let mut ordered = Vec::with_capacity(group.len());
+    while !group.is_empty() {
+        let total: u32 = group.iter().map(|t| u32::from(t.weight)).sum();
+        let pick = fastrand::u32(0..=total);
+        let mut running = 0;
+        let index = group
+            .iter()
+            .position(|t| {
+                running += u32::from(t.weight);
+                running >= pick
+            })
+            .unwrap_or(0);
+        ordered.push(group.remove(index));
+    }
+    ordered
+}
+
+/// Sends a request to every server, one after the other or all at once depending on the
+/// strategy of the options.
+///
+/// ### Parameters
+/// - `options`: Options describing the servers to reach.
+/// - `request`: Closure sending the request to the server of the options it is given.
+///
+/// ### Returns
+/// - `Result<Vec<ServerResult<T>>, String>`: Ok with the outcome on each server in the order of
+///   the servers, Err if the servers cannot be listed.
+///
+/// ### Example
+/// ```rust
+/// let results = on_every_server(&options, |options| async move { send_dns("host1".to_string(), &options).await }).await?;
+/// ```
+pub async fn on_every_server<T, F, Fut>(
+    options: &ClientOptions,
+    request: F,
+) -> Result<Vec<ServerResult<T>>, String>
+where
+    T: Send + 'static,
+    F: Fn(ClientOptions) -> Fut,
+    Fut: Future<Output = Result<T, String>> + Send + 'static,
+{
+    let urls = server_urls(options).await?;
+    let mut results = Vec::with_capacity(urls.len());
+
+    match options.strategy {
+        Strategy::Ordered => {
+            for url in urls {
+                let outcome = request(server_options(options, &url)).await;
+                results.push(ServerResult { url, outcome });
+            }
+        }
+        Strategy::Parallel => {
+            let mut requests = JoinSet::new();
+            for (index, url) in urls.into_iter().enumerate() {
+                let answer = request(server_options(options, &url));
+                requests.spawn(async move {
+                    (
+                        index,
+                        ServerResult {
+                            url,
+                            outcome: answer.await,
+                        },
+                    )
+                });
+            }
+            let mut answers = requests.join_all().await;
+            answers.sort_by_key(|(index, _)| *index);
+            results.extend(answers.into_iter().map(|(_, result)| result));
+        }
+    }
+
+    Ok(results)
+}
+
+/// Sends a request to the servers until one of them answers it. Servers are tried one after
+/// the other, or all at once with the first answer being kept, depending on the strategy of
+/// the options.
+///
+/// ### Parameters
+/// - `options`: Options describing the servers to reach.
+/// - `request`: Closure sending the request to the server of the options it is given.
+///
+/// ### Returns
+/// - `Result<Vec<ServerResult<T>>, String>`: Ok with the outcome on each server contacted, in the
+///   order they answered, the last one being the success if any. Err if the servers cannot be listed.
+///
+/// ### Example
+/// ```rust
+/// let results = on_first_server(&options, |options| async move { receive_list(&options).await }).await?;
+/// ```
+pub async fn on_first_server<T, F, Fut>(
+    options: &ClientOptions,
+    request: F,
+) -> Result<Vec<ServerResult<T>>, String>
+where
+    T: Send + 'static,
+    F: Fn(ClientOptions) -> Fut,
+    Fut: Future<Output = Result<T, String>> + Send + 'static,
+{
+    let urls = server_urls(options).await?;
+    let mut results = Vec::with_capacity(urls.len());
+
+    match options.strategy {
+        Strategy::Ordered => {
+            for url in urls {
+                let outcome = request(server_options(options, &url)).await;
+                let answered = outcome.is_ok();
+                results.push(ServerResult { url, outcome });
+                if answered {
+                    break;
+                }
+            }
+        }
+        Strategy::Parallel => {
+            let mut requests = JoinSet::new();
+            for url in urls {
+                let answer = request(server_options(options, &url));
+                requests.spawn(async move {
+                    ServerResult {
+                        url,
+                        outcome: answer.await,
+                    }
+                });
+            }
+            // The requests still running are aborted once the set is dropped.
+            while let Some(Ok(result)) = requests.join_next().await {
+                let answered = result.outcome.is_ok();
+                results.push(result);
+                if answered {
+                    break;
+                }
+            }
+        }
+    }
+
+    Ok(results)
+}
+
+/// Options of a request to one of the servers.
+fn server_options(options: &ClientOptions, url: &str) -> ClientOptions {
+    ClientOptions {
+        url: url.to_string(),
+        failover_urls: Vec::new(),
+        srv: None,
+        ..options.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::sync::Arc;
+
+    use rocket::Shutdown;
+    use rocket::serde::json::{Json, serde_json};
+    use rocket_krb5::{FakeAcceptor, KrbFairing, KrbToken, SharedAcceptor};
+
+    use super::{ServerResult, SrvTarget, Strategy, on_every_server, on_first_server, srv_urls};
+    use crate::testing::launch;
+    use crate::{ClientOptions, FakeMechanism, RetryPolicy, receive_list, send_dns};
+
+    #[rocket::get("/hosts")]
+    fn hosts(_token: KrbToken) -> Json<serde_json::Value> {
+        Json(serde_json::json!({"h1": {"addresses": [{"ip": "10.0.0.1", "source": "observed"}]}}))
+    }
+
+    #[rocket::post("/hosts")]
+    fn register(_token: KrbToken) -> Json<serde_json::Value> {
+        Json(serde_json::json!({"saved_ip": "10.0.0.1", "previous_ip": null, "changed": true}))
+    }
+
+    /// Launches a server answering the hosts API on an ephemeral port.
+    async fn serve() -> (String, Shutdown) {
+        let acceptor: SharedAcceptor = Arc::new(FakeAcceptor::default());
+        let rocket = rocket::build()
+            .mount("/", rocket::routes![hosts, register])
+            .manage(acceptor)
+            .attach(KrbFairing {});
+
+        let (url, shutdown) = launch(rocket).await;
+        (url + "/hosts", shutdown)
+    }
+
+    /// URL of a port nothing listens on.
+    fn unreachable() -> String {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        format!("http://127.0.0.1:{}/hosts", port)
+    }
+
+    fn options(urls: Vec<String>, strategy: Strategy) -> ClientOptions {
+        let mut urls = urls.into_iter();
+        ClientOptions {
+            url: urls.next().unwrap(),
+            failover_urls: urls.collect(),
+            strategy,
+            mechanism: Arc::new(FakeMechanism::new("alice@EXAMPLE.COM".to_string(), false)),
+            retry: RetryPolicy {
+                max_attempts: 1,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn answered<T>(results: &[ServerResult<T>]) -> Vec<(&str, bool)> {
+        results
+            .iter()
+            .map(|r| (r.url.as_str(), r.outcome.is_ok()))
+            .collect()
+    }
+
+    #[rocket::async_test]
+    async fn registrations_reach_every_server() {
+        let (first, shutdown_first) = serve().await;
+        let (second, shutdown_second) = serve().await;
+        let down = unreachable();
+
+        for strategy in [Strategy::Ordered, Strategy::Parallel] {
+            let options = options(vec![first.clone(), down.clone(), second.clone()], strategy);
+            let results = on_every_server(&options, |options| async move {
+                send_dns("h1".to_string(), &options).await
+            })
+            .await
+            .unwrap();
+
+            assert_eq!(
+                answered(&results),
+                vec![
+                    (first.as_str(), true),
+                    (down.as_str(), false),
+                    (second.as_str(), true)
+                ]
+            );
+        }
+        shutdown_first.notify();
+        shutdown_second.notify();
+    }
+
+    #[rocket::async_test]
+    async fn listings_are_read_from_the_first_server_answering() {
+        let (url, shutdown) = serve().await;
+        let down = unreachable();
+
+        let ordered = options(
+            vec![down.clone(), url.clone(), unreachable()],
+            Strategy::Ordered,
+        );
+        let results = on_first_server(
+            &ordered,
+            |options| async move { receive_list(&options).await },
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            answered(&results),
+            vec![(down.as_str(), false), (url.as_str(), true)]
+        );
+
+        let parallel = options(vec![down.clone(), url.clone()], Strategy::Parallel);
+        let results = on_first_server(
+            &parallel,
+            |options| async move { receive_list(&options).await },
+        )
+        .await
+        .unwrap();
+        let listed = results.last().unwrap();
+        assert_eq!(listed.url, url);
+        assert_eq!(listed.outcome.as_ref().unwrap()[0].hostname, "h1");
+        shutdown.notify();
+    }
+
+    #[test]
+    fn srv_records_are_tried_by_priority() {
+        let target = |priority, weight, target: &str| SrvTarget {
+            priority,
+            weight,
+            port: 8443,
+            target: target.to_string(),
+        };
+
+        let urls = srv_urls(vec![
+            target(20, 0, "c.example.com."),
+            target(10, 10, "b.example.com."),
+            target(10, 60, "a.example.com."),
+            target(0, 0, "."),
+        ]);
+
+        // The order of a and b depends on their weights and on chance.
+        let mut first = urls[..2].to_vec();
+        first.sort();
+        assert_eq!(
+            first,
+            vec![
+                "https://a.example.com:8443/api/v1/hosts",
+                "https://b.example.com:8443/api/v1/hosts",
+            ]
+        );
+        assert_eq!(urls[2..], ["https://c.example.com:8443/api/v1/hosts"]);
+    }
+}
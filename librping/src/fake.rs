@@ -0,0 +1,51 @@
+use crate::{
+    auth::{Mechanism, Negotiator},
+    types::ServiceName,
+};
+
+/// Deterministic mechanism for tests, speaking the plain text protocol of the
+/// `rocket-krb5` fake acceptor instead of Kerberos.
+#[derive(Clone, Debug)]
+pub struct FakeMechanism {
+    /// The client principal to authenticate as.
+    pub principal: String,
+    /// Whether to open with `fake-hello`, forcing one more round trip with the server.
+    pub hello: bool,
+}
+
+impl FakeMechanism {
+    /// Creates a fake mechanism authenticating as the given principal.
+    ///
+    /// ### Parameters
+    /// - `principal`: The client principal to authenticate as.
+    /// - `hello`: Whether the negotiation needs one more round trip.
+    ///
+    /// ### Returns
+    /// - `FakeMechanism`: New fake mechanism.
+    ///
+    /// ### Example
+    /// ```rust
+    /// let mechanism = FakeMechanism::new("alice@EXAMPLE.COM".to_string(), false);
+    /// ```
+    pub fn new(principal: String, hello: bool) -> FakeMechanism {
+        FakeMechanism { principal, hello }
+    }
+}
+
+impl Mechanism for FakeMechanism {
+    fn negotiator(&self, _service_name: &ServiceName) -> Result<Box<dyn Negotiator>, String> {
+        Ok(Box::new(self.clone()))
+    }
+}
+
+impl Negotiator for FakeMechanism {
+    fn step(&mut self, server_token: Option<&[u8]>) -> Result<Option<Vec<u8>>, String> {
+        let principal_token = format!("fake:{}", self.principal).into_bytes();
+        match server_token {
+            None if self.hello => Ok(Some(b"fake-hello".to_vec())),
+            None | Some(b"fake-challenge") => Ok(Some(principal_token)),
+            Some(b"fake-ok") => Ok(None),
+            Some(_) => Err(String::from("Unexpected fake server token")),
+        }
+    }
+}
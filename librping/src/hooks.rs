@@ -0,0 +1,134 @@
+use std::ffi::OsStr;
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+
+use tokio::process::Command;
+
+/// Runs a hook, the command being told about a change through its environment variables,
+/// such as `RPING_HOSTNAME`, `RPING_OLD_IP` and `RPING_NEW_IP`.
+///
+/// The output of the command is handed line by line to `output`, once the command exited,
+/// so that the agent can print it and the server log it.
+///
+/// ### Parameters
+/// - `command`: The executable to run.
+/// - `timeout`: Time after which the command is killed.
+/// - `name`: Name of the hook in the error messages, such as `Hook`.
+/// - `env`: The environment variables describing the change.
+/// - `output`: Called with each line the command wrote, standard output first.
+///
+/// ### Returns
+/// - `Result<(), String>`: Ok if the command succeeded, Err if it could not start,
+///   failed or timed out.
+///
+/// ### Example
+/// ```rust
+/// let response = send_dns(hostname.clone(), &options).await?;
+/// if response.changed {
+///     let env = [("RPING_HOSTNAME", hostname.as_str()), ("RPING_NEW_IP", &response.saved_ip)];
+///     run_hook(Path::new("/etc/rping/on-change"), Duration::from_secs(30), "Hook", env, |line| {
+///         println!("Hook: {}", line)
+///     })
+///     .await?;
+/// }
+/// ```
+pub async fn run_hook<I, K, V>(
+    command: &Path,
+    timeout: Duration,
+    name: &str,
+    env: I,
+    mut output: impl FnMut(&str),
+) -> Result<(), String>
+where
+    I: IntoIterator<Item = (K, V)>,
+    K: AsRef<OsStr>,
+    V: AsRef<OsStr>,
+{
+    let child = Command::new(command)
+        .envs(env)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("{} could not start {}: {}", name, command.display(), e))?;
+
+    let result = tokio::time::timeout(timeout, child.wait_with_output())
+        .await
+        .map_err(|_e| format!("{} killed after {:?}", name, timeout))?
+        .map_err(|e| format!("{} failed: {}", name, e))?;
+
+    for line in String::from_utf8_lossy(&result.stdout)
+        .lines()
+        .chain(String::from_utf8_lossy(&result.stderr).lines())
+    {
+        output(line);
+    }
+
+    if result.status.success() {
+        Ok(())
+    } else {
+        Err(format!("{} exited with {}", name, result.status))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use tempfile::TempDir;
+
+    use super::run_hook;
+
+    fn script(dir: &TempDir, body: &str) -> PathBuf {
+        let path = dir.path().join("hook.sh");
+        std::fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    #[rocket::async_test]
+    async fn hooks_receive_the_change_in_their_environment() {
+        let dir = TempDir::new().unwrap();
+        let hook = script(
+            &dir,
+            "echo \"$RPING_HOSTNAME $RPING_OLD_IP $RPING_NEW_IP\"\necho oops >&2\nexit 3",
+        );
+        let env = [
+            ("RPING_HOSTNAME", "h1"),
+            ("RPING_OLD_IP", "10.0.0.1"),
+            ("RPING_NEW_IP", "10.0.0.2"),
+        ];
+        let mut lines = Vec::new();
+
+        let error = run_hook(&hook, Duration::from_secs(5), "Hook", env, |line| {
+            lines.push(line.to_string())
+        })
+        .await
+        .unwrap_err();
+
+        assert_eq!(error, "Hook exited with exit status: 3");
+        assert_eq!(lines, vec!["h1 10.0.0.1 10.0.0.2", "oops"]);
+    }
+
+    #[rocket::async_test]
+    async fn hooks_are_killed_after_the_timeout() {
+        let dir = TempDir::new().unwrap();
+        let hook = script(&dir, "sleep 5");
+
+        let error = run_hook(
+            &hook,
+            Duration::from_millis(100),
+            "Hook updated for h1",
+            [("RPING_HOSTNAME", "h1")],
+            |_| {},
+        )
+        .await
+        .unwrap_err();
+
+        assert_eq!(error, "Hook updated for h1 killed after 100ms");
+    }
+}
@@ -1,7 +1,31 @@
 mod auth;
 mod client;
 mod display;
+mod failover;
+#[cfg(any(test, feature = "testing"))]
+mod fake;
+mod hooks;
+mod metadata;
+#[cfg(test)]
+mod testing;
 mod tools;
 mod types;
 
+pub use auth::{Gssapi, Mechanism, Negotiator, use_client_keytab};
+pub use client::{
+    check_health, cluster_status, export_snapshot, fetch_json, import_snapshot, receive_list,
+    send_dns, watch_events,
+};
+pub use failover::{
+    ServerResult, SrvTarget, Strategy, on_every_server, on_first_server, server_urls, srv_urls,
+};
+#[cfg(any(test, feature = "testing"))]
+pub use fake::FakeMechanism;
+pub use hooks::run_hook;
+pub use metadata::collect_metadata;
 pub use tools::*;
+pub use types::{
+    ClientOptions, ClusterStatus, Dns, DnsResponse, HealthReport, HostEvent, HostMetadata,
+    ImportReport, Interface, OutputFormat, PeerStatus, RetryPolicy, ServiceName, StreamEvent,
+    format_age,
+};
@@ -0,0 +1,76 @@
+use std::collections::BTreeMap;
+
+use crate::types::{HostMetadata, Interface};
+
+/// Collects the metadata describing the current host.
+///
+/// Each piece of information is read on a best-effort basis and left empty when
+/// it is not available on the platform.
+///
+/// ### Parameters
+/// - `tags`: Free-form tags to report along with the system information.
+///
+/// ### Returns
+/// - `HostMetadata`: The metadata of the host.
+///
+/// ### Example
+/// ```rust
+/// let metadata = collect_metadata(BTreeMap::from([("site".to_string(), "paris".to_string())]));
+/// assert!(metadata.version.is_some());
+/// ```
+pub fn collect_metadata(tags: BTreeMap<String, String>) -> HostMetadata {
+    HostMetadata {
+        os: os_name(),
+        kernel: read_trimmed("/proc/sys/kernel/osrelease"),
+        version: Some(env!("CARGO_PKG_VERSION").to_string()),
+        uptime: uptime(),
+        interfaces: interfaces(),
+        tags,
+    }
+}
+
+/// Reads the pretty name of the distribution, falling back to the platform name.
+fn os_name() -> Option<String> {
+    let pretty_name = std::fs::read_to_string("/etc/os-release")
+        .ok()
+        .and_then(|content| {
+            content
+                .lines()
+                .find_map(|l| l.strip_prefix("PRETTY_NAME="))
+                .map(|name| name.trim_matches('"').to_string())
+        });
+
+    pretty_name.or_else(|| Some(std::env::consts::OS.to_string()))
+}
+
+/// Reads the uptime of the host in seconds.
+fn uptime() -> Option<u64> {
+    read_trimmed("/proc/uptime")?
+        .split_whitespace()
+        .next()?
+        .parse::<f64>()
+        .ok()
+        .map(|seconds| seconds as u64)
+}
+
+/// Lists the addresses of the network interfaces, loopback excluded.
+fn interfaces() -> Vec<Interface> {
+    if_addrs::get_if_addrs()
+        .map(|interfaces| {
+            interfaces
+                .into_iter()
+                .filter(|i| !i.is_loopback())
+                .map(|i| Interface {
+                    ip: i.ip().to_string(),
+                    name: i.name,
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn read_trimmed(path: &str) -> Option<String> {
+    std::fs::read_to_string(path)
+        .ok()
+        .map(|content| content.trim().to_string())
+}
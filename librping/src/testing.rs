@@ -0,0 +1,36 @@
+use rocket::config::LogLevel;
+use rocket::fairing::AdHoc;
+use rocket::tokio::sync::oneshot;
+use rocket::{Build, Config, Rocket, Shutdown};
+
+/// Launches a Rocket instance on an ephemeral port of the loopback, for the tests needing
+/// a real server.
+///
+/// ### Parameters
+/// - `rocket`: The Rocket instance, its port and log level being overridden.
+///
+/// ### Returns
+/// - `(String, Shutdown)`: The base URL of the server and the handle stopping it.
+pub async fn launch(rocket: Rocket<Build>) -> (String, Shutdown) {
+    let config = Config {
+        port: 0,
+        log_level: LogLevel::Off,
+        ..Config::debug_default()
+    };
+    let (tx, rx) = oneshot::channel();
+    let rocket = rocket
+        .configure(config)
+        .attach(AdHoc::on_liftoff("Port", |rocket| {
+            Box::pin(async move {
+                let _ = tx.send(rocket.config().port);
+            })
+        }))
+        .ignite()
+        .await
+        .expect("valid rocket instance");
+    let shutdown = rocket.shutdown();
+    rocket::tokio::spawn(rocket.launch());
+
+    let port = rx.await.expect("server launched");
+    (format!("http://127.0.0.1:{}", port), shutdown)
+}
@@ -1,39 +1,495 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
 use crate::{
-    client::{receive_list, send_dns},
-    display::display_dns,
+    auth::{check_client_keytab, initiator_lifetime},
+    client::{
+        check_health, cluster_status, export_snapshot, import_snapshot, receive_list, send_dns,
+        watch_events,
+    },
+    display::{display, display_cluster, display_event},
+    failover::{ServerResult, on_every_server, on_first_server},
+    hooks::run_hook,
+    types::{ClientOptions, Dns, DnsResponse, OutputFormat, StreamEvent, format_age},
 };
 
-/// Lists DNS records from the server and displays them.
+/// Lists DNS records from the first server answering and displays them, keeping only the
+/// hosts carrying the tags of the options.
 ///
 /// ### Parameters
-/// - `url`: The service URL as a string.
-/// - `realm`: The Kerberos realm as a string.
+/// - `options`: Options describing the servers to reach.
 ///
 /// ### Example
 /// ```rust
-/// list("https://example.com/api".to_string(), "EXAMPLE.COM".to_string()).await;
+/// let options = ClientOptions { url: "https://example.com/api".to_string(), realm: "EXAMPLE.COM".to_string(), ..Default::default() };
+/// list(options).await;
 /// ```
-pub async fn list(url: String, realm: String) {
-    match receive_list(url, realm, None).await {
-        Ok(dns) => display_dns(dns),
+pub async fn list(options: ClientOptions) {
+    if let Err(e) = prepare_credentials(&options) {
+        println!("{}", e);
+        return;
+    }
+
+    match receive_from_first(&options).await {
+        Ok(dns) => display(
+            dns.into_iter()
+                .filter(|d| d.has_tags(&options.tags))
+                .collect(),
+            options.format,
+        ),
         Err(e) => println!("{}", e),
     }
 }
 
-/// Sends the current hostname as a DNS record to the server.
+/// Lists the hosts that stopped reporting, for monitoring checks. Hosts whose last
+/// registration time is unknown are reported as stale.
+///
+/// ### Parameters
+/// - `options`: Options describing the servers to reach.
+/// - `older_than`: Age above which a host is stale.
+///
+/// ### Returns
+/// - `Result<(), String>`: Ok if no host is stale, Err with error message otherwise.
+///
+/// ### Example
+/// ```rust
+/// let options = ClientOptions { url: "https://example.com/api".to_string(), ..Default::default() };
+/// stale(options, Duration::from_secs(3600)).await.unwrap();
+/// ```
+pub async fn stale(options: ClientOptions, older_than: Duration) -> Result<(), String> {
+    prepare_credentials(&options)?;
+
+    let stale: Vec<Dns> = receive_from_first(&options)
+        .await?
+        .into_iter()
+        .filter(|d| d.has_tags(&options.tags))
+        .filter(|d| d.age().is_none_or(|age| age > older_than))
+        .collect();
+
+    if stale.is_empty() {
+        println!("No host older than {}", format_age(older_than));
+        return Ok(());
+    }
+
+    let count = stale.len();
+    display(stale, options.format);
+    Err(format!(
+        "{} hosts did not report for more than {}",
+        count,
+        format_age(older_than)
+    ))
+}
+
+/// Checks whether the server is ready and prints the outcome of each of its checks,
+/// for scripts and orchestrators.
+///
+/// ### Parameters
+/// - `options`: Options describing the server to reach.
+///
+/// ### Returns
+/// - `Result<(), String>`: Ok if the server is ready, Err with error message otherwise.
+///
+/// ### Example
+/// ```rust
+/// let options = ClientOptions { url: "https://example.com".to_string(), ..Default::default() };
+/// health(options).await.unwrap();
+/// ```
+pub async fn health(options: ClientOptions) -> Result<(), String> {
+    let report = check_health(&options).await?;
+
+    for (check, outcome) in &report.checks {
+        println!("{}: {}", check, outcome);
+    }
+
+    match report.status.as_str() {
+        "ok" => {
+            println!("{} is ready", options.url);
+            Ok(())
+        }
+        status => Err(format!("{} is not ready ({})", options.url, status)),
+    }
+}
+
+/// Prints the replication state of a server with each of its peers, for operators and
+/// monitoring checks.
+///
+/// ### Parameters
+/// - `options`: Options describing the server to reach.
+///
+/// ### Returns
+/// - `Result<(), String>`: Ok if the last pull of every peer succeeded, Err with error message otherwise.
+///
+/// ### Example
+/// ```rust
+/// let options = ClientOptions { url: "https://example.com".to_string(), ..Default::default() };
+/// cluster(options).await.unwrap();
+/// ```
+pub async fn cluster(options: ClientOptions) -> Result<(), String> {
+    prepare_credentials(&options)?;
+
+    let status = cluster_status(&options).await?;
+    display_cluster(&status, options.format);
+
+    let failing = status
+        .peers
+        .iter()
+        .filter(|p| p.last_error.is_some() || p.last_success.is_none())
+        .count();
+    match failing {
+        0 => Ok(()),
+        n => Err(format!(
+            "{} of {} peers are not in sync with {}",
+            n,
+            status.peers.len(),
+            status.node
+        )),
+    }
+}
+
+/// Exports a snapshot of the host records of a server to a file, or prints it when no file
+/// is given, for migrations and backups.
+///
+/// ### Parameters
+/// - `options`: Options describing the server to reach, authenticated as an admin.
+/// - `path`: The file the snapshot is written to, standard output when `None`.
+///
+/// ### Returns
+/// - `Result<(), String>`: Ok once the snapshot is written, Err with error message otherwise.
+///
+/// ### Example
+/// ```rust
+/// let options = ClientOptions { url: "https://example.com".to_string(), ..Default::default() };
+/// admin_export(options, Some(PathBuf::from("rping.json"))).await.unwrap();
+/// ```
+pub async fn admin_export(options: ClientOptions, path: Option<PathBuf>) -> Result<(), String> {
+    prepare_credentials(&options)?;
+
+    let snapshot = export_snapshot(&options).await?;
+    let content = serde_json::to_string_pretty(&snapshot)
+        .map_err(|e| format!("Cannot serialize snapshot: {}", e))?;
+    match path {
+        Some(path) => {
+            std::fs::write(&path, content + "\n")
+                .map_err(|e| format!("Cannot write {}: {}", path.display(), e))?;
+            let hosts = snapshot["hosts"].as_object().map_or(0, |hosts| hosts.len());
+            eprintln!("Exported {} hosts to {}", hosts, path.display());
+        }
+        None => println!("{}", content),
+    }
+    Ok(())
+}
+
+/// Imports a snapshot file on a server and prints the changes made.
+///
+/// ### Parameters
+/// - `options`: Options describing the server to reach, authenticated as an admin.
+/// - `path`: The snapshot file, as written by [`admin_export`].
+/// - `mode`: Either `merge` or `replace`.
+///
+/// ### Returns
+/// - `Result<(), String>`: Ok once the snapshot is imported, Err with error message otherwise.
+///
+/// ### Example
+/// ```rust
+/// let options = ClientOptions { url: "https://example.com".to_string(), ..Default::default() };
+/// admin_import(options, PathBuf::from("rping.json"), "replace").await.unwrap();
+/// ```
+pub async fn admin_import(options: ClientOptions, path: PathBuf, mode: &str) -> Result<(), String> {
+    prepare_credentials(&options)?;
+
+    let content = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+    let snapshot: serde_json::Value = serde_json::from_str(&content)
+        .map_err(|e| format!("{} is not JSON: {}", path.display(), e))?;
+    let report = import_snapshot(&options, &snapshot, mode).await?;
+    match options.format {
+        OutputFormat::Table => println!(
+            "Imported {}: {} created, {} updated, {} kept, {} removed, {} events of history",
+            path.display(),
+            report.created,
+            report.updated,
+            report.kept,
+            report.removed,
+            report.history
+        ),
+        OutputFormat::Json => println!("{}", serde_json::json!(report)),
+    }
+    Ok(())
+}
+
+/// Prints the changes made to host records as the server streams them, reconnecting and
+/// resuming the stream whenever it is interrupted.
 ///
 /// ### Parameters
-/// - `url`: The service URL as a string.
-/// - `realm`: The Kerberos realm as a string.
+/// - `options`: Options describing the server to reach.
+///
+/// ### Returns
+/// - `Result<(), String>`: Err with error message once the server cannot be reached anymore.
+///
+/// ### Example
+/// ```rust
+/// let options = ClientOptions { url: "https://example.com/events".to_string(), ..Default::default() };
+/// watch(options).await.unwrap();
+/// ```
+pub async fn watch(options: ClientOptions) -> Result<(), String> {
+    prepare_credentials(&options)?;
+
+    let mut last_event_id = None;
+    loop {
+        last_event_id = watch_events(&options, last_event_id, |event| match event {
+            StreamEvent::Change(change) => display_event(&change, options.format),
+            StreamEvent::Reset => println!("Some changes were missed, list the hosts to catch up"),
+        })
+        .await?;
+
+        println!("Event stream closed, reconnecting");
+        tokio::time::sleep(options.retry.base_delay).await;
+    }
+}
+
+/// Sends the current hostname as a DNS record to every server, printing the outcome on
+/// each of them.
+///
+/// ### Parameters
+/// - `options`: Options describing the servers to reach.
 ///
 /// ### Example
 /// ```rust
-/// send("https://example.com/api".to_string(), "EXAMPLE.COM".to_string()).await;
+/// let options = ClientOptions { url: "https://example.com/api".to_string(), realm: "EXAMPLE.COM".to_string(), ..Default::default() };
+/// send(options).await;
 /// ```
-pub async fn send(url: String, realm: String) {
+pub async fn send(options: ClientOptions) {
+    if let Err(e) = prepare_credentials(&options) {
+        println!("{}", e);
+        return;
+    }
+
     let hostname = hostname::get().unwrap();
-    send_dns(hostname.into_string().unwrap(), url, realm, None)
-        .await
-        .err()
-        .inspect(|e| println!("{}", e));
+    let results = match register_everywhere(hostname.into_string().unwrap(), &options).await {
+        Ok(results) => results,
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    };
+
+    if let [result] = results.as_slice() {
+        match &result.outcome {
+            Ok(response) => println!("Saved ip: {}", response.saved_ip),
+            Err(e) => println!("{}", e),
+        }
+        return;
+    }
+
+    for result in &results {
+        match &result.outcome {
+            Ok(response) => println!("{}: saved ip {}", result.url, response.saved_ip),
+            Err(e) => println!("{}: {}", result.url, e),
+        }
+    }
+    let saved = results.iter().filter(|r| r.outcome.is_ok()).count();
+    println!("Registered on {} of {} servers", saved, results.len());
+}
+
+/// Runs the client as an agent, sending the current hostname to every server at a fixed interval.
+/// Failures are reported and the agent keeps going, so that unattended hosts resume reporting
+/// as soon as the server or the KDC is reachable again. The hook of the options is run whenever
+/// the addresses saved for the host differ from the ones saved by the previous registration.
+///
+/// ### Parameters
+/// - `options`: Options describing the server to reach.
+/// - `interval`: Time to wait between two registrations.
+///
+/// ### Returns
+/// - `Result<(), String>`: Err with error message if the agent cannot start.
+///
+/// ### Example
+/// ```rust
+/// let options = ClientOptions { url: "https://example.com/api".to_string(), client_keytab: Some("/etc/rping/client.keytab".to_string()), ..Default::default() };
+/// agent(options, Duration::from_secs(300)).await.unwrap();
+/// ```
+pub async fn agent(options: ClientOptions, interval: Duration) -> Result<(), String> {
+    prepare_credentials(&options)?;
+    let hostname = hostname::get()
+        .map_err(|e| format!("Cannot read hostname: {}", e))?
+        .to_string_lossy()
+        .to_string();
+    let mut saved_ip = None;
+
+    loop {
+        match initiator_lifetime() {
+            Some(lifetime) => println!("Initiator ticket valid for {}s", lifetime.as_secs()),
+            None => println!("No initiator ticket available"),
+        }
+
+        let results = register_everywhere(hostname.clone(), &options)
+            .await
+            .unwrap_or_else(|e| {
+                println!("{}", e);
+                Vec::new()
+            });
+        let mut saved = None;
+        for result in results {
+            match result.outcome {
+                Ok(response) => {
+                    println!(
+                        "Registered {} on {} with {}",
+                        hostname, result.url, response.saved_ip
+                    );
+                    saved.get_or_insert(response);
+                }
+                Err(e) => println!("{}: {}", result.url, e),
+            }
+        }
+
+        let change = saved.and_then(|response| address_change(&mut saved_ip, response));
+        if let (Some((old_ip, new_ip)), Some(hook)) = (change, &options.hook) {
+            let env = [
+                ("RPING_HOSTNAME", hostname.as_str()),
+                ("RPING_OLD_IP", &old_ip),
+                ("RPING_NEW_IP", &new_ip),
+            ];
+            if let Err(e) = run_hook(hook, options.hook_timeout, "Hook", env, |line| {
+                println!("Hook: {}", line)
+            })
+            .await
+            {
+                println!("{}", e);
+            }
+        }
+
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// Tells whether a registration changed the addresses of the host, comparing them with the
+/// ones saved by the previous registration of the agent rather than trusting each server,
+/// which would report the same change once per server. The first registration relies on
+/// the server.
+///
+/// ### Parameters
+/// - `saved_ip`: The addresses saved by the previous registration, updated.
+/// - `response`: The answer to the registration.
+///
+/// ### Returns
+/// - `Option<(String, String)>`: The old and new addresses if they changed, the old ones
+///   being empty for a host registered for the first time.
+fn address_change(
+    saved_ip: &mut Option<String>,
+    response: DnsResponse,
+) -> Option<(String, String)> {
+    let old_ip = match saved_ip.replace(response.saved_ip.clone()) {
+        Some(old_ip) => (old_ip != response.saved_ip).then_some(old_ip)?,
+        None if response.changed => response.previous_ip.unwrap_or_default(),
+        None => return None,
+    };
+    Some((old_ip, response.saved_ip))
+}
+
+/// Sends a registration to every server of the options.
+///
+/// ### Parameters
+/// - `hostname`: The DNS hostname to send.
+/// - `options`: Options describing the servers to reach.
+///
+/// ### Returns
+/// - `Result<Vec<ServerResult<DnsResponse>>, String>`: Ok with the outcome on each server, Err if
+///   the servers cannot be listed.
+async fn register_everywhere(
+    hostname: String,
+    options: &ClientOptions,
+) -> Result<Vec<ServerResult<DnsResponse>>, String> {
+    on_every_server(options, |options| {
+        let hostname = hostname.clone();
+        async move { send_dns(hostname, &options).await }
+    })
+    .await
+}
+
+/// Reads the hosts from the first server answering. When several servers are given, the
+/// ones that did not answer and the one read from are reported on the standard error.
+///
+/// ### Parameters
+/// - `options`: Options describing the servers to reach.
+///
+/// ### Returns
+/// - `Result<Vec<Dns>, String>`: Ok with the hosts, Err with error message if no server answered.
+async fn receive_from_first(options: &ClientOptions) -> Result<Vec<Dns>, String> {
+    let results = on_first_server(
+        options,
+        |options| async move { receive_list(&options).await },
+    )
+    .await?;
+    let failover = !options.failover_urls.is_empty() || options.srv.is_some();
+    let tried = results.len();
+
+    let mut last_error = String::from("No server to reach");
+    for result in results {
+        match result.outcome {
+            Ok(dns) => {
+                if failover {
+                    eprintln!("Listed from {}", result.url);
+                }
+                return Ok(dns);
+            }
+            Err(e) if failover => {
+                eprintln!("{}: {}", result.url, e);
+                last_error = format!("No server answered, tried {}", tried);
+            }
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error)
+}
+
+/// Checks that the Kerberos credentials described by the client options were set up.
+///
+/// ### Parameters
+/// - `options`: Options describing the server to reach.
+///
+/// ### Returns
+/// - `Result<(), String>`: Ok if the credentials are ready to be used, Err with error message otherwise.
+fn prepare_credentials(options: &ClientOptions) -> Result<(), String> {
+    match &options.client_keytab {
+        Some(keytab) => check_client_keytab(keytab),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::address_change;
+    use crate::DnsResponse;
+
+    fn response(saved_ip: &str, previous_ip: Option<&str>) -> DnsResponse {
+        DnsResponse {
+            saved_ip: saved_ip.to_string(),
+            previous_ip: previous_ip.map(str::to_string),
+            changed: previous_ip != Some(saved_ip),
+        }
+    }
+
+    #[test]
+    fn changes_are_told_once_across_servers() {
+        let mut saved_ip = None;
+
+        let first = address_change(&mut saved_ip, response("10.0.0.1", Some("10.0.0.1")));
+        let moved = address_change(&mut saved_ip, response("10.0.0.2", Some("10.0.0.1")));
+        // A lagging server reporting the change again, then the other one catching up.
+        let lagging = address_change(&mut saved_ip, response("10.0.0.2", Some("10.0.0.1")));
+        let back = address_change(&mut saved_ip, response("10.0.0.1", None));
+
+        assert_eq!(first, None);
+        assert_eq!(
+            moved,
+            Some(("10.0.0.1".to_string(), "10.0.0.2".to_string()))
+        );
+        assert_eq!(lagging, None);
+        assert_eq!(back, Some(("10.0.0.2".to_string(), "10.0.0.1".to_string())));
+        let mut fresh = None;
+        assert_eq!(
+            address_change(&mut fresh, response("10.0.0.1", None)),
+            Some((String::new(), "10.0.0.1".to_string()))
+        );
+    }
 }
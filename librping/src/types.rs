@@ -1,11 +1,27 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
 use tabled::Tabled;
 
-/// Represents a DNS entry with a hostname and IP address.
-/// Used for storing and displaying DNS records in the application.
-#[derive(Tabled)]
+use crate::auth::{Gssapi, Mechanism};
+use crate::failover::Strategy;
+
+/// Represents a DNS entry with a hostname, its IP addresses, the metadata reported by the host
+/// and the time it last reported. Used for storing and displaying DNS records in the application.
+#[derive(Debug, Serialize, Tabled)]
 pub struct Dns {
     pub hostname: String,
     pub ip: String,
+    #[tabled(rename = "system", display = "display_system")]
+    pub metadata: HostMetadata,
+    #[tabled(display = "display_tags")]
+    pub tags: BTreeMap<String, String>,
+    /// Time of the last registration, in seconds since the Unix epoch.
+    #[tabled(rename = "last seen", display = "display_last_seen")]
+    pub last_seen: Option<u64>,
 }
 
 impl Dns {
@@ -25,6 +41,423 @@ impl Dns {
     /// assert_eq!(dns.ip, "192.168.1.1");
     /// ```
     pub fn new(hostname: String, ip: String) -> Dns {
-        Dns { hostname, ip }
+        Dns {
+            hostname,
+            ip,
+            metadata: HostMetadata::default(),
+            tags: BTreeMap::new(),
+            last_seen: None,
+        }
+    }
+
+    /// Creates a new `Dns` struct from a host record sent by the server, asserted
+    /// addresses being marked as such.
+    ///
+    /// ### Parameters
+    /// - `hostname`: The DNS hostname as a string.
+    /// - `record`: The record of the host.
+    ///
+    /// ### Returns
+    /// - `Dns`: A new DNS record struct.
+    pub fn from_record(hostname: String, mut record: HostRecord) -> Dns {
+        let tags = std::mem::take(&mut record.metadata.tags);
+        let ip = record
+            .addresses
+            .iter()
+            .map(|a| match a.source.as_str() {
+                "asserted" => format!("{} (asserted)", a.ip),
+                _ => a.ip.clone(),
+            })
+            .collect::<Vec<String>>()
+            .join(", ");
+
+        Dns {
+            hostname,
+            ip,
+            metadata: record.metadata,
+            tags,
+            last_seen: record.last_seen.filter(|t| *t > 0),
+        }
+    }
+
+    /// Time elapsed since the host last reported, according to the local clock.
+    ///
+    /// ### Returns
+    /// - `Option<Duration>`: The age of the record, `None` if the server did not tell when it was seen.
+    ///
+    /// ### Example
+    /// ```rust
+    /// let dns = Dns::new("host1".to_string(), "192.168.1.1".to_string());
+    /// assert_eq!(dns.age(), None);
+    /// ```
+    pub fn age(&self) -> Option<Duration> {
+        age_of(self.last_seen)
+    }
+
+    /// Tells whether the host carries every given tag.
+    ///
+    /// ### Parameters
+    /// - `tags`: The `key=value` tags to look for.
+    ///
+    /// ### Returns
+    /// - `bool`: True if every tag is set to the same value on the host, false otherwise.
+    ///
+    /// ### Example
+    /// ```rust
+    /// let dns = Dns::new("host1".to_string(), "192.168.1.1".to_string());
+    /// assert!(dns.has_tags(&BTreeMap::new()));
+    /// ```
+    pub fn has_tags(&self, tags: &BTreeMap<String, String>) -> bool {
+        tags.iter().all(|(k, v)| self.tags.get(k) == Some(v))
+    }
+}
+
+fn display_system(metadata: &HostMetadata) -> String {
+    let mut parts = Vec::new();
+    if let Some(os) = &metadata.os {
+        parts.push(os.clone());
+    }
+    if let Some(version) = &metadata.version {
+        parts.push(format!("rping {}", version));
+    }
+    if let Some(uptime) = metadata.uptime {
+        parts.push(format!("up {}h", uptime / 3600));
+    }
+    parts.join(", ")
+}
+
+fn age_of(last_seen: Option<u64>) -> Option<Duration> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?;
+    Some(now.saturating_sub(Duration::from_secs(last_seen?)))
+}
+
+fn display_last_seen(last_seen: &Option<u64>) -> String {
+    match age_of(*last_seen) {
+        Some(age) => format!("{} ago", format_age(age)),
+        None => String::from("unknown"),
+    }
+}
+
+/// Formats an age with its largest unit, such as `42s`, `5m`, `3h` or `12d`.
+///
+/// ### Parameters
+/// - `age`: The age to format.
+///
+/// ### Returns
+/// - `String`: The formatted age.
+///
+/// ### Example
+/// ```rust
+/// assert_eq!(format_age(Duration::from_secs(7200)), "2h");
+/// ```
+pub fn format_age(age: Duration) -> String {
+    match age.as_secs() {
+        s if s < 60 => format!("{}s", s),
+        s if s < 3600 => format!("{}m", s / 60),
+        s if s < 86400 => format!("{}h", s / 3600),
+        s => format!("{}d", s / 86400),
+    }
+}
+
+fn display_tags(tags: &BTreeMap<String, String>) -> String {
+    tags.iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// Outcome of a registration as sent by the server.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct DnsResponse {
+    /// Addresses saved for the host.
+    pub saved_ip: String,
+    /// Addresses saved before the registration, `None` for a new host.
+    pub previous_ip: Option<String>,
+    /// Whether the registration changed the addresses of the host.
+    pub changed: bool,
+}
+
+/// Outcome of the readiness checks of a server.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct HealthReport {
+    /// `ok` if every check passed, `unavailable` otherwise.
+    pub status: String,
+    /// Outcome of each check by name, `ok` or the reason of the failure.
+    pub checks: BTreeMap<String, String>,
+}
+
+/// Replication state of a server, as answered by `/api/v1/cluster`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ClusterStatus {
+    /// Name of the server, the origin of the changes made on it.
+    pub node: String,
+    /// Number of hosts registered on the server.
+    pub hosts: usize,
+    pub peers: Vec<PeerStatus>,
+}
+
+/// Outcome of the pulls of a peer by a server.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Tabled)]
+pub struct PeerStatus {
+    /// Base URL of the peer.
+    pub url: String,
+    /// Time of the last successful pull, in seconds since the Unix epoch.
+    #[tabled(rename = "last sync", display = "display_last_seen")]
+    pub last_success: Option<u64>,
+    /// Number of changes of the peer applied since the server started.
+    pub applied: u64,
+    /// Error of the last pull, `None` if it succeeded.
+    #[tabled(rename = "last error", display = "display_error")]
+    pub last_error: Option<String>,
+}
+
+/// Changes made by the import of a snapshot, as answered by `/api/v1/admin/snapshot`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ImportReport {
+    /// Hosts of the snapshot the server did not know.
+    pub created: usize,
+    /// Hosts whose record was replaced by the one of the snapshot.
+    pub updated: usize,
+    /// Hosts of the snapshot whose record on the server was seen more recently.
+    pub kept: usize,
+    /// Hosts of the server missing from the snapshot, removed when replacing.
+    pub removed: usize,
+    /// Events of the history restored on the server.
+    pub history: usize,
+}
+
+fn display_error(error: &Option<String>) -> String {
+    error.clone().unwrap_or_else(|| String::from("-"))
+}
+
+/// Change made to a host record, as streamed by the server.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HostEvent {
+    /// Sequence number of the event, used to resume the stream.
+    pub id: u64,
+    /// One of `created`, `updated`, `expired` or `deleted`.
+    pub event: String,
+    pub hostname: String,
+    pub old_ip: Option<String>,
+    pub new_ip: Option<String>,
+    /// Principal that made the change, `None` when the server did.
+    pub principal: Option<String>,
+    /// Time of the change, in seconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+/// Event of the change stream of the server.
+#[derive(Clone, Debug, PartialEq)]
+pub enum StreamEvent {
+    /// A change made to a host record.
+    Change(HostEvent),
+    /// Some changes were missed, the list of hosts needs to be fetched again.
+    Reset,
+}
+
+/// Record of a host as sent by the server.
+#[derive(Debug, Deserialize)]
+pub struct HostRecord {
+    pub addresses: Vec<Address>,
+    #[serde(default)]
+    pub metadata: HostMetadata,
+    #[serde(default)]
+    pub last_seen: Option<u64>,
+}
+
+/// A network interface address of a host.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Interface {
+    pub name: String,
+    pub ip: String,
+}
+
+/// Metadata a host may report along with its registration, every field being optional.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HostMetadata {
+    /// Operating system name, such as `Debian GNU/Linux 12 (bookworm)`.
+    pub os: Option<String>,
+    /// Kernel release.
+    pub kernel: Option<String>,
+    /// Version of the rping client.
+    pub version: Option<String>,
+    /// Uptime of the host in seconds.
+    pub uptime: Option<u64>,
+    /// Addresses of the local network interfaces.
+    pub interfaces: Vec<Interface>,
+    /// Free-form `key=value` tags.
+    pub tags: BTreeMap<String, String>,
+}
+
+/// An address of a host, with its source: `observed` by the server or `asserted` by the host.
+#[derive(Debug, Deserialize)]
+pub struct Address {
+    pub ip: String,
+    pub source: String,
+}
+
+/// Retry policy applied to every request sent to the rping server.
+/// Failed attempts are retried with an exponential backoff and full jitter,
+/// the whole operation being bounded by an optional deadline.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts, the first one included.
+    pub max_attempts: usize,
+    /// Delay used for the first retry, doubled on every following one.
+    pub base_delay: Duration,
+    /// Upper bound of the delay between two attempts.
+    pub max_delay: Duration,
+    /// Optional total time allowed for the operation, retries included.
+    pub deadline: Option<Duration>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            deadline: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Computes the delay to wait after a failed attempt.
+    ///
+    /// ### Parameters
+    /// - `attempt`: The number of the attempt that just failed, starting at 1.
+    ///
+    /// ### Returns
+    /// - `Duration`: A random delay between zero and the exponential backoff ceiling.
+    ///
+    /// ### Example
+    /// ```rust
+    /// let policy = RetryPolicy::default();
+    /// assert!(policy.backoff(3) <= Duration::from_secs(2));
+    /// ```
+    pub fn backoff(&self, attempt: usize) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(31) as u32;
+        let ceiling = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(exponent))
+            .min(self.max_delay);
+
+        Duration::from_millis(fastrand::u64(0..=ceiling.as_millis() as u64))
+    }
+}
+
+/// Options shared by every client action, describing how to reach and authenticate
+/// against the rping server.
+#[derive(Clone, Debug)]
+pub struct ClientOptions {
+    /// The service URL.
+    pub url: String,
+    /// Other servers reached after `url`: registrations are sent to every server, listings
+    /// are read from the first one answering.
+    pub failover_urls: Vec<String>,
+    /// DNS SRV name, such as `_rping._tcp.example.com`, listing more servers to reach.
+    pub srv: Option<String>,
+    /// Whether the servers are contacted one after the other or all at once.
+    pub strategy: Strategy,
+    /// Kerberos realm of the service, discovered from the krb5 configuration when empty.
+    pub realm: String,
+    /// Explicit service principal, overriding the one derived from the URL.
+    /// Either a Kerberos principal (`HTTP/host@REALM`) or a host-based service name (`HTTP@host`).
+    pub service_principal: Option<String>,
+    /// Client keytab used to obtain and renew the initiator ticket without a user session,
+    /// selected with `use_client_keytab` before the async runtime starts.
+    pub client_keytab: Option<String>,
+    /// API token sent as a bearer token instead of negotiating with Kerberos.
+    pub api_token: Option<String>,
+    /// Addresses asserted when registering, such as LAN or VPN addresses, next to the
+    /// address the server observes.
+    pub addresses: Vec<String>,
+    /// Whether to report the system metadata of the host when registering.
+    pub metadata: bool,
+    /// Tags reported when registering, or required on the hosts when listing.
+    pub tags: BTreeMap<String, String>,
+    /// Output format of the listing actions.
+    pub format: OutputFormat,
+    /// Retry policy applied to every request.
+    pub retry: RetryPolicy,
+    /// Command run by the agent when the addresses of the host change.
+    pub hook: Option<PathBuf>,
+    /// Time after which a running hook is killed.
+    pub hook_timeout: Duration,
+    /// Authentication mechanism, the system GSSAPI library unless testing.
+    pub mechanism: Arc<dyn Mechanism>,
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        ClientOptions {
+            url: String::new(),
+            failover_urls: Vec::new(),
+            srv: None,
+            strategy: Strategy::Ordered,
+            realm: String::new(),
+            service_principal: None,
+            client_keytab: None,
+            api_token: None,
+            addresses: Vec::new(),
+            metadata: false,
+            tags: BTreeMap::new(),
+            format: OutputFormat::Table,
+            retry: RetryPolicy::default(),
+            hook: None,
+            hook_timeout: Duration::from_secs(30),
+            mechanism: Arc::new(Gssapi),
+        }
+    }
+}
+
+/// Output format of the listing actions.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    /// A table for humans.
+    Table,
+    /// A JSON array for scripts.
+    Json,
+}
+
+impl OutputFormat {
+    /// Parses an output format name as given on the command line.
+    ///
+    /// ### Parameters
+    /// - `value`: Either `table` or `json`.
+    ///
+    /// ### Returns
+    /// - `Result<OutputFormat, &'static str>`: Ok with the format, Err otherwise.
+    ///
+    /// ### Example
+    /// ```rust
+    /// assert_eq!(OutputFormat::parse("json"), Ok(OutputFormat::Json));
+    /// ```
+    pub fn parse(value: &str) -> Result<OutputFormat, &'static str> {
+        match value {
+            "table" => Ok(OutputFormat::Table),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err("Unknown output format"),
+        }
+    }
+}
+
+/// Name of the Kerberos service the client authenticates against.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ServiceName {
+    /// A full Kerberos principal, such as `HTTP/host@REALM`.
+    Principal(String),
+    /// A GSSAPI host-based service name, such as `HTTP@host`, whose realm is
+    /// resolved by the krb5 library.
+    HostBased(String),
+}
+
+impl std::fmt::Display for ServiceName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServiceName::Principal(name) | ServiceName::HostBased(name) => write!(f, "{}", name),
+        }
     }
 }
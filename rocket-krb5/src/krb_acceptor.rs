@@ -0,0 +1,124 @@
+use std::sync::Arc;
+
+use libgssapi::context::{SecurityContext, ServerCtx};
+use rocket::tokio::task;
+
+use crate::{KrbServerCreds, ReloadableCreds};
+
+/// Acceptor shared by the request guards, managed as Rocket state.
+pub type SharedAcceptor = Arc<dyn Acceptor>;
+
+/// Result of one acceptor step over a client token.
+#[derive(Debug, Default, PartialEq)]
+pub struct AcceptStep {
+    /// The authenticated client principal, set once the security context is complete.
+    pub principal: Option<String>,
+    /// Token to send back to the client, either to continue the negotiation or for
+    /// mutual authentication.
+    pub token: Option<Vec<u8>>,
+}
+
+/// Server side of a SPNEGO negotiation, abstracting the GSSAPI library away from
+/// the request guards so that they can be exercised without a KDC.
+#[rocket::async_trait]
+pub trait Acceptor: Send + Sync {
+    /// Steps a security context over a client token.
+    ///
+    /// ### Parameters
+    /// - `token`: The decoded token sent by the client.
+    ///
+    /// ### Returns
+    /// - `Result<AcceptStep, String>`: Ok with the step result, Err if the client must be rejected.
+    async fn step(&self, token: &[u8]) -> Result<AcceptStep, String>;
+
+    /// Checks that the acceptor can still accept negotiations, for readiness probes.
+    ///
+    /// ### Returns
+    /// - `Result<(), String>`: Ok if the acceptor is usable, Err with the reason otherwise.
+    async fn ready(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[rocket::async_trait]
+impl Acceptor for KrbServerCreds {
+    async fn ready(&self) -> Result<(), String> {
+        self.check()
+    }
+
+    async fn step(&self, token: &[u8]) -> Result<AcceptStep, String> {
+        self.accept(token)
+    }
+}
+
+impl KrbServerCreds {
+    /// Steps a new security context over a client token, blocking on the GSSAPI library.
+    ///
+    /// ### Parameters
+    /// - `token`: The decoded token sent by the client.
+    ///
+    /// ### Returns
+    /// - `Result<AcceptStep, String>`: Ok with the step result, Err if the client must be rejected.
+    pub fn accept(&self, token: &[u8]) -> Result<AcceptStep, String> {
+        let mut context = ServerCtx::new(Some(self.creds.clone()));
+
+        let maybe_token = context
+            .step(token)
+            .map_err(|e| format!("There is an error while stepping in server context: {}", e))?;
+
+        let principal = get_source_principal(&mut context);
+        if principal.is_some() {
+            check_target(&mut context, self)?;
+        }
+
+        Ok(AcceptStep {
+            principal,
+            token: maybe_token.map(|t| t.to_vec()),
+        })
+    }
+}
+
+/// Negotiations run on the blocking thread pool with the credentials current when they
+/// start, so that neither the async workers nor the other negotiations wait for them.
+#[rocket::async_trait]
+impl Acceptor for ReloadableCreds {
+    async fn step(&self, token: &[u8]) -> Result<AcceptStep, String> {
+        let creds = self.current();
+        let token = token.to_vec();
+        task::spawn_blocking(move || creds.accept(&token))
+            .await
+            .map_err(|e| format!("Negotiation task failed: {}", e))?
+    }
+
+    async fn ready(&self) -> Result<(), String> {
+        self.current().check()
+    }
+}
+
+fn get_source_principal(context: &mut ServerCtx) -> Option<String> {
+    if !context.is_complete() {
+        return None;
+    }
+    let principal_cname = context.source_name().ok()?;
+    String::from_utf8(principal_cname.display_name().ok()?.to_vec()).ok()
+}
+
+fn check_target(context: &mut ServerCtx, creds: &KrbServerCreds) -> Result<(), String> {
+    if creds.principals.is_empty() {
+        return Ok(());
+    }
+
+    let target = context
+        .target_name()
+        .ok()
+        .and_then(|name| name.display_name().ok())
+        .and_then(|buf| String::from_utf8(buf.to_vec()).ok());
+
+    match target {
+        Some(t) if creds.accepts(&t) => Ok(()),
+        t => Err(format!(
+            "Rejecting context targeting a principal not accepted: {:?}",
+            t
+        )),
+    }
+}
@@ -24,6 +24,8 @@ impl Fairing for KrbFairing {
     }
 
     /// Handles the response, attaching Kerberos authentication headers if needed.
+    /// The Negotiate challenge of an unauthorized response is added next to the
+    /// challenges set by the route, if any.
     ///
     /// ### Parameters
     /// - `request`: Reference to the incoming request.
@@ -34,7 +36,7 @@ impl Fairing for KrbFairing {
         });
 
         if response.status() == Status::Unauthorized {
-            response.set_raw_header(
+            response.adjoin_raw_header(
                 "WWW-Authenticate",
                 "Negotiate ".to_string() + spnego.token.as_str(),
             );
@@ -49,3 +51,119 @@ impl Fairing for KrbFairing {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use base64::Engine;
+    use base64::engine::general_purpose;
+    use rocket::http::{Header, Status};
+    use rocket::local::asynchronous::Client;
+    use rocket::{Build, Rocket};
+
+    use crate::{
+        FAKE_CHALLENGE, FAKE_HELLO, FAKE_OK, FakeAcceptor, KrbFairing, KrbToken, SharedAcceptor,
+    };
+
+    #[rocket::get("/")]
+    fn whoami(token: KrbToken) -> String {
+        token.principal
+    }
+
+    fn rocket(allowed: Vec<String>) -> Rocket<Build> {
+        let acceptor: SharedAcceptor = Arc::new(FakeAcceptor::new(allowed));
+        rocket::build()
+            .mount("/", rocket::routes![whoami])
+            .manage(acceptor)
+            .attach(KrbFairing {})
+    }
+
+    fn negotiate(token: &[u8]) -> Header<'static> {
+        Header::new(
+            "Authorization",
+            format!("Negotiate {}", general_purpose::STANDARD.encode(token)),
+        )
+    }
+
+    #[rocket::async_test]
+    async fn missing_authorization_asks_for_negotiation() {
+        let client = Client::tracked(rocket(vec![])).await.unwrap();
+
+        let response = client.get("/").dispatch().await;
+
+        assert_eq!(response.status(), Status::Unauthorized);
+        let header = response.headers().get_one("WWW-Authenticate").unwrap();
+        assert_eq!(header.trim(), "Negotiate");
+    }
+
+    #[rocket::async_test]
+    async fn incomplete_context_sends_continuation_token() {
+        let client = Client::tracked(rocket(vec![])).await.unwrap();
+
+        let response = client
+            .get("/")
+            .header(negotiate(FAKE_HELLO))
+            .dispatch()
+            .await;
+
+        assert_eq!(response.status(), Status::Unauthorized);
+        assert_eq!(
+            response.headers().get_one("WWW-Authenticate"),
+            Some(
+                format!(
+                    "Negotiate {}",
+                    general_purpose::STANDARD.encode(FAKE_CHALLENGE)
+                )
+                .as_str()
+            )
+        );
+    }
+
+    #[rocket::async_test]
+    async fn complete_context_sends_mutual_token() {
+        let client = Client::tracked(rocket(vec![])).await.unwrap();
+
+        let response = client
+            .get("/")
+            .header(negotiate(b"fake:alice@EXAMPLE.COM"))
+            .dispatch()
+            .await;
+
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.headers().get_one("WWW-Authenticate"),
+            Some(format!("Negotiate {}", general_purpose::STANDARD.encode(FAKE_OK)).as_str())
+        );
+        assert_eq!(response.into_string().await.unwrap(), "alice@EXAMPLE.COM");
+    }
+
+    #[rocket::async_test]
+    async fn rejected_principal_is_forbidden() {
+        let client = Client::tracked(rocket(vec!["bob@EXAMPLE.COM".to_string()]))
+            .await
+            .unwrap();
+
+        let response = client
+            .get("/")
+            .header(negotiate(b"fake:alice@EXAMPLE.COM"))
+            .dispatch()
+            .await;
+
+        assert_eq!(response.status(), Status::Forbidden);
+        assert!(response.headers().get_one("WWW-Authenticate").is_none());
+    }
+
+    #[rocket::async_test]
+    async fn malformed_authorization_is_forbidden() {
+        let client = Client::tracked(rocket(vec![])).await.unwrap();
+
+        let response = client
+            .get("/")
+            .header(Header::new("Authorization", "Basic YWxpY2U6c2VjcmV0"))
+            .dispatch()
+            .await;
+
+        assert_eq!(response.status(), Status::Forbidden);
+    }
+}
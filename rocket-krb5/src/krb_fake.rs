@@ -0,0 +1,74 @@
+use crate::{AcceptStep, Acceptor};
+
+/// Token a fake client sends to open a negotiation needing one more round trip.
+pub const FAKE_HELLO: &[u8] = b"fake-hello";
+/// Token a fake acceptor answers to [`FAKE_HELLO`].
+pub const FAKE_CHALLENGE: &[u8] = b"fake-challenge";
+/// Mutual authentication token a fake acceptor sends once the client is authenticated.
+pub const FAKE_OK: &[u8] = b"fake-ok";
+/// Prefix of the token carrying the client principal, e.g. `fake:alice@EXAMPLE.COM`.
+pub const FAKE_PRINCIPAL_PREFIX: &str = "fake:";
+
+/// Deterministic acceptor for tests, speaking a tiny plain text protocol instead of Kerberos.
+///
+/// - `fake-hello` is answered with `fake-challenge` without authenticating anybody.
+/// - `fake:<principal>` authenticates `<principal>` and is answered with `fake-ok`.
+/// - Anything else, or a principal not allowed, is rejected.
+#[derive(Clone, Debug, Default)]
+pub struct FakeAcceptor {
+    /// Client principals allowed to authenticate, anybody when empty.
+    pub allowed: Vec<String>,
+    /// Reason given by readiness checks, the acceptor being ready when `None`.
+    pub unready: Option<String>,
+}
+
+impl FakeAcceptor {
+    /// Creates a fake acceptor letting the given principals in, anybody when empty.
+    ///
+    /// ### Parameters
+    /// - `allowed`: The client principals allowed to authenticate.
+    ///
+    /// ### Returns
+    /// - `FakeAcceptor`: New fake acceptor.
+    ///
+    /// ### Example
+    /// ```rust
+    /// let acceptor = FakeAcceptor::new(vec!["alice@EXAMPLE.COM".to_string()]);
+    /// ```
+    pub fn new(allowed: Vec<String>) -> FakeAcceptor {
+        FakeAcceptor {
+            allowed,
+            unready: None,
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl Acceptor for FakeAcceptor {
+    async fn ready(&self) -> Result<(), String> {
+        self.unready.clone().map_or(Ok(()), Err)
+    }
+
+    async fn step(&self, token: &[u8]) -> Result<AcceptStep, String> {
+        if token == FAKE_HELLO {
+            return Ok(AcceptStep {
+                principal: None,
+                token: Some(FAKE_CHALLENGE.to_vec()),
+            });
+        }
+
+        let principal = std::str::from_utf8(token)
+            .ok()
+            .and_then(|t| t.strip_prefix(FAKE_PRINCIPAL_PREFIX))
+            .ok_or("Malformed fake token")?;
+
+        if !self.allowed.is_empty() && !self.allowed.iter().any(|p| p == principal) {
+            return Err(format!("Principal {} not allowed", principal));
+        }
+
+        Ok(AcceptStep {
+            principal: Some(principal.to_string()),
+            token: Some(FAKE_OK.to_vec()),
+        })
+    }
+}
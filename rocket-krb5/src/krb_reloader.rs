@@ -0,0 +1,141 @@
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use rocket::tokio;
+use rocket::tokio::signal::unix::{SignalKind, signal};
+
+use crate::KrbServerCreds;
+
+/// Kerberos server credentials that a reload swaps in place of the current ones.
+///
+/// Negotiations take a reference to the credentials current when they start and hold no
+/// lock while stepping, so they neither serialise each other nor wait for a reload.
+pub struct ReloadableCreds {
+    current: RwLock<Arc<KrbServerCreds>>,
+}
+
+impl ReloadableCreds {
+    /// Wraps the initial credentials.
+    ///
+    /// ### Parameters
+    /// - `creds`: The credentials acquired at startup.
+    ///
+    /// ### Returns
+    /// - `ReloadableCreds`: The reloadable credentials.
+    pub fn new(creds: KrbServerCreds) -> ReloadableCreds {
+        ReloadableCreds {
+            current: RwLock::new(Arc::new(creds)),
+        }
+    }
+
+    /// Returns the current credentials, the lock being held only to clone the reference.
+    ///
+    /// ### Returns
+    /// - `Arc<KrbServerCreds>`: The current credentials.
+    pub fn current(&self) -> Arc<KrbServerCreds> {
+        self.current
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Replaces the current credentials, the negotiations in progress finishing with the
+    /// previous ones.
+    ///
+    /// ### Parameters
+    /// - `creds`: The fresh credentials.
+    pub fn replace(&self, creds: KrbServerCreds) {
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(creds);
+    }
+}
+
+/// Kerberos server credentials shared between the request guards and the reloader.
+pub type SharedServerCreds = Arc<ReloadableCreds>;
+
+/// Spawns a background task reloading the acceptor credentials when the keytab
+/// changes on disk or when the process receives SIGHUP.
+///
+/// The keytab modification time is polled at the given interval. A failed reload
+/// keeps the previous credentials in place.
+///
+/// ### Parameters
+/// - `creds`: The shared server credentials to reload.
+/// - `interval`: Interval between two keytab modification checks.
+///
+/// ### Example
+/// ```rust
+/// let creds: SharedServerCreds = Arc::new(ReloadableCreds::new(KrbServerCreds::new("HTTP/server@EXAMPLE.COM".to_string()).unwrap()));
+/// spawn_reloader(creds.clone(), Duration::from_secs(30));
+/// ```
+pub fn spawn_reloader(creds: SharedServerCreds, interval: Duration) {
+    tokio::spawn(async move {
+        let keytab = keytab_path(creds.current().keytab.as_deref());
+        let mut last_modified = modified_at(&keytab);
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(s) => Some(s),
+            Err(e) => {
+                log::warn!("Cannot listen for SIGHUP, keytab polling only: {}", e);
+                None
+            }
+        };
+        let mut ticker = tokio::time::interval(interval.max(Duration::from_secs(1)));
+
+        loop {
+            tokio::select! {
+                _ = ticker.tick() => {
+                    let modified = modified_at(&keytab);
+                    if modified == last_modified {
+                        continue;
+                    }
+                    last_modified = modified;
+                    log::info!("Keytab {} changed, reloading credentials", keytab.display());
+                }
+                Some(_) = async { hangup.as_mut()?.recv().await } => {
+                    log::info!("SIGHUP received, reloading credentials");
+                }
+            }
+
+            reload(&creds);
+        }
+    });
+}
+
+/// Acquires fresh acceptor credentials and swaps them in place of the current ones.
+///
+/// ### Parameters
+/// - `creds`: The shared server credentials to reload.
+///
+/// ### Returns
+/// - `bool`: True if the credentials were reloaded, false otherwise.
+pub fn reload(creds: &SharedServerCreds) -> bool {
+    let current = creds.current();
+    let (keytab, principals) = (current.keytab.clone(), current.principals.clone());
+
+    match KrbServerCreds::from_keytab(keytab, principals) {
+        Ok(fresh) => {
+            log::info!("{}", fresh.describe());
+            creds.replace(fresh);
+            true
+        }
+        Err(e) => {
+            log::error!("Reload failed, keeping previous credentials: {}", e);
+            false
+        }
+    }
+}
+
+/// Resolves the keytab file to watch, following the krb5 defaults when none is configured.
+fn keytab_path(keytab: Option<&str>) -> PathBuf {
+    let path = keytab
+        .map(|k| k.to_string())
+        .or_else(|| std::env::var("KRB5_KTNAME").ok())
+        .unwrap_or_else(|| String::from("/etc/krb5.keytab"));
+
+    PathBuf::from(path.trim_start_matches("FILE:"))
+}
+
+/// Returns the last modification time of a file, `None` if it cannot be read.
+fn modified_at(path: &PathBuf) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
@@ -1,19 +1,51 @@
+use std::path::Path;
+use std::time::Duration;
+
 use libgssapi::{
     credential::{Cred, CredUsage},
     name::Name,
     oid::{GSS_MECH_KRB5, GSS_MECH_SPNEGO, GSS_NT_KRB5_PRINCIPAL, OidSet},
 };
 
+/// Selects the keytab the acceptor credentials are read from, through `KRB5_KTNAME`.
+///
+/// ### Parameters
+/// - `keytab`: Path of the keytab, optionally prefixed with `FILE:`.
+///
+/// ### Returns
+/// - `Result<(), String>`: Ok if the keytab exists, Err with error message otherwise.
+///
+/// ### Safety
+/// The environment of the process is written, which is only sound while no other thread
+/// may read it: call it from `main`, before the async runtime is built.
+///
+/// ### Example
+/// ```rust
+/// // SAFETY: the runtime is not started yet.
+/// unsafe { use_server_keytab("/etc/rping.keytab") }.unwrap();
+/// rocket::execute(launch());
+/// ```
+pub unsafe fn use_server_keytab(keytab: &str) -> Result<(), String> {
+    if !Path::new(keytab.trim_start_matches("FILE:")).exists() {
+        return Err(format!("Keytab '{}' does not exist", keytab));
+    }
+    // SAFETY: forwarded to the caller, no other thread runs yet.
+    unsafe { std::env::set_var("KRB5_KTNAME", keytab) };
+    Ok(())
+}
+
 /// Kerberos server credentials struct, used for accepting and validating Kerberos tokens.
-/// Stores principal, credentials, and canonicalized name for the server.
+/// Stores the acceptor principals, the keytab they come from and the acquired credentials.
 pub struct KrbServerCreds {
-    pub principal: String,
+    /// Principals clients may authenticate against, any principal of the keytab when empty.
+    pub principals: Vec<String>,
+    /// Keytab the credentials were loaded from, the default keytab when `None`.
+    pub keytab: Option<String>,
     pub creds: Cred,
-    pub name: Name,
 }
 
 impl KrbServerCreds {
-    /// Creates new Kerberos server credentials from a principal string.
+    /// Creates new Kerberos server credentials from a principal string, using the default keytab.
     ///
     /// ### Parameters
     /// - `principal`: The Kerberos principal as a string.
@@ -27,23 +59,148 @@ impl KrbServerCreds {
     /// assert!(creds.is_some());
     /// ```
     pub fn new(principal: String) -> Option<KrbServerCreds> {
-        let name = Name::new(principal.as_bytes(), Some(&GSS_NT_KRB5_PRINCIPAL)).ok()?;
-        let cname = name.canonicalize(Some(&GSS_MECH_KRB5)).ok()?;
-        let mut desired = OidSet::new().ok()?;
-        desired.add(&GSS_MECH_SPNEGO).ok()?;
-
-        let creds = match Cred::acquire(Some(&cname), None, CredUsage::Accept, Some(&desired)) {
-            Ok(s) => Some(s),
-            Err(e) => {
-                println!("{}", e);
-                None
+        KrbServerCreds::from_keytab(None, vec![principal])
+            .inspect_err(|e| log::error!("{}", e))
+            .ok()
+    }
+
+    /// Creates new Kerberos server credentials from an optional keytab and a list of principals.
+    ///
+    /// The keytab is read by the krb5 library from the environment, where it must have been
+    /// selected with [`use_server_keytab`] before the async runtime started: the environment
+    /// is never written here, as reloads run while negotiations read it on other threads.
+    ///
+    /// A single principal acquires credentials for that principal only. Several principals, or
+    /// none at all, acquire credentials for every principal of the keytab, the allowed ones being
+    /// checked against the target of each security context by [`KrbServerCreds::accepts`].
+    ///
+    /// ### Parameters
+    /// - `keytab`: Optional path of the keytab to read the acceptor keys from.
+    /// - `principals`: The Kerberos principals to accept, any principal of the keytab when empty.
+    ///
+    /// ### Returns
+    /// - `Result<KrbServerCreds, String>`: New server credentials struct, or Err with error message otherwise.
+    ///
+    /// ### Example
+    /// ```rust
+    /// let creds = KrbServerCreds::from_keytab(
+    ///     Some("/etc/rping.keytab".to_string()),
+    ///     vec!["HTTP/a.example.com@EXAMPLE.COM".to_string(), "HTTP/b.example.com@EXAMPLE.COM".to_string()],
+    /// );
+    /// assert!(creds.is_ok());
+    /// ```
+    pub fn from_keytab(
+        keytab: Option<String>,
+        principals: Vec<String>,
+    ) -> Result<KrbServerCreds, String> {
+        if let Some(path) = &keytab {
+            if !Path::new(path.trim_start_matches("FILE:")).exists() {
+                return Err(format!("Keytab '{}' does not exist", path));
             }
-        }?;
+            if std::env::var("KRB5_KTNAME").ok().as_deref() != Some(path) {
+                return Err(format!(
+                    "Keytab '{}' is not selected, see use_server_keytab",
+                    path
+                ));
+            }
+        }
+
+        let mut desired = OidSet::new().map_err(|e| e.to_string())?;
+        desired.add(&GSS_MECH_SPNEGO).map_err(|e| e.to_string())?;
+
+        let name = match principals.as_slice() {
+            [principal] => Some(canonicalize(principal)?),
+            _ => None,
+        };
 
-        Some(KrbServerCreds {
-            principal,
+        let creds = Cred::acquire(name.as_ref(), None, CredUsage::Accept, Some(&desired))
+            .map_err(|e| format!("Cannot acquire acceptor credentials: {}", e))?;
+
+        Ok(KrbServerCreds {
+            principals,
+            keytab,
             creds,
-            name: cname,
         })
     }
+
+    /// Tells whether a security context targeting the given principal may be accepted.
+    ///
+    /// ### Parameters
+    /// - `target`: The principal the client authenticated against.
+    ///
+    /// ### Returns
+    /// - `bool`: True if the principal is allowed, false otherwise.
+    ///
+    /// ### Example
+    /// ```rust
+    /// let creds = KrbServerCreds::new("HTTP/server@EXAMPLE.COM".to_string()).unwrap();
+    /// assert!(creds.accepts("HTTP/server@EXAMPLE.COM"));
+    /// ```
+    pub fn accepts(&self, target: &str) -> bool {
+        self.principals.is_empty() || self.principals.iter().any(|p| p == target)
+    }
+
+    /// Remaining lifetime of the acquired credentials, `None` when they never expire.
+    ///
+    /// ### Returns
+    /// - `Option<Duration>`: The remaining lifetime.
+    pub fn lifetime(&self) -> Option<Duration> {
+        self.creds
+            .lifetime()
+            .ok()
+            .filter(|d| d.as_secs() < u32::MAX as u64)
+    }
+
+    /// Checks that the acquired credentials are still valid.
+    ///
+    /// ### Returns
+    /// - `Result<(), String>`: Ok if the credentials can accept negotiations, Err otherwise.
+    ///
+    /// ### Example
+    /// ```rust
+    /// let creds = KrbServerCreds::new("HTTP/server@EXAMPLE.COM".to_string()).unwrap();
+    /// assert!(creds.check().is_ok());
+    /// ```
+    pub fn check(&self) -> Result<(), String> {
+        match self.creds.lifetime() {
+            Ok(lifetime) if lifetime.is_zero() => Err(String::from("Acceptor credentials expired")),
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("Acceptor credentials are unusable: {}", e)),
+        }
+    }
+
+    /// Describes the loaded credentials, for startup and reload logs.
+    ///
+    /// ### Returns
+    /// - `String`: A human readable description of the credentials.
+    pub fn describe(&self) -> String {
+        let principals = if self.principals.is_empty() {
+            String::from("any principal")
+        } else {
+            self.principals.join(", ")
+        };
+        let keytab = self.keytab.as_deref().unwrap_or("default keytab");
+        let expiry = match self.lifetime() {
+            Some(d) => format!("expiring in {}s", d.as_secs()),
+            None => String::from("without expiry"),
+        };
+
+        format!(
+            "Acceptor credentials for {} loaded from {}, {}",
+            principals, keytab, expiry
+        )
+    }
+}
+
+/// Canonicalizes a principal string into a Kerberos name.
+///
+/// ### Parameters
+/// - `principal`: The Kerberos principal as a string.
+///
+/// ### Returns
+/// - `Result<Name, String>`: The canonical name, or Err with error message otherwise.
+fn canonicalize(principal: &str) -> Result<Name, String> {
+    Name::new(principal.as_bytes(), Some(&GSS_NT_KRB5_PRINCIPAL))
+        .and_then(|name| name.canonicalize(Some(&GSS_MECH_KRB5)))
+        .map_err(|e| format!("Invalid principal '{}': {}", principal, e))
 }
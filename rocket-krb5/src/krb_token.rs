@@ -1,14 +1,11 @@
 use base64::Engine;
 use base64::engine::general_purpose;
-use libgssapi::context::{SecurityContext, ServerCtx};
-use libgssapi::util::Buf;
 use rocket::Request;
 use rocket::State;
-use rocket::futures::lock::Mutex;
 use rocket::http::Status;
 use rocket::request::{FromRequest, Outcome};
 
-use crate::KrbServerCreds;
+use crate::{AcceptStep, SharedAcceptor};
 
 /// Kerberos token struct, used for authentication in Rocket requests.
 pub struct KrbToken {
@@ -42,9 +39,7 @@ impl KrbToken {
     /// assert_eq!(token.principal, "user@EXAMPLE.COM");
     /// ```
     pub fn new(principal: String) -> KrbToken {
-        KrbToken {
-            principal: principal,
-        }
+        KrbToken { principal }
     }
 }
 
@@ -61,37 +56,31 @@ impl<'r> FromRequest<'r> for KrbToken {
     async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
         let header = request.headers().get_one("Authorization");
 
-        let server_creds = match request
-            .guard::<&State<Mutex<KrbServerCreds>>>()
-            .await
-            .succeeded()
-        {
+        let acceptor = match request.guard::<&State<SharedAcceptor>>().await.succeeded() {
             Some(t) => t,
             None => {
                 return Outcome::Error((
                     Status::InternalServerError,
-                    "No Kerberos Server credential state set.".to_string(),
+                    "No Kerberos acceptor state set.".to_string(),
                 ));
             }
         };
-        let locked_creds = server_creds.lock().await;
         match header {
             None => Outcome::Error((
                 Status::Unauthorized,
                 "SPNEGO Authentication required.".to_string(),
             )),
-            Some(encoded_token) => get_decoded_token(&locked_creds, encoded_token).map_or(
-                Outcome::Error((Status::Forbidden, "Principal not allowed".to_string())),
-                |auth_status| finalize_response(auth_status, request),
-            ),
+            Some(encoded_token) => get_decoded_token(acceptor.inner(), encoded_token)
+                .await
+                .map_or(
+                    Outcome::Error((Status::Forbidden, "Principal not allowed".to_string())),
+                    |auth_status| finalize_response(auth_status, request),
+                ),
         }
     }
 }
 
-fn finalize_response<'r>(
-    auth_status: AuthStatus,
-    req: &'r Request<'_>,
-) -> Outcome<KrbToken, String> {
+fn finalize_response(auth_status: AuthStatus, req: &Request<'_>) -> Outcome<KrbToken, String> {
     if let Some(spnego) = auth_status.spnego {
         req.local_cache(|| spnego);
     }
@@ -105,49 +94,25 @@ fn finalize_response<'r>(
     }
 }
 
-fn get_decoded_token(creds: &KrbServerCreds, header_value: &str) -> Option<AuthStatus> {
-    let c = creds.creds.clone();
-    let mut context = ServerCtx::new(Some(c));
-
+async fn get_decoded_token(acceptor: &SharedAcceptor, header_value: &str) -> Option<AuthStatus> {
     let token = header_value
         .strip_prefix("Negotiate ")
         .and_then(|b64| general_purpose::STANDARD.decode(b64).ok())?;
 
-    match context.step(&*token) {
-        Ok(opt) => wrap_up_token(opt, context),
+    match acceptor.step(&token).await {
+        Ok(step) => Some(wrap_up_token(step)),
         Err(e) => {
-            println!("There is an error while stepping in server context: {}", e);
+            log::warn!("{}", e);
             None
         }
     }
 }
 
-fn wrap_up_token(maybe_token: Option<Buf>, context: ServerCtx) -> Option<AuthStatus> {
-    let principal = get_source_principal(context);
-    match maybe_token {
-        None => Some(AuthStatus {
-            krb: Some(KrbToken::new(principal.unwrap())),
-            spnego: None,
+fn wrap_up_token(step: AcceptStep) -> AuthStatus {
+    AuthStatus {
+        krb: step.principal.map(KrbToken::new),
+        spnego: step.token.map(|t| IncompleteSpnego {
+            token: general_purpose::STANDARD.encode(t),
         }),
-        Some(t) => {
-            let encoded_token = general_purpose::STANDARD.encode(&*t).as_str().to_string();
-
-            let krb_token = principal.map_or(None, |p| Some(KrbToken::new(p)));
-
-            Some(AuthStatus {
-                krb: krb_token,
-                spnego: Some(IncompleteSpnego {
-                    token: encoded_token,
-                }),
-            })
-        }
-    }
-}
-
-fn get_source_principal(mut context: ServerCtx) -> Option<String> {
-    if !context.is_complete() {
-        return None;
     }
-    let principal_cname = context.source_name().ok()?;
-    String::from_utf8(principal_cname.display_name().ok()?.to_vec()).ok()
 }
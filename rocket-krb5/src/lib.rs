@@ -1,7 +1,15 @@
+mod krb_acceptor;
 mod krb_fairing;
+#[cfg(any(test, feature = "testing"))]
+mod krb_fake;
+mod krb_reloader;
 mod krb_server_creds;
 mod krb_token;
 
+pub use krb_acceptor::*;
 pub use krb_fairing::*;
+#[cfg(any(test, feature = "testing"))]
+pub use krb_fake::*;
+pub use krb_reloader::*;
 pub use krb_server_creds::*;
 pub use krb_token::*;
@@ -0,0 +1,34 @@
+use rocket::Request;
+use rocket::http::Status;
+use rocket::outcome::try_outcome;
+use rocket::request::{FromRequest, Outcome};
+
+use crate::auth::Identity;
+use crate::routes::note_guard_error;
+use crate::types::ServerOptions;
+
+/// Guard letting through the admin principals of the server only, authenticated with
+/// Kerberos so that an API token or a client certificate named after an admin is refused.
+pub struct Admin(pub Identity);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin {
+    type Error = String;
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let identity = try_outcome!(request.guard::<Identity>().await);
+        let Some(options) = request.rocket().state::<ServerOptions>() else {
+            return Outcome::Error((
+                Status::InternalServerError,
+                "No server options set.".to_string(),
+            ));
+        };
+
+        if identity.is_admin(&options.admins) {
+            Outcome::Success(Admin(identity))
+        } else {
+            let message = format!("{} is not an admin", identity.principal);
+            note_guard_error(request, &message);
+            Outcome::Error((Status::Forbidden, message))
+        }
+    }
+}
@@ -0,0 +1,5 @@
+mod guard;
+mod snapshot;
+
+pub use guard::*;
+pub use snapshot::*;
@@ -0,0 +1,260 @@
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+
+use rocket::serde::json::{Value, serde_json};
+use rocket::serde::{Deserialize, Serialize};
+
+use crate::events::{EventKind, Events, HostEvent};
+use crate::routes::snapshot_schema;
+use crate::store::{HostStore, Replica};
+use crate::types::{HostRecord, unix_now};
+
+/// Version of the snapshot format written by this server, the only one it imports.
+pub const SNAPSHOT_VERSION: u64 = 1;
+/// Number of schema violations told when a snapshot is refused.
+const REPORTED_ERRORS: usize = 5;
+
+/// Copy of the host records and of their recent history, for migrations and disaster
+/// recovery. Its JSON form is described by the `Snapshot` schema of the OpenAPI document.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Snapshot {
+    /// Version of the snapshot format.
+    pub version: u64,
+    /// Time of the export, in seconds since the Unix epoch.
+    pub exported_at: u64,
+    /// Hosts by hostname.
+    pub hosts: BTreeMap<String, SnapshotHost>,
+    /// Recent changes made to the host records, oldest first.
+    pub history: Vec<HostEvent>,
+}
+
+/// Host of a snapshot.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct SnapshotHost {
+    /// The record of the host.
+    pub record: HostRecord,
+    /// Principal owning the host, `None` if it is left to the next registrant.
+    pub owner: Option<String>,
+}
+
+/// How the records of a snapshot are imported.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImportMode {
+    /// The records of the snapshot are added, a host known on both sides keeping the
+    /// record it was seen last with.
+    Merge,
+    /// The records of the snapshot replace every record of the server.
+    Replace,
+}
+
+impl ImportMode {
+    /// Parses an import mode name as given in the query.
+    ///
+    /// ### Parameters
+    /// - `value`: Either `merge` or `replace`.
+    ///
+    /// ### Returns
+    /// - `Result<ImportMode, &'static str>`: Ok with the mode, Err otherwise.
+    ///
+    /// ### Example
+    /// ```rust
+    /// assert_eq!(ImportMode::parse("replace"), Ok(ImportMode::Replace));
+    /// ```
+    pub fn parse(value: &str) -> Result<ImportMode, &'static str> {
+        match value {
+            "merge" => Ok(ImportMode::Merge),
+            "replace" => Ok(ImportMode::Replace),
+            _ => Err("Unknown import mode, expected merge or replace"),
+        }
+    }
+}
+
+impl std::fmt::Display for ImportMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportMode::Merge => write!(f, "merge"),
+            ImportMode::Replace => write!(f, "replace"),
+        }
+    }
+}
+
+/// Changes made by the import of a snapshot.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ImportReport {
+    /// Hosts of the snapshot the server did not know.
+    pub created: usize,
+    /// Hosts whose record was replaced by the one of the snapshot.
+    pub updated: usize,
+    /// Hosts of the snapshot whose record on the server was seen more recently.
+    pub kept: usize,
+    /// Hosts of the server missing from the snapshot, removed when replacing.
+    pub removed: usize,
+    /// Events of the history restored, none if the server already published events.
+    pub history: usize,
+}
+
+impl Snapshot {
+    /// Takes a snapshot of the records and of the history of the server.
+    ///
+    /// ### Parameters
+    /// - `map`: Shared state for DNS records.
+    /// - `events`: The event bus keeping the recent history.
+    ///
+    /// ### Returns
+    /// - `Snapshot`: The snapshot.
+    pub fn export(map: &HostStore, events: &Events) -> Snapshot {
+        let hosts = map
+            .replicas()
+            .into_iter()
+            .filter_map(
+                |Replica {
+                     hostname,
+                     record,
+                     owner,
+                     ..
+                 }| {
+                    Some((
+                        hostname,
+                        SnapshotHost {
+                            record: record?,
+                            owner,
+                        },
+                    ))
+                },
+            )
+            .collect();
+
+        Snapshot {
+            version: SNAPSHOT_VERSION,
+            exported_at: unix_now(),
+            hosts,
+            history: events.history(),
+        }
+    }
+
+    /// Validates a snapshot against its schema and reads it.
+    ///
+    /// ### Parameters
+    /// - `document`: The snapshot as JSON.
+    ///
+    /// ### Returns
+    /// - `Result<Snapshot, String>`: Ok with the snapshot, Err telling why it is invalid.
+    ///
+    /// ### Example
+    /// ```rust
+    /// let snapshot = Snapshot::parse(&serde_json::from_str(&body)?)?;
+    /// ```
+    pub fn parse(document: &Value) -> Result<Snapshot, String> {
+        if let Some(version) = document.get("version")
+            && *version != SNAPSHOT_VERSION
+        {
+            return Err(format!(
+                "Unsupported snapshot version {}, expected {}",
+                version, SNAPSHOT_VERSION
+            ));
+        }
+
+        let validator = jsonschema::validator_for(&snapshot_schema())
+            .map_err(|e| format!("Invalid snapshot schema: {}", e))?;
+        let errors: Vec<String> = validator
+            .iter_errors(document)
+            .take(REPORTED_ERRORS)
+            .map(|e| match e.instance_path.to_string().as_str() {
+                "" => e.to_string(),
+                path => format!("{}: {}", path, e),
+            })
+            .collect();
+        if !errors.is_empty() {
+            return Err(format!("Invalid snapshot: {}", errors.join("; ")));
+        }
+
+        let snapshot: Snapshot = serde_json::from_value(document.clone())
+            .map_err(|e| format!("Invalid snapshot: {}", e))?;
+        for (hostname, host) in &snapshot.hosts {
+            if let Some(address) = host
+                .record
+                .addresses
+                .iter()
+                .find(|a| a.ip.parse::<IpAddr>().is_err())
+            {
+                return Err(format!(
+                    "Invalid snapshot: /hosts/{}/record: invalid address {}",
+                    hostname, address.ip
+                ));
+            }
+        }
+        if snapshot.history.windows(2).any(|w| w[0].id >= w[1].id) {
+            return Err(String::from(
+                "Invalid snapshot: /history: identifiers are not increasing",
+            ));
+        }
+
+        Ok(snapshot)
+    }
+
+    /// Imports the snapshot, every change being published on behalf of the importing
+    /// principal and the owners of the hosts being restored with their records. The
+    /// history is restored before the records, when the server did not publish any event
+    /// yet.
+    ///
+    /// ### Parameters
+    /// - `mode`: Whether to merge the records or to replace them.
+    /// - `map`: Shared state for DNS records.
+    /// - `events`: The event bus.
+    /// - `principal`: The admin principal importing the snapshot.
+    ///
+    /// ### Returns
+    /// - `ImportReport`: The changes made.
+    pub fn import(
+        self,
+        mode: ImportMode,
+        map: &HostStore,
+        events: &Events,
+        principal: &str,
+    ) -> ImportReport {
+        let mut report = ImportReport {
+            history: events.restore(self.history),
+            ..Default::default()
+        };
+
+        if mode == ImportMode::Replace {
+            let mut missing = Vec::new();
+            map.for_each(|hostname, _| {
+                if !self.hosts.contains_key(hostname) {
+                    missing.push(hostname.to_string());
+                }
+            });
+            for hostname in missing {
+                let removed = map.remove(&hostname, Some(principal), |record| {
+                    events.removed(EventKind::Deleted, &hostname, record, Some(principal))
+                });
+                report.removed += usize::from(removed.is_some());
+            }
+        }
+
+        for (hostname, SnapshotHost { record, owner }) in self.hosts {
+            // Compared while the host is locked, so that a registration made meanwhile wins.
+            let previous = map.restore(
+                &hostname,
+                principal,
+                owner.as_deref(),
+                |current| {
+                    let newer = mode == ImportMode::Replace
+                        || current.is_none_or(|c| c.last_seen < record.last_seen);
+                    newer.then(|| record.clone())
+                },
+                |old, new| events.registered(&hostname, old, new, principal),
+            );
+            match previous {
+                None => report.kept += 1,
+                Some(Some(_)) => report.updated += 1,
+                Some(None) => report.created += 1,
+            }
+        }
+
+        report
+    }
+}
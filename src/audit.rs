@@ -0,0 +1,281 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Method;
+use rocket::serde::{Serialize, json::serde_json};
+use rocket::{Request, Response};
+
+use crate::auth::AuthMethod;
+use crate::logging::{RequestId, timestamp};
+use crate::types::ServerOptions;
+
+/// Append-only audit log of the reads, writes, deletes and authentication failures, one
+/// JSON object per line. Nothing is recorded when no file is configured.
+#[derive(Clone, Debug, Default)]
+pub struct AuditLog {
+    file: Option<Arc<Mutex<File>>>,
+}
+
+impl AuditLog {
+    /// Opens an audit log, creating the file if needed and appending to it otherwise.
+    ///
+    /// ### Parameters
+    /// - `path`: Path of the audit log file.
+    ///
+    /// ### Returns
+    /// - `Result<AuditLog, String>`: Ok with the audit log, Err if the file cannot be opened.
+    ///
+    /// ### Example
+    /// ```rust
+    /// let audit = AuditLog::open(Path::new("/var/log/rping/audit.jsonl")).unwrap();
+    /// ```
+    pub fn open(path: &Path) -> Result<AuditLog, String> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("Cannot open audit log {}: {}", path.display(), e))?;
+        Ok(AuditLog {
+            file: Some(Arc::new(Mutex::new(file))),
+        })
+    }
+
+    /// Appends an entry to the audit log.
+    ///
+    /// ### Parameters
+    /// - `entry`: The entry to record.
+    ///
+    /// ### Returns
+    /// - `Result<(), String>`: Ok if the entry is written or no file is configured, Err otherwise.
+    pub fn record(&self, entry: &AuditEntry) -> Result<(), String> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        let line = serde_json::to_string(entry).map_err(|e| e.to_string())?;
+        let mut file = file.lock().unwrap_or_else(|e| e.into_inner());
+        writeln!(file, "{}", line).map_err(|e| format!("Cannot write audit log: {}", e))
+    }
+}
+
+/// Outcome of the authentication of a request, noted by the guards for the audit log.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AuthOutcome {
+    /// The client authenticated as a principal.
+    Authenticated {
+        principal: String,
+        method: AuthMethod,
+    },
+    /// The client presented credentials that were refused.
+    Failed {
+        principal: Option<String>,
+        method: AuthMethod,
+        reason: String,
+    },
+}
+
+/// Authentication outcome cached in the request.
+struct AuthNote(Option<AuthOutcome>);
+
+impl AuthOutcome {
+    /// Notes the authentication outcome of a request, logging the failures. Requests whose
+    /// outcome is never noted, such as probes or SPNEGO continuations, are not audited.
+    ///
+    /// ### Parameters
+    /// - `request`: The request being authenticated.
+    ///
+    /// ### Example
+    /// ```rust
+    /// AuthOutcome::Authenticated { principal: name.to_string(), method: AuthMethod::Token }.note(request);
+    /// ```
+    pub fn note(self, request: &Request<'_>) {
+        if let AuthOutcome::Failed {
+            principal,
+            method,
+            reason,
+        } = &self
+        {
+            let method = method.to_string();
+            log::warn!(
+                target: "rping::auth",
+                request_id = RequestId::of(request),
+                method = method.as_str(),
+                principal = principal.as_deref().unwrap_or("-");
+                "{}",
+                reason
+            );
+        }
+        request.local_cache(|| AuthNote(Some(self)));
+    }
+}
+
+/// Entry of the audit log.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct AuditEntry {
+    pub timestamp: String,
+    pub request_id: String,
+    /// `read`, `write`, `delete` or `auth_failure`.
+    pub event: &'static str,
+    pub principal: Option<String>,
+    pub method: String,
+    pub client_ip: Option<String>,
+    /// Method and path of the request, such as `DELETE /api/v1/hosts/h1`.
+    pub request: String,
+    pub status: u16,
+    /// `success`, `failure` or `denied`.
+    pub outcome: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Fairing appending an entry to the audit log for each request that authenticated or
+/// failed to.
+pub struct AuditTrail;
+
+#[rocket::async_trait]
+impl Fairing for AuditTrail {
+    fn info(&self) -> Info {
+        Info {
+            name: "Audit trail",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let Some(options) = request.rocket().state::<ServerOptions>() else {
+            return;
+        };
+        let AuthNote(Some(outcome)) = request.local_cache(|| AuthNote(None)) else {
+            return;
+        };
+
+        let status = response.status();
+        let (event, principal, method, outcome, reason) = match outcome {
+            AuthOutcome::Authenticated { principal, method } => {
+                let outcome = match status.code {
+                    200..=399 => "success",
+                    401 | 403 => "denied",
+                    _ => "failure",
+                };
+                (action(request), Some(principal), method, outcome, None)
+            }
+            AuthOutcome::Failed {
+                principal,
+                method,
+                reason,
+            } => (
+                "auth_failure",
+                principal.as_ref(),
+                method,
+                "denied",
+                Some(reason.clone()),
+            ),
+        };
+
+        let entry = AuditEntry {
+            timestamp: timestamp(SystemTime::now()),
+            request_id: RequestId::of(request).to_string(),
+            event,
+            principal: principal.cloned(),
+            method: method.to_string(),
+            client_ip: request.client_ip().map(|ip| ip.to_string()),
+            request: format!("{} {}", request.method(), request.uri().path()),
+            status: status.code,
+            outcome,
+            reason,
+        };
+        if let Err(e) = options.audit.record(&entry) {
+            log::error!(request_id = entry.request_id.as_str(); "{}", e);
+        }
+    }
+}
+
+/// Classifies a request as a read, a write or a delete.
+fn action(request: &Request<'_>) -> &'static str {
+    match request.method() {
+        Method::Delete => "delete",
+        Method::Post | Method::Put | Method::Patch => "write",
+        // DynDNS clients register through GET requests.
+        _ if request.uri().path().starts_with("/nic/") => "write",
+        _ => "read",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+
+    use rocket::http::{ContentType, Header, Status};
+    use rocket::serde::json::{Value, serde_json};
+    use rocket_krb5::FakeAcceptor;
+
+    use super::AuditLog;
+    use crate::auth::Authenticators;
+    use crate::testing::{negotiate, server};
+    use crate::types::ServerOptions;
+
+    #[rocket::async_test]
+    async fn audit_log_records_writes_reads_and_failures() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let acceptor = FakeAcceptor::new(vec!["host/h1@EXAMPLE.COM".to_string()]);
+        let client = server(
+            Authenticators::kerberos(Arc::new(acceptor)),
+            ServerOptions {
+                audit: AuditLog::open(&path).unwrap(),
+                ..Default::default()
+            },
+        )
+        .await;
+        let remote = "10.0.0.1:4242".parse::<SocketAddr>().unwrap();
+
+        client
+            .post("/add")
+            .header(ContentType::JSON)
+            .header(negotiate("host/h1@EXAMPLE.COM"))
+            .header(Header::new("X-Request-Id", "req-1"))
+            .remote(remote)
+            .body(r#"{"hostname":"h1"}"#)
+            .dispatch()
+            .await;
+        client
+            .get("/get")
+            .header(negotiate("host/h1@EXAMPLE.COM"))
+            .remote(remote)
+            .dispatch()
+            .await;
+        let denied = client
+            .get("/get")
+            .header(negotiate("mallory@EXAMPLE.COM"))
+            .remote(remote)
+            .dispatch()
+            .await;
+        client.get("/healthz").dispatch().await;
+
+        assert_eq!(denied.status(), Status::Forbidden);
+        let entries: Vec<Value> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0]["request_id"], "req-1");
+        assert_eq!(entries[0]["event"], "write");
+        assert_eq!(entries[0]["principal"], "host/h1@EXAMPLE.COM");
+        assert_eq!(entries[0]["method"], "kerberos");
+        assert_eq!(entries[0]["client_ip"], "10.0.0.1");
+        assert_eq!(entries[0]["request"], "POST /add");
+        assert_eq!(entries[0]["outcome"], "success");
+        assert_eq!(entries[1]["event"], "read");
+        assert_eq!(entries[1]["status"], 200);
+        assert_eq!(entries[2]["event"], "auth_failure");
+        assert_eq!(entries[2]["outcome"], "denied");
+        assert_eq!(entries[2]["status"], 403);
+        assert_eq!(entries[2]["reason"], "Principal not allowed");
+    }
+}
@@ -0,0 +1,408 @@
+use std::time::Instant;
+
+use rocket::Request;
+use rocket::http::Status;
+use rocket::mtls::Certificate;
+use rocket::request::{FromRequest, Outcome};
+use rocket_krb5::{KrbToken, SharedAcceptor};
+
+use crate::audit::AuthOutcome;
+use crate::auth::TokenStore;
+use crate::limits::RateLimits;
+use crate::metrics::Metrics;
+use crate::routes::note_guard_error;
+
+/// Authentication methods a deployment may enable.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AuthMethod {
+    /// Kerberos SPNEGO negotiation, through the `Authorization: Negotiate` header.
+    Kerberos,
+    /// Per-host API token, through the `Authorization: Bearer` header.
+    Token,
+    /// Client certificate verified during the TLS handshake.
+    Mtls,
+}
+
+impl AuthMethod {
+    /// Parses an authentication method name as given on the command line.
+    ///
+    /// ### Parameters
+    /// - `value`: One of `kerberos`, `token` or `mtls`.
+    ///
+    /// ### Returns
+    /// - `Result<AuthMethod, &'static str>`: Ok with the method, Err otherwise.
+    ///
+    /// ### Example
+    /// ```rust
+    /// assert_eq!(AuthMethod::parse("token"), Ok(AuthMethod::Token));
+    /// ```
+    pub fn parse(value: &str) -> Result<AuthMethod, &'static str> {
+        match value {
+            "kerberos" => Ok(AuthMethod::Kerberos),
+            "token" => Ok(AuthMethod::Token),
+            "mtls" => Ok(AuthMethod::Mtls),
+            _ => Err("Unknown authentication method"),
+        }
+    }
+}
+
+impl AuthMethod {
+    /// Qualifies a name with the method it was authenticated with, Kerberos principals
+    /// being left as they are, so that an API token or a certificate subject cannot pass
+    /// for a Kerberos principal.
+    ///
+    /// ### Parameters
+    /// - `name`: The authenticated name.
+    ///
+    /// ### Returns
+    /// - `String`: The name, such as `token:router1` for an API token.
+    ///
+    /// ### Example
+    /// ```rust
+    /// assert_eq!(AuthMethod::Mtls.qualify("admin@EXAMPLE.COM"), "mtls:admin@EXAMPLE.COM");
+    /// ```
+    pub fn qualify(&self, name: &str) -> String {
+        match self {
+            AuthMethod::Kerberos => name.to_string(),
+            method => format!("{}:{}", method, name),
+        }
+    }
+}
+
+impl std::fmt::Display for AuthMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuthMethod::Kerberos => write!(f, "kerberos"),
+            AuthMethod::Token => write!(f, "token"),
+            AuthMethod::Mtls => write!(f, "mtls"),
+        }
+    }
+}
+
+/// Authentication methods enabled on the server, managed as Rocket state.
+/// A method is enabled when its backend is configured.
+#[derive(Default)]
+pub struct Authenticators {
+    /// Acceptor for Kerberos SPNEGO negotiations.
+    pub acceptor: Option<SharedAcceptor>,
+    /// Hashed API tokens.
+    pub tokens: Option<TokenStore>,
+    /// Whether verified TLS client certificates authenticate their subject.
+    pub mtls: bool,
+}
+
+impl Authenticators {
+    /// Creates authenticators accepting Kerberos only.
+    ///
+    /// ### Parameters
+    /// - `acceptor`: The acceptor authenticating the clients.
+    ///
+    /// ### Returns
+    /// - `Authenticators`: The authenticators.
+    ///
+    /// ### Example
+    /// ```rust
+    /// let auth = Authenticators::kerberos(Arc::new(FakeAcceptor::default()));
+    /// ```
+    pub fn kerberos(acceptor: SharedAcceptor) -> Authenticators {
+        Authenticators {
+            acceptor: Some(acceptor),
+            ..Default::default()
+        }
+    }
+
+    /// Lists the enabled methods, for startup logs.
+    ///
+    /// ### Returns
+    /// - `Vec<AuthMethod>`: The enabled methods.
+    pub fn methods(&self) -> Vec<AuthMethod> {
+        let mut methods = Vec::new();
+        if self.acceptor.is_some() {
+            methods.push(AuthMethod::Kerberos);
+        }
+        if self.tokens.is_some() {
+            methods.push(AuthMethod::Token);
+        }
+        if self.mtls {
+            methods.push(AuthMethod::Mtls);
+        }
+        methods
+    }
+}
+
+/// Authenticated identity of a client, whatever the method it used.
+///
+/// The principal is the Kerberos principal, the identity an API token is issued to,
+/// or the common name of the client certificate.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Identity {
+    pub principal: String,
+    pub method: AuthMethod,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Identity {
+    type Error = String;
+    /// Authenticates the client, noting the reason of a failure for the error catchers.
+    ///
+    /// ### Parameters
+    /// - `request`: Reference to the incoming request.
+    ///
+    /// ### Returns
+    /// - `Outcome<Self, Self::Error>`: Success with the identity or error outcome.
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let outcome = Identity::authenticate(request).await;
+        if let Outcome::Error((_, message)) = &outcome {
+            note_guard_error(request, message);
+        }
+        outcome
+    }
+}
+
+impl Identity {
+    /// Name owning the hosts the client registers, qualified with its method.
+    ///
+    /// ### Returns
+    /// - `String`: The principal for Kerberos clients, such as `token:router1` otherwise.
+    pub fn owner(&self) -> String {
+        self.method.qualify(&self.principal)
+    }
+
+    /// Tells whether the client is an admin of the server. Admins authenticate with
+    /// Kerberos, so that an API token or a client certificate named after an admin is not.
+    ///
+    /// ### Parameters
+    /// - `admins`: The admin principals of the server.
+    ///
+    /// ### Returns
+    /// - `bool`: Whether the client is an admin.
+    pub fn is_admin(&self, admins: &[String]) -> bool {
+        self.method == AuthMethod::Kerberos && admins.contains(&self.principal)
+    }
+
+    /// Tells whether the client may register a host. Clients authenticated with an API
+    /// token are bound to the hosts of their token, the others may register any host.
+    ///
+    /// ### Parameters
+    /// - `auth`: The authentication methods of the server.
+    /// - `hostname`: The hostname to register.
+    ///
+    /// ### Returns
+    /// - `bool`: Whether the registration is allowed.
+    pub fn may_register(&self, auth: &Authenticators, hostname: &str) -> bool {
+        self.method != AuthMethod::Token
+            || auth
+                .tokens
+                .as_ref()
+                .is_some_and(|tokens| tokens.identity_may_update(&self.principal, hostname))
+    }
+
+    /// Authenticates the client with the first enabled method it presents credentials for:
+    /// an API token, then a client certificate, then a Kerberos negotiation. Clients over
+    /// their rate are refused before any credential is checked.
+    ///
+    /// ### Parameters
+    /// - `request`: Reference to the incoming request.
+    ///
+    /// ### Returns
+    /// - `Outcome<Identity, String>`: Success with the identity or error outcome.
+    async fn authenticate(request: &Request<'_>) -> Outcome<Identity, String> {
+        let auth = match request.rocket().state::<Authenticators>() {
+            Some(a) => a,
+            None => {
+                return Outcome::Error((
+                    Status::InternalServerError,
+                    "No authenticators state set.".to_string(),
+                ));
+            }
+        };
+        let limits = request.rocket().state::<RateLimits>();
+        if let Some(Err(error)) = limits.map(|l| l.check_ip(request)) {
+            return Outcome::Error(error);
+        }
+
+        if let (Some(tokens), Some(token)) = (&auth.tokens, bearer_token(request)) {
+            return match tokens.identify(token) {
+                Some(name) => Identity::authenticated(request, name, AuthMethod::Token),
+                None => {
+                    let reason = "Unknown API token".to_string();
+                    AuthOutcome::Failed {
+                        principal: None,
+                        method: AuthMethod::Token,
+                        reason: reason.clone(),
+                    }
+                    .note(request);
+                    Outcome::Error((Status::Forbidden, reason))
+                }
+            };
+        }
+
+        if auth.mtls {
+            let certificate = request.guard::<Certificate<'_>>().await.succeeded();
+            if let Some(name) = certificate.and_then(|c| c.subject().common_name()) {
+                return Identity::authenticated(request, name, AuthMethod::Mtls);
+            }
+        }
+
+        if auth.acceptor.is_some() {
+            let _slot = match limits.map(|l| l.negotiation(request)) {
+                Some(Err(error)) => return Outcome::Error(error),
+                Some(Ok(slot)) => slot,
+                None => None,
+            };
+            let started = Instant::now();
+            let outcome = request.guard::<KrbToken>().await;
+            let (result, reason) = spnego_outcome(request, &outcome);
+            if let Some(metrics) = request.rocket().state::<Metrics>() {
+                metrics.spnego(result, reason, started.elapsed());
+            }
+            return match outcome {
+                Outcome::Success(token) => {
+                    Identity::authenticated(request, &token.principal, AuthMethod::Kerberos)
+                }
+                Outcome::Error((status, error)) => {
+                    if result == "failure" && reason != "missing_token" {
+                        AuthOutcome::Failed {
+                            principal: None,
+                            method: AuthMethod::Kerberos,
+                            reason: error.clone(),
+                        }
+                        .note(request);
+                    }
+                    Outcome::Error((status, error))
+                }
+                Outcome::Forward(status) => Outcome::Forward(status),
+            };
+        }
+
+        Outcome::Error((Status::Unauthorized, "Authentication required.".to_string()))
+    }
+
+    /// Notes a successful authentication for the audit log, unless the principal is over
+    /// its rate.
+    fn authenticated(
+        request: &Request<'_>,
+        principal: &str,
+        method: AuthMethod,
+    ) -> Outcome<Identity, String> {
+        let limits = request.rocket().state::<RateLimits>();
+        if let Some(Err(error)) = limits.map(|l| l.check_principal(request, principal)) {
+            return Outcome::Error(error);
+        }
+        AuthOutcome::Authenticated {
+            principal: principal.to_string(),
+            method,
+        }
+        .note(request);
+        Outcome::Success(Identity {
+            principal: principal.to_string(),
+            method,
+        })
+    }
+}
+
+/// Classifies the outcome of a SPNEGO step for the metrics and the audit log.
+///
+/// ### Returns
+/// - `(&'static str, &'static str)`: The result, `success` or `failure`, and its reason.
+fn spnego_outcome(
+    request: &Request<'_>,
+    outcome: &Outcome<KrbToken, String>,
+) -> (&'static str, &'static str) {
+    match outcome {
+        Outcome::Success(_) => ("success", "complete"),
+        Outcome::Error((status, _)) if *status == Status::Unauthorized => {
+            match request.headers().get_one("Authorization") {
+                None => ("failure", "missing_token"),
+                Some(_) => ("success", "continue"),
+            }
+        }
+        Outcome::Error((status, _)) if *status == Status::Forbidden => ("failure", "rejected"),
+        _ => ("failure", "error"),
+    }
+}
+
+/// Extracts the token of an `Authorization: Bearer` header.
+fn bearer_token<'r>(request: &'r Request<'_>) -> Option<&'r str> {
+    request
+        .headers()
+        .get_one("Authorization")
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .map(str::trim)
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::Status;
+
+    use crate::auth::{Authenticators, TokenStore, hash_token};
+    use crate::testing::{bearer, client, negotiate, server};
+    use crate::types::ServerOptions;
+
+    fn tokens() -> TokenStore {
+        TokenStore::parse(&format!(
+            "# edge devices\nrouter1:{}\n",
+            hash_token("s3cret")
+        ))
+        .unwrap()
+    }
+
+    #[rocket::async_test]
+    async fn api_token_authenticates_its_identity() {
+        let client = server(
+            Authenticators {
+                tokens: Some(tokens()),
+                ..Default::default()
+            },
+            ServerOptions::default(),
+        )
+        .await;
+
+        let response = client.get("/get").header(bearer("s3cret")).dispatch().await;
+
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    #[rocket::async_test]
+    async fn unknown_api_token_is_forbidden() {
+        let client = server(
+            Authenticators {
+                tokens: Some(tokens()),
+                ..Default::default()
+            },
+            ServerOptions::default(),
+        )
+        .await;
+
+        let response = client.get("/get").header(bearer("guess")).dispatch().await;
+
+        assert_eq!(response.status(), Status::Forbidden);
+    }
+
+    #[rocket::async_test]
+    async fn disabled_methods_are_ignored() {
+        let kerberos_only = client(vec![]).await;
+        let response = kerberos_only
+            .get("/get")
+            .header(bearer("s3cret"))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Forbidden);
+
+        let token_only = server(
+            Authenticators {
+                tokens: Some(tokens()),
+                ..Default::default()
+            },
+            ServerOptions::default(),
+        )
+        .await;
+        let response = token_only
+            .get("/get")
+            .header(negotiate("alice@EXAMPLE.COM"))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Unauthorized);
+        assert!(response.headers().get_one("WWW-Authenticate").is_none());
+    }
+}
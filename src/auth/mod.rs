@@ -0,0 +1,5 @@
+mod identity;
+mod tokens;
+
+pub use identity::*;
+pub use tokens::*;
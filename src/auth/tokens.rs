@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+
+use sha2::{Digest, Sha256};
+
+/// Store of per-host API tokens, only keeping the SHA-256 hash of each token.
+///
+/// The token file holds one `<identity>:<sha256 hex>` entry per line, optionally followed by
+/// the comma separated hostnames the token may register besides the one named after its
+/// identity, such as `router1:<sha256 hex> router1.example.com,home.example.com`.
+/// Blank lines and lines starting with `#` are ignored.
+#[derive(Debug, Default)]
+pub struct TokenStore {
+    hashes: HashMap<String, TokenEntry>,
+}
+
+/// Identity a token is issued to and the hostnames it may update.
+#[derive(Debug)]
+struct TokenEntry {
+    identity: String,
+    hosts: Vec<String>,
+}
+
+impl TokenStore {
+    /// Loads a token store from a file.
+    ///
+    /// ### Parameters
+    /// - `path`: Path of the token file.
+    ///
+    /// ### Returns
+    /// - `Result<TokenStore, String>`: Ok with the store, Err with error message otherwise.
+    ///
+    /// ### Example
+    /// ```rust
+    /// let store = TokenStore::load("/etc/rping/tokens").unwrap();
+    /// ```
+    pub fn load(path: &str) -> Result<TokenStore, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read token file '{}': {}", path, e))?;
+
+        TokenStore::parse(&content)
+    }
+
+    /// Parses the content of a token file.
+    ///
+    /// ### Parameters
+    /// - `content`: The content of the token file.
+    ///
+    /// ### Returns
+    /// - `Result<TokenStore, String>`: Ok with the store, Err with error message otherwise.
+    ///
+    /// ### Example
+    /// ```rust
+    /// let store = TokenStore::parse("router1:9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08").unwrap();
+    /// ```
+    pub fn parse(content: &str) -> Result<TokenStore, String> {
+        let mut hashes = HashMap::new();
+
+        for (number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (credential, hosts) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let (identity, hash) = credential
+                .rsplit_once(':')
+                .filter(|(identity, hash)| !identity.is_empty() && is_sha256_hex(hash))
+                .ok_or(format!("Malformed token entry on line {}", number + 1))?;
+            let hosts = hosts
+                .split(',')
+                .map(str::trim)
+                .filter(|host| !host.is_empty())
+                .map(str::to_lowercase)
+                .collect();
+            hashes.insert(
+                hash.to_lowercase(),
+                TokenEntry {
+                    identity: identity.to_string(),
+                    hosts,
+                },
+            );
+        }
+
+        Ok(TokenStore { hashes })
+    }
+
+    /// Finds the identity owning a token.
+    ///
+    /// ### Parameters
+    /// - `token`: The token presented by the client.
+    ///
+    /// ### Returns
+    /// - `Option<&str>`: The identity owning the token, `None` if the token is unknown.
+    pub fn identify(&self, token: &str) -> Option<&str> {
+        self.hashes
+            .get(&hash_token(token))
+            .map(|entry| entry.identity.as_str())
+    }
+
+    /// Tells whether a token may update the record of a host: the one named after its
+    /// identity or one listed along with it in the token file.
+    ///
+    /// ### Parameters
+    /// - `token`: The token presented by the client.
+    /// - `hostname`: The hostname to update.
+    ///
+    /// ### Returns
+    /// - `bool`: Whether the token is known and allowed to update the host.
+    ///
+    /// ### Example
+    /// ```rust
+    /// if !tokens.may_update(&credentials.password, "router1.example.com") {
+    ///     return "nohost";
+    /// }
+    /// ```
+    pub fn may_update(&self, token: &str, hostname: &str) -> bool {
+        self.hashes
+            .get(&hash_token(token))
+            .is_some_and(|entry| entry.allows(hostname))
+    }
+
+    /// Tells whether one of the tokens of an identity may update the record of a host.
+    ///
+    /// ### Parameters
+    /// - `identity`: The identity the client authenticated as with its token.
+    /// - `hostname`: The hostname to update.
+    ///
+    /// ### Returns
+    /// - `bool`: Whether a token of the identity is allowed to update the host.
+    ///
+    /// ### Example
+    /// ```rust
+    /// if !tokens.identity_may_update(&identity.principal, &info.hostname) {
+    ///     return Err(ApiError::new(Status::Forbidden, "Host not bound to the token"));
+    /// }
+    /// ```
+    pub fn identity_may_update(&self, identity: &str, hostname: &str) -> bool {
+        self.hashes
+            .values()
+            .any(|entry| entry.identity == identity && entry.allows(hostname))
+    }
+
+    /// Number of tokens in the store.
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    /// Whether the store holds no token.
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+}
+
+impl TokenEntry {
+    /// Whether the host is the one named after the identity or one listed with it.
+    fn allows(&self, hostname: &str) -> bool {
+        let hostname = hostname.to_lowercase();
+        self.identity.to_lowercase() == hostname || self.hosts.contains(&hostname)
+    }
+}
+
+/// Hashes a token the way it is stored at rest.
+///
+/// ### Parameters
+/// - `token`: The token in clear.
+///
+/// ### Returns
+/// - `String`: The lowercase hexadecimal SHA-256 hash of the token.
+pub fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Generates a new random API token for an identity.
+///
+/// ### Parameters
+/// - `identity`: The identity the token is issued to.
+///
+/// ### Returns
+/// - `(String, String)`: The token in clear and the entry to append to the token file.
+///
+/// ### Example
+/// ```rust
+/// let (token, entry) = generate_token("router1");
+/// ```
+pub fn generate_token(identity: &str) -> (String, String) {
+    let bytes: [u8; 32] = rand::random();
+    let token: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    let entry = format!("{}:{}", identity, hash_token(&token));
+
+    (token, entry)
+}
+
+fn is_sha256_hex(value: &str) -> bool {
+    value.len() == 64 && value.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TokenStore, generate_token, hash_token};
+
+    #[test]
+    fn generated_tokens_match_their_entry() {
+        let (token, entry) = generate_token("router1");
+        let store = TokenStore::parse(&entry).unwrap();
+
+        assert_eq!(store.identify(&token), Some("router1"));
+        assert_eq!(store.identify("router1"), None);
+    }
+
+    #[test]
+    fn tokens_only_update_their_hosts() {
+        let entry = format!(
+            "router1:{} Router1.example.com, home.example.com",
+            hash_token("s3cret")
+        );
+        let store = TokenStore::parse(&entry).unwrap();
+
+        assert_eq!(store.identify("s3cret"), Some("router1"));
+        assert!(store.may_update("s3cret", "router1"));
+        assert!(store.may_update("s3cret", "router1.EXAMPLE.com"));
+        assert!(store.may_update("s3cret", "home.example.com"));
+        assert!(!store.may_update("s3cret", "router2.example.com"));
+        assert!(!store.may_update("guess", "router1"));
+        assert!(store.identity_may_update("router1", "home.example.com"));
+        assert!(!store.identity_may_update("router2", "home.example.com"));
+    }
+
+    #[test]
+    fn malformed_entries_are_rejected() {
+        let error = TokenStore::parse("\n# comment\nrouter1:not-a-hash\n").unwrap_err();
+
+        assert_eq!(error, "Malformed token entry on line 3");
+    }
+}
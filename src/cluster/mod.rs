@@ -0,0 +1,7 @@
+mod peer;
+mod replication;
+mod state;
+
+pub use peer::*;
+pub use replication::*;
+pub use state::*;
@@ -0,0 +1,34 @@
+use rocket::Request;
+use rocket::http::Status;
+use rocket::outcome::try_outcome;
+use rocket::request::{FromRequest, Outcome};
+
+use crate::auth::{AuthMethod, Identity};
+use crate::cluster::Cluster;
+use crate::routes::note_guard_error;
+
+/// Guard letting through the other servers of the cluster only, authenticated with the
+/// Kerberos service principals they are configured with.
+pub struct ClusterPeer(pub Identity);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ClusterPeer {
+    type Error = String;
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let identity = try_outcome!(request.guard::<Identity>().await);
+        let Some(cluster) = request.rocket().state::<Cluster>() else {
+            return Outcome::Error((
+                Status::InternalServerError,
+                "No cluster state set.".to_string(),
+            ));
+        };
+
+        if identity.method == AuthMethod::Kerberos && cluster.is_peer(&identity.principal) {
+            Outcome::Success(ClusterPeer(identity))
+        } else {
+            let message = format!("{} is not a cluster peer", identity.principal);
+            note_guard_error(request, &message);
+            Outcome::Error((Status::Forbidden, message))
+        }
+    }
+}
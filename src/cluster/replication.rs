@@ -0,0 +1,224 @@
+use std::time::Duration;
+
+use librping::{ClientOptions, fetch_json};
+use rocket::tokio;
+
+use crate::cluster::Cluster;
+use crate::events::{EventKind, Events};
+use crate::routes::API_V1;
+use crate::store::Replica;
+use crate::types::{HostMap, HostRecord};
+
+/// Time after which the removal of a host is forgotten. A server that could not reach the
+/// cluster for longer than that may bring back the hosts removed meanwhile.
+const TOMBSTONE_TTL: Duration = Duration::from_secs(24 * 3600);
+
+/// Spawns the background task pulling the records of every peer at the interval of the
+/// cluster settings and forgetting the old removals.
+///
+/// Peers are pulled with their whole state, each host being applied if its version is newer
+/// than the local one, so that every server converges on the last change made anywhere.
+///
+/// ### Parameters
+/// - `cluster`: The cluster state, noting the outcome of each pull.
+/// - `map`: Shared state for DNS records.
+/// - `events`: The event bus, told of the changes applied.
+///
+/// ### Example
+/// ```rust
+/// spawn_replication(cluster.clone(), map.clone(), events.clone());
+/// ```
+pub fn spawn_replication(cluster: Cluster, map: HostMap, events: Events) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(cluster.options.interval);
+        loop {
+            ticker.tick().await;
+            sync_peers(&cluster, &map, &events).await;
+            let pruned = map.prune(TOMBSTONE_TTL);
+            if pruned > 0 {
+                log::debug!(target: "rping::cluster", "{} removals forgotten", pruned);
+            }
+        }
+    });
+}
+
+/// Pulls the records of every peer once, noting the outcome of each pull.
+///
+/// ### Parameters
+/// - `cluster`: The cluster state.
+/// - `map`: Shared state for DNS records.
+/// - `events`: The event bus, told of the changes applied.
+pub async fn sync_peers(cluster: &Cluster, map: &HostMap, events: &Events) {
+    for url in &cluster.options.peers {
+        let outcome = pull(&cluster.options.client, url, map, events).await;
+        match &outcome {
+            Ok(0) => {}
+            Ok(applied) => {
+                log::info!(target: "rping::cluster", "{} changes applied from {}", applied, url)
+            }
+            Err(e) => log::warn!(target: "rping::cluster", "Cannot pull {}: {}", url, e),
+        }
+        cluster.record(url, outcome);
+    }
+}
+
+/// Pulls the records of a peer and applies the newer ones.
+///
+/// ### Returns
+/// - `Result<usize, String>`: Ok with the number of changes applied, Err if the peer cannot be pulled.
+async fn pull(
+    client: &ClientOptions,
+    url: &str,
+    map: &HostMap,
+    events: &Events,
+) -> Result<usize, String> {
+    let options = ClientOptions {
+        url: format!("{}{}/cluster/replicas", url.trim_end_matches('/'), API_V1),
+        ..client.clone()
+    };
+    let replicas: Vec<Replica> = fetch_json(&options).await?;
+
+    let mut applied = 0;
+    for replica in replicas {
+        if map.merge(replica, |old, replica| announce(events, old, replica)) {
+            applied += 1;
+        }
+    }
+    Ok(applied)
+}
+
+/// Publishes the event matching a change applied from a peer, on behalf of the principal
+/// that made it.
+fn announce(events: &Events, old: Option<&HostRecord>, replica: &Replica) {
+    let principal = replica.principal.as_deref();
+    match (old, &replica.record) {
+        (_, Some(new)) => {
+            events.registered(&replica.hostname, old, new, principal.unwrap_or_default())
+        }
+        (Some(old), None) => {
+            let kind = match principal {
+                Some(_) => EventKind::Deleted,
+                None => EventKind::Expired,
+            };
+            events.removed(kind, &replica.hostname, old, principal);
+        }
+        (None, None) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use librping::{
+        ClientOptions, FakeMechanism, RetryPolicy, cluster_status, fetch_json, send_dns,
+    };
+    use rocket_krb5::FakeAcceptor;
+
+    use super::sync_peers;
+    use crate::auth::Authenticators;
+    use crate::cluster::{Cluster, ClusterOptions};
+    use crate::events::{EventKind, Events};
+    use crate::launcher::build_server;
+    use crate::store::{HostStore, Replica};
+    use crate::testing::{launch, negotiate};
+    use crate::types::{HostMap, HostRecord, ServerOptions};
+
+    /// Launches a server on an ephemeral port, pulled by the peer `HTTP/b@EXAMPLE.COM`.
+    async fn serve_peer() -> (String, rocket::Shutdown) {
+        let options = ServerOptions {
+            cluster: ClusterOptions {
+                node: "a".to_string(),
+                principals: vec!["HTTP/b@EXAMPLE.COM".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        launch(build_server(
+            rocket::build(),
+            Authenticators::kerberos(Arc::new(FakeAcceptor::default())),
+            options,
+        ))
+        .await
+    }
+
+    fn as_principal(url: String, principal: &str) -> ClientOptions {
+        ClientOptions {
+            url,
+            mechanism: Arc::new(FakeMechanism::new(principal.to_string(), false)),
+            retry: RetryPolicy {
+                max_attempts: 1,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[rocket::async_test]
+    async fn peers_converge_on_the_last_change() {
+        let (url, shutdown) = serve_peer().await;
+        let alice = |path: &str| as_principal(format!("{}{}", url, path), "alice@EXAMPLE.COM");
+        let cluster = Cluster::new(ClusterOptions {
+            node: "b".to_string(),
+            peers: vec![url.clone()],
+            client: as_principal(String::new(), "HTTP/b@EXAMPLE.COM"),
+            ..Default::default()
+        });
+        let map: HostMap = Arc::new(HostStore::replicated("b"));
+        let events = Events::default();
+        let mut changes = events.subscribe();
+
+        send_dns("h1".to_string(), &alice("/api/v1/hosts"))
+            .await
+            .unwrap();
+        send_dns("h2".to_string(), &alice("/api/v1/hosts"))
+            .await
+            .unwrap();
+        sync_peers(&cluster, &map, &events).await;
+
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get("h1").unwrap().ips(), "127.0.0.1");
+        let created = changes.recv().await.unwrap();
+        assert_eq!(created.kind, EventKind::Created);
+        assert_eq!(created.principal.as_deref(), Some("alice@EXAMPLE.COM"));
+        let refused = fetch_json::<Vec<Replica>>(&alice("/api/v1/cluster/replicas")).await;
+        assert!(
+            refused
+                .unwrap_err()
+                .contains("alice@EXAMPLE.COM is not a cluster peer")
+        );
+
+        // A removal on the peer is applied, a later local change is kept.
+        let deleted = reqwest::Client::new()
+            .delete(format!("{}/api/v1/hosts/h2", url))
+            .header("Authorization", negotiate("alice@EXAMPLE.COM").value())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(deleted.status(), 204);
+        let local = HostRecord {
+            last_seen: 42,
+            ..Default::default()
+        };
+        map.upsert(
+            "h1",
+            "bob@EXAMPLE.COM",
+            "bob@EXAMPLE.COM",
+            |_| local.clone(),
+            |_, _| {},
+        );
+        sync_peers(&cluster, &map, &events).await;
+
+        assert_eq!(map.get("h1"), Some(local));
+        assert!(map.get("h2").is_none());
+        let status = cluster.status(map.len());
+        assert_eq!(status.peers[0].applied, 3);
+        assert!(status.peers[0].last_success.is_some());
+        assert_eq!(status.peers[0].last_error, None);
+        let remote = cluster_status(&alice("")).await.unwrap();
+        assert_eq!((remote.node.as_str(), remote.hosts), ("a", 1));
+        assert!(remote.peers.is_empty());
+
+        shutdown.notify();
+    }
+}
@@ -0,0 +1,154 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use librping::{ClientOptions, RetryPolicy};
+use rocket::serde::{Deserialize, Serialize};
+
+use crate::types::unix_now;
+
+/// Replication of the host records between the servers of a cluster, disabled by default.
+#[derive(Clone, Debug)]
+pub struct ClusterOptions {
+    /// Name of this server, the origin of the changes made on it.
+    pub node: String,
+    /// Base URLs of the other servers, whose records are pulled.
+    pub peers: Vec<String>,
+    /// Service principals of the other servers, the only ones allowed to pull the records.
+    pub principals: Vec<String>,
+    /// Time between two pulls of the peers.
+    pub interval: Duration,
+    /// Options of the client pulling the peers, such as the realm and the retry policy.
+    pub client: ClientOptions,
+}
+
+impl Default for ClusterOptions {
+    fn default() -> Self {
+        ClusterOptions {
+            node: String::new(),
+            peers: Vec::new(),
+            principals: Vec::new(),
+            interval: Duration::from_secs(10),
+            client: ClientOptions {
+                retry: RetryPolicy {
+                    max_attempts: 1,
+                    deadline: Some(Duration::from_secs(30)),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        }
+    }
+}
+
+impl ClusterOptions {
+    /// Tells whether the server is part of a cluster, pulling peers or being pulled.
+    ///
+    /// ### Returns
+    /// - `bool`: True if peers or peer principals are configured.
+    pub fn enabled(&self) -> bool {
+        !self.peers.is_empty() || !self.principals.is_empty()
+    }
+}
+
+/// Outcome of the pulls of a peer.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct PeerStatus {
+    /// Base URL of the peer.
+    pub url: String,
+    /// Time of the last successful pull, in seconds since the Unix epoch.
+    pub last_success: Option<u64>,
+    /// Error of the last pull, `None` if it succeeded.
+    pub last_error: Option<String>,
+    /// Number of changes of the peer applied since the server started.
+    pub applied: u64,
+}
+
+/// Replication state of a server, as answered by `/api/v1/cluster`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ClusterStatus {
+    pub node: String,
+    /// Number of registered hosts.
+    pub hosts: usize,
+    pub peers: Vec<PeerStatus>,
+}
+
+/// Cluster settings and outcome of the pulls of each peer, managed as Rocket state and
+/// shared with the replication task.
+#[derive(Clone)]
+pub struct Cluster {
+    pub options: ClusterOptions,
+    peers: Arc<Mutex<Vec<PeerStatus>>>,
+}
+
+impl Cluster {
+    /// Creates the state of the cluster, no peer being pulled yet.
+    ///
+    /// ### Parameters
+    /// - `options`: The cluster settings.
+    ///
+    /// ### Returns
+    /// - `Cluster`: The cluster state.
+    pub fn new(options: ClusterOptions) -> Cluster {
+        let peers = options
+            .peers
+            .iter()
+            .map(|url| PeerStatus {
+                url: url.clone(),
+                ..Default::default()
+            })
+            .collect();
+
+        Cluster {
+            options,
+            peers: Arc::new(Mutex::new(peers)),
+        }
+    }
+
+    /// Tells whether a principal is the service principal of a peer.
+    ///
+    /// ### Parameters
+    /// - `principal`: The authenticated principal.
+    ///
+    /// ### Returns
+    /// - `bool`: True if the principal may pull the records.
+    pub fn is_peer(&self, principal: &str) -> bool {
+        self.options.principals.iter().any(|p| p == principal)
+    }
+
+    /// Notes the outcome of a pull of a peer.
+    ///
+    /// ### Parameters
+    /// - `url`: The base URL of the peer.
+    /// - `outcome`: Ok with the number of changes applied, Err with the reason of the failure.
+    pub fn record(&self, url: &str, outcome: Result<usize, String>) {
+        let mut peers = self.peers.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(peer) = peers.iter_mut().find(|p| p.url == url) else {
+            return;
+        };
+        match outcome {
+            Ok(applied) => {
+                peer.last_success = Some(unix_now());
+                peer.last_error = None;
+                peer.applied += applied as u64;
+            }
+            Err(e) => peer.last_error = Some(e),
+        }
+    }
+
+    /// Reports the replication state of the server.
+    ///
+    /// ### Parameters
+    /// - `hosts`: The number of registered hosts.
+    ///
+    /// ### Returns
+    /// - `ClusterStatus`: The node name and the outcome of the pulls of each peer.
+    pub fn status(&self, hosts: usize) -> ClusterStatus {
+        ClusterStatus {
+            node: self.options.node.clone(),
+            hosts,
+            peers: self.peers.lock().unwrap_or_else(|e| e.into_inner()).clone(),
+        }
+    }
+}
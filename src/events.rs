@@ -0,0 +1,305 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use rocket::serde::{Deserialize, Serialize};
+use rocket::tokio::sync::broadcast;
+
+use crate::types::{HostRecord, unix_now};
+
+/// Number of events a slow subscriber may lag behind before missing some.
+const EVENTS_CAPACITY: usize = 1024;
+/// Number of past events kept for the subscribers resuming a stream.
+const EVENT_LOG_CAPACITY: usize = 1024;
+
+/// Kind of change made to a host record.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum EventKind {
+    /// A host registered for the first time.
+    Created,
+    /// The addresses of a host changed.
+    Updated,
+    /// A host stopped reporting and its record expired.
+    Expired,
+    /// A host record was deleted.
+    Deleted,
+}
+
+impl std::fmt::Display for EventKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EventKind::Created => write!(f, "created"),
+            EventKind::Updated => write!(f, "updated"),
+            EventKind::Expired => write!(f, "expired"),
+            EventKind::Deleted => write!(f, "deleted"),
+        }
+    }
+}
+
+/// Change made to a host record, as sent to the subscribers.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct HostEvent {
+    /// Sequence number of the event, increasing over the life of the server.
+    pub id: u64,
+    #[serde(rename = "event")]
+    pub kind: EventKind,
+    pub hostname: String,
+    /// Addresses before the change, `None` for a created host.
+    pub old_ip: Option<String>,
+    /// Addresses after the change, `None` for an expired or deleted host.
+    pub new_ip: Option<String>,
+    /// Principal that made the change, `None` when the server did.
+    pub principal: Option<String>,
+    /// Time of the change, in seconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+/// Bus broadcasting the changes made to host records, managed as Rocket state.
+///
+/// Every subscriber receives every event published after it subscribed, the most recent
+/// events being kept in a bounded log for the subscribers catching up.
+#[derive(Clone)]
+pub struct Events {
+    sender: broadcast::Sender<HostEvent>,
+    log: Arc<Mutex<EventLog>>,
+}
+
+/// Most recent events, along with the identifier of the next one.
+struct EventLog {
+    next_id: u64,
+    recent: VecDeque<HostEvent>,
+}
+
+impl Default for Events {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(EVENTS_CAPACITY);
+        Events {
+            sender,
+            log: Arc::new(Mutex::new(EventLog {
+                next_id: 1,
+                recent: VecDeque::with_capacity(EVENT_LOG_CAPACITY),
+            })),
+        }
+    }
+}
+
+impl Events {
+    /// Subscribes to the events published from now on.
+    ///
+    /// ### Returns
+    /// - `broadcast::Receiver<HostEvent>`: The receiving end of the bus.
+    pub fn subscribe(&self) -> broadcast::Receiver<HostEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Lists the events published after a given one, from the log.
+    ///
+    /// ### Parameters
+    /// - `last_id`: Identifier of the last event the subscriber received.
+    ///
+    /// ### Returns
+    /// - `Option<Vec<HostEvent>>`: The events following `last_id`, `None` if some of them
+    ///   already left the log or if `last_id` was never published, such as an identifier
+    ///   given out before a restart.
+    ///
+    /// ### Example
+    /// ```rust
+    /// let receiver = events.subscribe();
+    /// let missed = events.since(41).unwrap_or_default();
+    /// ```
+    pub fn since(&self, last_id: u64) -> Option<Vec<HostEvent>> {
+        let log = self.log.lock().unwrap_or_else(|e| e.into_inner());
+        let oldest = log.recent.front().map_or(log.next_id, |e| e.id);
+        if last_id.saturating_add(1) < oldest || last_id >= log.next_id {
+            return None;
+        }
+
+        Some(
+            log.recent
+                .iter()
+                .filter(|e| e.id > last_id)
+                .cloned()
+                .collect(),
+        )
+    }
+
+    /// Lists the events kept in the log, oldest first.
+    ///
+    /// ### Returns
+    /// - `Vec<HostEvent>`: The most recent events.
+    pub fn history(&self) -> Vec<HostEvent> {
+        let log = self.log.lock().unwrap_or_else(|e| e.into_inner());
+        log.recent.iter().cloned().collect()
+    }
+
+    /// Fills the log with the events of another server, such as the history of a snapshot,
+    /// if no event was published yet. The events keep their identifiers, the next ones
+    /// following them, and are not sent to the subscribers.
+    ///
+    /// ### Parameters
+    /// - `history`: The events, oldest first, their identifiers increasing.
+    ///
+    /// ### Returns
+    /// - `usize`: The number of events restored, none if events were already published.
+    ///
+    /// ### Example
+    /// ```rust
+    /// let restored = events.restore(snapshot.history.clone());
+    /// ```
+    pub fn restore(&self, history: Vec<HostEvent>) -> usize {
+        let mut log = self.log.lock().unwrap_or_else(|e| e.into_inner());
+        if log.next_id != 1 {
+            return 0;
+        }
+
+        let skipped = history.len().saturating_sub(EVENT_LOG_CAPACITY);
+        log.recent.extend(history.into_iter().skip(skipped));
+        log.next_id = log.recent.back().map_or(1, |e| e.id + 1);
+        log.recent.len()
+    }
+
+    /// Publishes the event matching a registration, if it changed anything worth telling:
+    /// a new host or new addresses.
+    ///
+    /// ### Parameters
+    /// - `hostname`: The registered hostname.
+    /// - `old`: The record before the registration, if any.
+    /// - `new`: The record after the registration.
+    /// - `principal`: The principal that registered the host.
+    ///
+    /// ### Example
+    /// ```rust
+    /// let previous = map.lock().await.insert(hostname.clone(), record.clone());
+    /// events.registered(&hostname, previous.as_ref(), &record, &identity.principal);
+    /// ```
+    pub fn registered(
+        &self,
+        hostname: &str,
+        old: Option<&HostRecord>,
+        new: &HostRecord,
+        principal: &str,
+    ) {
+        let kind = match old {
+            None => EventKind::Created,
+            Some(old) if old.addresses != new.addresses => EventKind::Updated,
+            Some(_) => return,
+        };
+
+        self.publish(
+            kind,
+            hostname,
+            old.map(HostRecord::ips),
+            Some(new.ips()),
+            Some(principal),
+        );
+    }
+
+    /// Publishes the event matching the removal of a host record.
+    ///
+    /// ### Parameters
+    /// - `kind`: Either [`EventKind::Expired`] or [`EventKind::Deleted`].
+    /// - `hostname`: The removed hostname.
+    /// - `old`: The removed record.
+    /// - `principal`: The principal that removed the host, `None` for an expiry.
+    pub fn removed(
+        &self,
+        kind: EventKind,
+        hostname: &str,
+        old: &HostRecord,
+        principal: Option<&str>,
+    ) {
+        self.publish(kind, hostname, Some(old.ips()), None, principal);
+    }
+
+    fn publish(
+        &self,
+        kind: EventKind,
+        hostname: &str,
+        old_ip: Option<String>,
+        new_ip: Option<String>,
+        principal: Option<&str>,
+    ) {
+        // Identifiers are given under the lock so that the log and the bus stay in order.
+        let mut log = self.log.lock().unwrap_or_else(|e| e.into_inner());
+        let event = HostEvent {
+            id: log.next_id,
+            kind,
+            hostname: hostname.to_string(),
+            old_ip,
+            new_ip,
+            principal: principal.map(String::from),
+            timestamp: unix_now(),
+        };
+        log.next_id += 1;
+        if log.recent.len() == EVENT_LOG_CAPACITY {
+            log.recent.pop_front();
+        }
+        log.recent.push_back(event.clone());
+
+        // Sending only fails when nobody listens, which is fine.
+        let _ = self.sender.send(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EventKind, Events};
+    use crate::types::AddressPolicy;
+
+    #[test]
+    fn registrations_publish_creations_and_updates_only() {
+        let events = Events::default();
+        let mut receiver = events.subscribe();
+        let first = AddressPolicy::Observed
+            .record("10.0.0.1".to_string(), &[])
+            .unwrap();
+        let second = AddressPolicy::Observed
+            .record("10.0.0.2".to_string(), &[])
+            .unwrap();
+
+        events.registered("h1", None, &first, "alice@EXAMPLE.COM");
+        events.registered("h1", Some(&first), &first, "alice@EXAMPLE.COM");
+        events.registered("h1", Some(&first), &second, "alice@EXAMPLE.COM");
+        events.removed(EventKind::Deleted, "h1", &second, None);
+
+        let created = receiver.try_recv().unwrap();
+        let updated = receiver.try_recv().unwrap();
+        let deleted = receiver.try_recv().unwrap();
+        assert!(receiver.try_recv().is_err());
+        assert_eq!((created.id, created.kind), (1, EventKind::Created));
+        assert_eq!(created.old_ip, None);
+        assert_eq!((updated.id, updated.kind), (2, EventKind::Updated));
+        assert_eq!(updated.old_ip.as_deref(), Some("10.0.0.1"));
+        assert_eq!(updated.new_ip.as_deref(), Some("10.0.0.2"));
+        assert_eq!(deleted.kind, EventKind::Deleted);
+        assert_eq!(deleted.new_ip, None);
+    }
+
+    #[test]
+    fn log_replays_recent_events_only() {
+        let events = Events::default();
+        let record = AddressPolicy::Observed
+            .record("10.0.0.1".to_string(), &[])
+            .unwrap();
+
+        for i in 0..super::EVENT_LOG_CAPACITY + 2 {
+            events.registered(&format!("h{}", i), None, &record, "alice@EXAMPLE.COM");
+        }
+
+        let ids: Vec<u64> = events.since(1024).unwrap().iter().map(|e| e.id).collect();
+        assert_eq!(ids, vec![1025, 1026]);
+        assert_eq!(events.since(1026).unwrap(), vec![]);
+        assert!(events.since(2).is_some());
+        assert!(events.since(1).is_none());
+        assert!(events.since(1027).is_none());
+    }
+
+    #[test]
+    fn identifiers_from_before_a_restart_are_unknown() {
+        let events = Events::default();
+
+        assert_eq!(events.since(0), Some(vec![]));
+        assert!(events.since(41).is_none());
+    }
+}
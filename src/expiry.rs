@@ -0,0 +1,97 @@
+use std::time::Duration;
+
+use rocket::tokio;
+
+use crate::events::{EventKind, Events};
+use crate::types::{HostMap, unix_now};
+
+/// Spawns a background task removing the hosts that did not report for longer than
+/// the given time, publishing an expiry event for each of them.
+///
+/// ### Parameters
+/// - `map`: Shared state for DNS records.
+/// - `events`: The event bus.
+/// - `expire_after`: Age above which a record expires.
+///
+/// ### Example
+/// ```rust
+/// spawn_expiry(map.clone(), events.clone(), Duration::from_secs(86400));
+/// ```
+pub fn spawn_expiry(map: HostMap, events: Events, expire_after: Duration) {
+    let period = (expire_after / 4).clamp(Duration::from_secs(1), Duration::from_secs(60));
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(period);
+        loop {
+            ticker.tick().await;
+            let expired = expire(&map, &events, expire_after);
+            if expired > 0 {
+                log::info!("{} hosts expired", expired);
+            }
+        }
+    });
+}
+
+/// Removes the records older than the given age.
+///
+/// ### Parameters
+/// - `map`: Shared state for DNS records.
+/// - `events`: The event bus.
+/// - `expire_after`: Age above which a record expires.
+///
+/// ### Returns
+/// - `usize`: The number of expired records.
+pub fn expire(map: &HostMap, events: &Events, expire_after: Duration) -> usize {
+    let deadline = unix_now().saturating_sub(expire_after.as_secs());
+    let mut expired = 0;
+
+    map.retain(|hostname, record| {
+        if record.last_seen >= deadline {
+            return true;
+        }
+        events.removed(EventKind::Expired, hostname, record, None);
+        expired += 1;
+        false
+    });
+
+    expired
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::expire;
+    use crate::events::{EventKind, Events};
+    use crate::store::HostStore;
+    use crate::types::{HostMap, HostRecord, unix_now};
+
+    #[rocket::async_test]
+    async fn old_records_expire() {
+        let fresh = HostRecord {
+            last_seen: unix_now(),
+            ..Default::default()
+        };
+        let old = HostRecord {
+            last_seen: unix_now() - 7200,
+            ..Default::default()
+        };
+        let map: HostMap = Arc::new(HostStore::from_iter([
+            ("fresh".to_string(), fresh),
+            ("old".to_string(), old),
+        ]));
+        let events = Events::default();
+        let mut receiver = events.subscribe();
+
+        let expired = expire(&map, &events, Duration::from_secs(3600));
+
+        assert_eq!(expired, 1);
+        assert!(map.get("fresh").is_some());
+        let event = receiver.try_recv().unwrap();
+        assert_eq!(
+            (event.kind, event.hostname.as_str()),
+            (EventKind::Expired, "old")
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rocket::tokio::sync::Semaphore;
+use rocket::tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::events::HostEvent;
+
+/// Local command run on every change made to a host record.
+#[derive(Clone, Debug)]
+pub struct HookOptions {
+    /// Executable to run, no hook being run when `None`.
+    pub command: Option<PathBuf>,
+    /// Time after which a running hook is killed.
+    pub timeout: Duration,
+    /// Number of hooks allowed to run at the same time.
+    pub concurrency: usize,
+}
+
+impl Default for HookOptions {
+    fn default() -> Self {
+        HookOptions {
+            command: None,
+            timeout: Duration::from_secs(30),
+            concurrency: 4,
+        }
+    }
+}
+
+/// Spawns the background task running the hook on every event.
+///
+/// Hooks are started in the order of the events, at most `concurrency` of them at the same time,
+/// so hooks of successive changes may overlap. The change is described to the command
+/// through the environment variables `RPING_EVENT`, `RPING_HOSTNAME`, `RPING_OLD_IP`,
+/// `RPING_NEW_IP` and `RPING_PRINCIPAL`, the unknown ones being empty.
+///
+/// ### Parameters
+/// - `options`: The hook to run.
+/// - `events`: Subscription to the event bus.
+///
+/// ### Example
+/// ```rust
+/// let options = HookOptions { command: Some("/etc/rping/reload-nginx".into()), ..Default::default() };
+/// spawn_hooks(options, events.subscribe());
+/// ```
+pub fn spawn_hooks(options: HookOptions, mut events: broadcast::Receiver<HostEvent>) {
+    let Some(command) = options.command.clone() else {
+        return;
+    };
+    let permits = Arc::new(Semaphore::new(options.concurrency.max(1)));
+
+    rocket::tokio::spawn(async move {
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(missed)) => {
+                    log::warn!("Hooks fell behind and missed {} events", missed);
+                    continue;
+                }
+                Err(RecvError::Closed) => return,
+            };
+
+            let Ok(permit) = permits.clone().acquire_owned().await else {
+                return;
+            };
+            let command = command.clone();
+            rocket::tokio::spawn(async move {
+                if let Err(e) = run_hook(&command, options.timeout, &event).await {
+                    log::warn!("{}", e);
+                }
+                drop(permit);
+            });
+        }
+    });
+}
+
+/// Runs the hook for one event, copying its output into the server log.
+///
+/// ### Parameters
+/// - `command`: The executable to run.
+/// - `timeout`: Time after which the command is killed.
+/// - `event`: The change to describe to the command.
+///
+/// ### Returns
+/// - `Result<(), String>`: Ok if the command succeeded, Err if it could not start,
+///   failed or timed out.
+///
+/// ### Example
+/// ```rust
+/// run_hook(Path::new("/etc/rping/reload-nginx"), Duration::from_secs(30), &event).await?;
+/// ```
+pub async fn run_hook(command: &Path, timeout: Duration, event: &HostEvent) -> Result<(), String> {
+    let name = format!("Hook {} for {}", event.kind, event.hostname);
+    librping::run_hook(command, timeout, &name, hook_env(event), |line| {
+        log::info!("{}: {}", name, line)
+    })
+    .await
+}
+
+/// Describes an event through the environment variables of the hook, the unknown values
+/// being empty.
+fn hook_env(event: &HostEvent) -> [(&'static str, String); 5] {
+    [
+        ("RPING_EVENT", event.kind.to_string()),
+        ("RPING_HOSTNAME", event.hostname.clone()),
+        ("RPING_OLD_IP", event.old_ip.clone().unwrap_or_default()),
+        ("RPING_NEW_IP", event.new_ip.clone().unwrap_or_default()),
+        (
+            "RPING_PRINCIPAL",
+            event.principal.clone().unwrap_or_default(),
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::hook_env;
+    use crate::events::{EventKind, HostEvent};
+
+    #[test]
+    fn hooks_receive_the_change_in_their_environment() {
+        let event = HostEvent {
+            id: 1,
+            kind: EventKind::Updated,
+            hostname: "h1".to_string(),
+            old_ip: Some("10.0.0.1".to_string()),
+            new_ip: None,
+            principal: Some("host/h1@EXAMPLE.COM".to_string()),
+            timestamp: 0,
+        };
+
+        assert_eq!(
+            hook_env(&event).map(|(_, value)| value),
+            ["updated", "h1", "10.0.0.1", "", "host/h1@EXAMPLE.COM"]
+        );
+    }
+}
@@ -1,22 +1,122 @@
-use rocket::futures::lock::Mutex;
-use rocket_krb5::{KrbFairing, KrbServerCreds};
-use std::collections::HashMap;
+use librping::{ClientOptions, OutputFormat, RetryPolicy, Strategy};
+use log::LevelFilter;
+use rocket::fairing::AdHoc;
+use rocket::{Build, Orbit, Rocket};
+use rocket_krb5::{
+    KrbFairing, KrbServerCreds, ReloadableCreds, SharedAcceptor, SharedServerCreds, spawn_reloader,
+    use_server_keytab,
+};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 
-use crate::{routes, types::HostMap};
+use crate::{
+    admin::ImportMode,
+    audit::{AuditLog, AuditTrail},
+    auth::{AuthMethod, Authenticators, TokenStore, generate_token, hash_token},
+    cluster::{Cluster, ClusterOptions, spawn_replication},
+    events::Events,
+    expiry::spawn_expiry,
+    hooks::{HookOptions, spawn_hooks},
+    limits::{LimitOptions, Rate, RateLimits},
+    logging::{LogFormat, RequestLogger, init_logging},
+    metrics::{Metrics, MetricsOptions, Network, RequestTimer},
+    routes::{self, OpenApiDocument},
+    store::HostStore,
+    types::{AddressPolicy, HostMap, ServerOptions},
+    webhooks::{WebhookOptions, spawn_webhooks},
+};
 
 /// Configuration struct for application launch parameters.
-/// Used to store options for serving, listing, or sending DNS records.
+/// Used to store options for serving, listing, sending DNS records, running the agent,
+/// watching changes, checking the server health or issuing API tokens.
 struct Config {
     // common options [serve, list, send]
     action: String,
+    // sub-command of the cluster and admin actions
+    command: String,
     url: String,
+    failover_urls: Vec<String>,
+    srv: Option<String>,
+    strategy: Strategy,
 
     // serve action params
     port: u16,
-    principal: String,
+    auth: Vec<AuthMethod>,
+    principals: Vec<String>,
+    keytab: Option<String>,
+    reload_interval: Duration,
+    token_file: Option<String>,
+    tls_cert: Option<String>,
+    tls_key: Option<String>,
+    tls_ca: Option<String>,
+    address_policy: AddressPolicy,
+    expire_after: Option<Duration>,
+    webhooks: WebhookOptions,
+    hooks: HookOptions,
+    metrics_allow: Vec<Network>,
+    metrics_token_hash: Option<String>,
+    log_level: LevelFilter,
+    log_format: LogFormat,
+    audit_log: Option<PathBuf>,
+    limits: LimitOptions,
+    cluster: ClusterOptions,
+    peer_keytab: Option<String>,
+    admins: Vec<String>,
 
     // list send action params
     realm: String,
+    service_principal: Option<String>,
+    client_keytab: Option<String>,
+    api_token: Option<String>,
+    addresses: Vec<String>,
+    metadata: bool,
+    tags: BTreeMap<String, String>,
+    format: OutputFormat,
+    retry: RetryPolicy,
+
+    // agent action params
+    interval: Duration,
+    on_change: Option<PathBuf>,
+    on_change_timeout: Duration,
+
+    // stale action params
+    older_than: Duration,
+
+    // token action params
+    identity: String,
+
+    // admin action params
+    snapshot: Option<PathBuf>,
+    import_mode: ImportMode,
+}
+
+impl Config {
+    /// Builds the options used by the client actions.
+    ///
+    /// ### Returns
+    /// - `ClientOptions`: Options describing the server to reach.
+    fn client_options(&self) -> ClientOptions {
+        ClientOptions {
+            url: self.url.clone(),
+            failover_urls: self.failover_urls.clone(),
+            srv: self.srv.clone(),
+            strategy: self.strategy,
+            realm: self.realm.clone(),
+            service_principal: self.service_principal.clone(),
+            client_keytab: self.client_keytab.clone(),
+            api_token: self.api_token.clone(),
+            addresses: self.addresses.clone(),
+            metadata: self.metadata,
+            tags: self.tags.clone(),
+            format: self.format,
+            retry: self.retry.clone(),
+            hook: self.on_change.clone(),
+            hook_timeout: self.on_change_timeout,
+            ..Default::default()
+        }
+    }
 }
 
 /// Launches the application based on parsed command-line parameters.
@@ -27,44 +127,420 @@ struct Config {
 /// ### Returns
 /// - `Result<(), &'static str>`: Ok if launch is successful, Err otherwise.
 ///
+/// The keytabs are selected before the async runtime is built, the krb5 library reading
+/// them from the environment.
+///
 /// ### Example
 /// ```rust
 /// let params = vec!["serve".to_string(), "--url".to_string(), "http://localhost:8000".to_string()];
-/// launch_based_on_params(params).await.unwrap();
+/// launch_based_on_params(params).unwrap();
 /// ```
-pub async fn launch_based_on_params(params: Vec<String>) -> Result<(), &'static str> {
+pub fn launch_based_on_params(params: Vec<String>) -> Result<(), &'static str> {
     let config: Config = parse_params(params)?;
 
+    // SAFETY: the runtime is built below, no other thread of the process runs yet.
+    unsafe { select_keytabs(&config)? };
+    rocket::execute(launch(config))
+}
+
+/// Selects the keytabs of the configuration through the environment of the process.
+///
+/// ### Parameters
+/// - `config`: The parsed configuration.
+///
+/// ### Returns
+/// - `Result<(), &'static str>`: Ok if the keytabs can be used, Err otherwise.
+///
+/// ### Safety
+/// Writes the environment, see [`launch_based_on_params`].
+unsafe fn select_keytabs(config: &Config) -> Result<(), &'static str> {
+    if config.action == "serve"
+        && config.auth.contains(&AuthMethod::Kerberos)
+        && let Some(keytab) = &config.keytab
+    {
+        // SAFETY: forwarded to the caller.
+        unsafe { use_server_keytab(keytab) }.map_err(|e| {
+            eprintln!("{}", e);
+            "Cannot use keytab"
+        })?;
+    }
+    // The server pulls its peers with the peer keytab, the server keytab by default.
+    let client_keytab = match config.action.as_str() {
+        "serve" if !config.cluster.peers.is_empty() => {
+            config.peer_keytab.as_ref().or(config.keytab.as_ref())
+        }
+        "serve" => None,
+        _ => config.client_keytab.as_ref(),
+    };
+    if let Some(keytab) = client_keytab {
+        // SAFETY: forwarded to the caller.
+        unsafe { librping::use_client_keytab(keytab) }.map_err(|e| {
+            eprintln!("{}", e);
+            "Cannot use client keytab"
+        })?;
+    }
+    Ok(())
+}
+
+/// Runs the action of the configuration.
+///
+/// ### Parameters
+/// - `config`: The parsed configuration, its keytabs selected.
+///
+/// ### Returns
+/// - `Result<(), &'static str>`: Ok if the action succeeded, Err otherwise.
+async fn launch(config: Config) -> Result<(), &'static str> {
     match config.action.as_str() {
         "serve" => {
-            let auth_fairing = KrbFairing {};
-            let creds: KrbServerCreds = KrbServerCreds::new(config.principal)
-                .ok_or_else(|| "Cannot instantiate kerberos creds")?;
-            println!("{}", creds.principal.clone());
-
-            let _rocket = rocket::build()
-                .mount("/add", routes![routes::post_address])
-                .mount("/get", routes![routes::get_list])
-                .manage(HostMap::new(HashMap::new()))
-                .manage(Mutex::new(creds))
-                .attach(auth_fairing)
+            init_logging(config.log_level, config.log_format)?;
+            let audit = match &config.audit_log {
+                Some(path) => AuditLog::open(path).map_err(|e| {
+                    log::error!("{}", e);
+                    "Cannot open audit log"
+                })?,
+                None => AuditLog::default(),
+            };
+            let figment = server_figment(&config);
+            let options = ServerOptions {
+                address_policy: config.address_policy,
+                expire_after: config.expire_after,
+                webhooks: config.webhooks.clone(),
+                hooks: config.hooks.clone(),
+                metrics: metrics_options(&config),
+                audit,
+                limits: config.limits.clone(),
+                cluster: cluster_options(&config)?,
+                admins: config.admins.clone(),
+            };
+            let auth = authenticators(config)?;
+            let methods: Vec<String> = auth.methods().iter().map(|m| m.to_string()).collect();
+            log::info!("Authentication methods: {}", methods.join(", "));
+
+            let _rocket = build_server(rocket::custom(figment), auth, options)
                 .launch()
                 .await
-                .or_else(|_e| Err("Could not start Rocket server"))?;
+                .map_err(|_e| "Could not start Rocket server")?;
             Ok(())
         }
         "list" => {
-            librping::list(config.url, config.realm).await;
+            librping::list(config.client_options()).await;
             Ok(())
         }
         "send" => {
-            librping::send(config.url, config.realm).await;
+            librping::send(config.client_options()).await;
+            Ok(())
+        }
+        "stale" => librping::stale(config.client_options(), config.older_than)
+            .await
+            .map_err(|e| {
+                println!("{}", e);
+                "Stale hosts found"
+            }),
+        "health" => librping::health(config.client_options())
+            .await
+            .map_err(|e| {
+                println!("{}", e);
+                "Server is not ready"
+            }),
+        "watch" => librping::watch(config.client_options()).await.map_err(|e| {
+            println!("{}", e);
+            "Could not watch events"
+        }),
+        "cluster" => match config.command.as_str() {
+            "status" => librping::cluster(config.client_options())
+                .await
+                .map_err(|e| {
+                    println!("{}", e);
+                    "Cluster is not in sync"
+                }),
+            _ => Err("Unknown cluster command, expected status"),
+        },
+        "admin" => match config.command.as_str() {
+            "export" => librping::admin_export(config.client_options(), config.snapshot.clone())
+                .await
+                .map_err(|e| {
+                    println!("{}", e);
+                    "Could not export snapshot"
+                }),
+            "import" => {
+                let snapshot = config.snapshot.clone().unwrap_or_default();
+                let mode = config.import_mode.to_string();
+                librping::admin_import(config.client_options(), snapshot, &mode)
+                    .await
+                    .map_err(|e| {
+                        println!("{}", e);
+                        "Could not import snapshot"
+                    })
+            }
+            _ => Err("Unknown admin command, expected export or import"),
+        },
+        "token" => {
+            let (token, entry) = generate_token(&config.identity);
+            println!("Token for {}: {}", config.identity, token);
+            println!("Add this line to the server token file:\n{}", entry);
             Ok(())
         }
+        "agent" => librping::agent(config.client_options(), config.interval)
+            .await
+            .map_err(|e| {
+                println!("{}", e);
+                "Could not start agent"
+            }),
         _ => Err("Unknown command"),
     }
 }
 
+/// Mounts the routes, states and fairings of the rping server on a Rocket instance.
+/// The Kerberos acceptor and fairing are only set up when Kerberos is enabled, the
+/// background tasks being started once the server has launched.
+///
+/// ### Parameters
+/// - `rocket`: The Rocket instance to build upon.
+/// - `auth`: The authentication methods enabled on the server.
+/// - `options`: The server options.
+///
+/// ### Returns
+/// - `Rocket<Build>`: The Rocket instance ready to be launched.
+///
+/// ### Example
+/// ```rust
+/// let acceptor: SharedAcceptor = Arc::new(FakeAcceptor::default());
+/// let rocket = build_server(rocket::build(), Authenticators::kerberos(acceptor), ServerOptions::default());
+/// ```
+pub fn build_server(
+    rocket: Rocket<Build>,
+    auth: Authenticators,
+    options: ServerOptions,
+) -> Rocket<Build> {
+    let rocket = match auth.acceptor.clone() {
+        Some(acceptor) => rocket.manage(acceptor).attach(KrbFairing {}),
+        None => rocket,
+    };
+
+    rocket
+        .mount("/", routes![routes::healthz, routes::readyz])
+        .mount(
+            routes::API_V1.to_string() + "/hosts",
+            routes![
+                routes::get_list,
+                routes::post_address,
+                routes::get_host,
+                routes::delete_host
+            ],
+        )
+        .mount(
+            routes::API_V1.to_string() + "/events",
+            routes![routes::stream_events],
+        )
+        .mount(
+            routes::API_V1.to_string() + "/cluster",
+            routes![routes::get_cluster_status, routes::get_replicas],
+        )
+        .mount(
+            routes::API_V1.to_string() + "/admin",
+            routes![routes::export_snapshot, routes::import_snapshot],
+        )
+        .mount(routes::API_V1, routes![routes::get_openapi])
+        // Paths of the unversioned API, kept for the clients configured with them.
+        .mount("/add", routes![routes::post_legacy_address])
+        .mount("/get", routes![routes::get_legacy_list])
+        .mount("/nic", routes![routes::dyndns_update])
+        .mount("/metrics", routes![routes::get_metrics])
+        .register(
+            "/",
+            catchers![
+                routes::bad_request,
+                routes::unauthorized,
+                routes::forbidden,
+                routes::not_found,
+                routes::unprocessable_entity,
+                routes::too_many_requests,
+                routes::internal_error,
+                routes::service_unavailable
+            ],
+        )
+        .manage(host_map(&options.cluster))
+        .manage(Events::default())
+        .manage(Metrics::default())
+        .manage(RateLimits::new(&options.limits))
+        .manage(Cluster::new(options.cluster.clone()))
+        .manage(auth)
+        .manage(options)
+        .attach(RequestLogger)
+        .attach(RequestTimer)
+        .attach(AuditTrail)
+        .attach(AdHoc::on_ignite("OpenAPI document", |rocket| async {
+            OpenApiDocument::manage(rocket)
+        }))
+        .attach(AdHoc::on_liftoff("Background tasks", |rocket| {
+            Box::pin(async move { spawn_background_tasks(rocket) })
+        }))
+}
+
+/// Starts the background tasks enabled in the server options: the expiry of the hosts
+/// that stopped reporting, the webhook deliveries, the local hooks and the replication.
+///
+/// ### Parameters
+/// - `rocket`: The launched Rocket instance.
+fn spawn_background_tasks(rocket: &Rocket<Orbit>) {
+    let (Some(map), Some(events), Some(options)) = (
+        rocket.state::<HostMap>(),
+        rocket.state::<Events>(),
+        rocket.state::<ServerOptions>(),
+    ) else {
+        return;
+    };
+
+    if let Some(expire_after) = options.expire_after {
+        spawn_expiry(map.clone(), events.clone(), expire_after);
+    }
+
+    if !options.webhooks.urls.is_empty()
+        && let Err(e) = spawn_webhooks(options.webhooks.clone(), events.subscribe())
+    {
+        log::error!("{}", e);
+        rocket.shutdown().notify();
+    }
+
+    if options.hooks.command.is_some() {
+        spawn_hooks(options.hooks.clone(), events.subscribe());
+    }
+
+    if let Some(cluster) = rocket.state::<Cluster>()
+        && cluster.options.enabled()
+    {
+        spawn_replication(cluster.clone(), map.clone(), events.clone());
+    }
+}
+
+/// Creates the store of the host records, replicated when the server is part of a cluster.
+///
+/// ### Parameters
+/// - `cluster`: The cluster settings.
+///
+/// ### Returns
+/// - `HostMap`: The empty store.
+fn host_map(cluster: &ClusterOptions) -> HostMap {
+    if cluster.enabled() {
+        Arc::new(HostStore::replicated(&cluster.node))
+    } else {
+        HostMap::default()
+    }
+}
+
+/// Sets up the backends of the authentication methods enabled in the configuration.
+///
+/// ### Parameters
+/// - `config`: The parsed configuration.
+///
+/// ### Returns
+/// - `Result<Authenticators, &'static str>`: Ok with the authenticators, Err otherwise.
+fn authenticators(config: Config) -> Result<Authenticators, &'static str> {
+    let mut auth = Authenticators::default();
+
+    if config.auth.contains(&AuthMethod::Kerberos) {
+        let creds: KrbServerCreds =
+            KrbServerCreds::from_keytab(config.keytab, acceptor_principals(config.principals))
+                .map_err(|e| {
+                    log::error!("{}", e);
+                    "Cannot instantiate kerberos creds"
+                })?;
+        log::info!("{}", creds.describe());
+        let creds: SharedServerCreds = Arc::new(ReloadableCreds::new(creds));
+        spawn_reloader(creds.clone(), config.reload_interval);
+        auth.acceptor = Some(creds as SharedAcceptor);
+    }
+
+    if config.auth.contains(&AuthMethod::Token) {
+        let path = config.token_file.ok_or("No token file specified")?;
+        let tokens = TokenStore::load(&path).map_err(|e| {
+            log::error!("{}", e);
+            "Cannot load API tokens"
+        })?;
+        log::info!("{} API tokens loaded from {}", tokens.len(), path);
+        auth.tokens = Some(tokens);
+    }
+
+    auth.mtls = config.auth.contains(&AuthMethod::Mtls);
+
+    Ok(auth)
+}
+
+/// Builds the access control of the metrics endpoint, scrapers being allowed from the
+/// loopback addresses unless networks are given.
+///
+/// ### Parameters
+/// - `config`: The parsed configuration.
+///
+/// ### Returns
+/// - `MetricsOptions`: The access control of the metrics.
+fn metrics_options(config: &Config) -> MetricsOptions {
+    let mut options = MetricsOptions {
+        token_hash: config.metrics_token_hash.clone(),
+        ..Default::default()
+    };
+    if !config.metrics_allow.is_empty() {
+        options.allow = config.metrics_allow.clone();
+    }
+    options
+}
+
+/// Builds the replication settings of the server, named after its hostname unless a node
+/// name is given. Peers are pulled with the credentials of the peer keytab, the server
+/// keytab by default, selected before the runtime started.
+///
+/// ### Parameters
+/// - `config`: The parsed configuration.
+///
+/// ### Returns
+/// - `Result<ClusterOptions, &'static str>`: Ok with the settings, Err if the hostname cannot be read.
+fn cluster_options(config: &Config) -> Result<ClusterOptions, &'static str> {
+    let mut options = config.cluster.clone();
+    if options.node.is_empty() {
+        options.node = hostname::get()
+            .map_err(|_e| "Cannot read hostname, see --node")?
+            .to_string_lossy()
+            .to_string();
+    }
+    options.client.realm = config.realm.clone();
+    options.client.client_keytab = config.peer_keytab.clone().or(config.keytab.clone());
+
+    if !options.peers.is_empty()
+        && let Some(keytab) = &options.client.client_keytab
+    {
+        log::info!(
+            "Peers are pulled with tickets from {}, kept in a memory credential cache",
+            keytab
+        );
+    }
+    Ok(options)
+}
+
+/// Builds the Rocket configuration of the server: its port, plain log lines and, when
+/// certificates are given, TLS with optional verification of the client certificates.
+///
+/// ### Parameters
+/// - `config`: The parsed configuration.
+///
+/// ### Returns
+/// - `Figment`: The Rocket configuration provider.
+fn server_figment(config: &Config) -> rocket::figment::Figment {
+    let mut figment = rocket::Config::figment()
+        .merge(("port", config.port))
+        .merge(("cli_colors", false));
+
+    if let (Some(cert), Some(key)) = (&config.tls_cert, &config.tls_key) {
+        figment = figment.merge(("tls.certs", cert)).merge(("tls.key", key));
+    }
+    if let Some(ca) = &config.tls_ca {
+        figment = figment
+            .merge(("tls.mutual.ca_certs", ca))
+            .merge(("tls.mutual.mandatory", false));
+    }
+
+    figment
+}
+
 /// Parses command-line parameters into a Config struct.
 ///
 /// ### Parameters
@@ -75,10 +551,49 @@ pub async fn launch_based_on_params(params: Vec<String>) -> Result<(), &'static
 fn parse_params(params: Vec<String>) -> Result<Config, &'static str> {
     let mut config = Config {
         action: String::new(),
+        command: String::new(),
         url: String::new(),
+        failover_urls: Vec::new(),
+        srv: None,
+        strategy: Strategy::Ordered,
         port: 8000,
-        principal: String::new(),
+        auth: vec![AuthMethod::Kerberos],
+        principals: Vec::new(),
+        keytab: None,
+        reload_interval: Duration::from_secs(30),
+        token_file: None,
+        tls_cert: None,
+        tls_key: None,
+        tls_ca: None,
+        address_policy: AddressPolicy::default(),
+        expire_after: None,
+        webhooks: WebhookOptions::default(),
+        hooks: HookOptions::default(),
+        metrics_allow: Vec::new(),
+        metrics_token_hash: None,
+        log_level: LevelFilter::Info,
+        log_format: LogFormat::default(),
+        audit_log: None,
+        limits: LimitOptions::default(),
+        cluster: ClusterOptions::default(),
+        peer_keytab: None,
+        admins: Vec::new(),
         realm: String::new(),
+        service_principal: None,
+        client_keytab: None,
+        api_token: None,
+        addresses: Vec::new(),
+        metadata: false,
+        tags: BTreeMap::new(),
+        format: OutputFormat::Table,
+        retry: RetryPolicy::default(),
+        interval: Duration::from_secs(300),
+        on_change: None,
+        on_change_timeout: ClientOptions::default().hook_timeout,
+        older_than: Duration::from_secs(3600),
+        identity: String::new(),
+        snapshot: None,
+        import_mode: ImportMode::Merge,
     };
 
     let mut i = 0;
@@ -87,13 +602,12 @@ fn parse_params(params: Vec<String>) -> Result<Config, &'static str> {
         let mut param = params[i].to_owned();
 
         if param.starts_with("--") && i + 1 < len {
-            config = add_param(config, param.split_off(2), &params[i + 1])
-                .or_else(|_e| Err("Unknown option"))?;
-            i = i + 1;
+            config = add_param(config, param.split_off(2), &params[i + 1])?;
+            i += 1;
         } else {
             config = set_action(config, param)?;
         }
-        i = i + 1;
+        i += 1;
     }
 
     config = config_valid(config)?;
@@ -108,31 +622,303 @@ fn parse_params(params: Vec<String>) -> Result<Config, &'static str> {
 /// - `next_param`: The value for the parameter.
 ///
 /// ### Returns
-/// - `Result<Config, &str>`: Ok with updated Config, Err otherwise.
-fn add_param(mut config: Config, param: String, next_param: &str) -> Result<Config, &str> {
+/// - `Result<Config, &'static str>`: Ok with updated Config, Err otherwise.
+fn add_param(mut config: Config, param: String, next_param: &str) -> Result<Config, &'static str> {
     match param.as_str() {
         "url" => {
-            config.url = next_param.to_string();
+            if config.url.is_empty() {
+                config.url = next_param.to_string();
+            } else {
+                config.failover_urls.push(next_param.to_string());
+            }
+            Ok(config)
+        }
+        "srv" => {
+            config.srv = Some(next_param.to_string());
+            Ok(config)
+        }
+        "failover" => {
+            config.strategy = Strategy::parse(next_param)?;
             Ok(config)
         }
         "port" => {
             config.port = next_param
                 .parse::<u16>()
-                .or_else(|_e| Err("Port is not integer"))?;
+                .map_err(|_e| "Port is not integer")?;
+            Ok(config)
+        }
+        "auth" => {
+            config.auth = next_param
+                .split(',')
+                .map(|m| AuthMethod::parse(m.trim()))
+                .collect::<Result<Vec<AuthMethod>, &'static str>>()?;
+            Ok(config)
+        }
+        "token-file" => {
+            config.token_file = Some(next_param.to_string());
+            Ok(config)
+        }
+        "tls-cert" => {
+            config.tls_cert = Some(next_param.to_string());
+            Ok(config)
+        }
+        "tls-key" => {
+            config.tls_key = Some(next_param.to_string());
+            Ok(config)
+        }
+        "tls-ca" => {
+            config.tls_ca = Some(next_param.to_string());
+            Ok(config)
+        }
+        "api-token-file" => {
+            let token =
+                std::fs::read_to_string(next_param).map_err(|_e| "Cannot read API token file")?;
+            config.api_token = Some(token.trim().to_string());
+            Ok(config)
+        }
+        "expire-after" => {
+            config.expire_after = Some(parse_duration(next_param)?);
+            Ok(config)
+        }
+        "webhook" => {
+            config.webhooks.urls.push(next_param.to_string());
+            Ok(config)
+        }
+        "webhook-secret-file" => {
+            let secret = std::fs::read_to_string(next_param)
+                .map_err(|_e| "Cannot read webhook secret file")?;
+            config.webhooks.secret = Some(secret.trim().to_string());
+            Ok(config)
+        }
+        "webhook-queue" => {
+            config.webhooks.queue = Some(next_param.into());
+            Ok(config)
+        }
+        "hook" => {
+            config.hooks.command = Some(next_param.into());
+            Ok(config)
+        }
+        "hook-timeout" => {
+            config.hooks.timeout = parse_duration(next_param)?;
+            Ok(config)
+        }
+        "hook-concurrency" => {
+            config.hooks.concurrency = next_param
+                .parse::<usize>()
+                .map_err(|_e| "Hook concurrency is not integer")?
+                .max(1);
+            Ok(config)
+        }
+        "metrics-allow" => {
+            config.metrics_allow.push(Network::parse(next_param)?);
+            Ok(config)
+        }
+        "metrics-token-file" => {
+            let token = std::fs::read_to_string(next_param)
+                .map_err(|_e| "Cannot read metrics token file")?;
+            config.metrics_token_hash = Some(hash_token(token.trim()));
+            Ok(config)
+        }
+        "log-level" => {
+            config.log_level = next_param
+                .parse::<LevelFilter>()
+                .map_err(|_e| "Unknown log level")?;
+            Ok(config)
+        }
+        "log-format" => {
+            config.log_format = LogFormat::parse(next_param)?;
+            Ok(config)
+        }
+        "audit-log" => {
+            config.audit_log = Some(next_param.into());
+            Ok(config)
+        }
+        "rate-limit-ip" => {
+            config.limits.per_ip = Some(Rate::parse(next_param)?);
+            Ok(config)
+        }
+        "rate-limit-principal" => {
+            config.limits.per_principal = Some(Rate::parse(next_param)?);
+            Ok(config)
+        }
+        "max-negotiations" => {
+            config.limits.negotiations = Some(
+                next_param
+                    .parse::<usize>()
+                    .map_err(|_e| "Max negotiations is not integer")?
+                    .max(1),
+            );
+            Ok(config)
+        }
+        "node" => {
+            config.cluster.node = next_param.to_string();
+            Ok(config)
+        }
+        "peer" => {
+            config.cluster.peers.push(next_param.to_string());
+            Ok(config)
+        }
+        "peer-principal" => {
+            config.cluster.principals.push(next_param.to_string());
+            Ok(config)
+        }
+        "peer-keytab" => {
+            config.peer_keytab = Some(next_param.to_string());
+            Ok(config)
+        }
+        "sync-interval" => {
+            config.cluster.interval = parse_duration(next_param)?.max(Duration::from_secs(1));
+            Ok(config)
+        }
+        "admin-principal" => {
+            config.admins.push(next_param.to_string());
+            Ok(config)
+        }
+        "snapshot" => {
+            config.snapshot = Some(next_param.into());
+            Ok(config)
+        }
+        "mode" => {
+            config.import_mode = ImportMode::parse(next_param)?;
+            Ok(config)
+        }
+        "address-policy" => {
+            config.address_policy = AddressPolicy::parse(next_param)?;
+            Ok(config)
+        }
+        "address" => {
+            config.addresses.push(next_param.to_string());
+            Ok(config)
+        }
+        "metadata" => {
+            config.metadata = next_param
+                .parse::<bool>()
+                .map_err(|_e| "Metadata is not true or false")?;
+            Ok(config)
+        }
+        "format" => {
+            config.format = OutputFormat::parse(next_param)?;
+            Ok(config)
+        }
+        "older-than" => {
+            config.older_than = parse_duration(next_param)?;
+            Ok(config)
+        }
+        "tag" => {
+            let (key, value) = next_param.split_once('=').ok_or("Tag is not key=value")?;
+            config.tags.insert(key.to_string(), value.to_string());
+            Ok(config)
+        }
+        "identity" => {
+            config.identity = next_param.to_string();
             Ok(config)
         }
         "principal" => {
-            config.principal = next_param.to_string();
+            config.principals.push(next_param.to_string());
+            Ok(config)
+        }
+        "keytab" => {
+            config.keytab = Some(next_param.to_string());
             Ok(config)
         }
         "realm" => {
             config.realm = next_param.to_string().to_uppercase();
             Ok(config)
         }
+        "reload-interval" => {
+            config.reload_interval = parse_duration(next_param)?;
+            Ok(config)
+        }
+        "client-keytab" => {
+            config.client_keytab = Some(next_param.to_string());
+            Ok(config)
+        }
+        "interval" => {
+            config.interval = parse_duration(next_param)?;
+            Ok(config)
+        }
+        "on-change" => {
+            config.on_change = Some(next_param.into());
+            Ok(config)
+        }
+        "on-change-timeout" => {
+            config.on_change_timeout = parse_duration(next_param)?;
+            Ok(config)
+        }
+        "service-principal" => {
+            config.service_principal = Some(next_param.to_string());
+            Ok(config)
+        }
+        "retries" => {
+            config.retry.max_attempts = next_param
+                .parse::<usize>()
+                .map_err(|_e| "Retries is not integer")?
+                .max(1);
+            Ok(config)
+        }
+        "retry-delay" => {
+            config.retry.base_delay = parse_duration(next_param)?;
+            Ok(config)
+        }
+        "retry-max-delay" => {
+            config.retry.max_delay = parse_duration(next_param)?;
+            Ok(config)
+        }
+        "deadline" => {
+            config.retry.deadline = Some(parse_duration(next_param)?);
+            Ok(config)
+        }
         _ => Err("Unknown option"),
     }
 }
 
+/// Turns the `--principal` options into the list of acceptor principals,
+/// a `*` among them meaning any principal present in the keytab.
+///
+/// ### Parameters
+/// - `principals`: The principals given on the command line.
+///
+/// ### Returns
+/// - `Vec<String>`: The principals to accept, empty to accept any of them.
+fn acceptor_principals(principals: Vec<String>) -> Vec<String> {
+    if principals.iter().any(|p| p == "*") {
+        Vec::new()
+    } else {
+        principals
+    }
+}
+
+/// Parses a human readable duration such as `500ms`, `30s`, `5m` or `1h`.
+/// A value without unit is read as seconds.
+///
+/// ### Parameters
+/// - `value`: The duration as a string.
+///
+/// ### Returns
+/// - `Result<Duration, &'static str>`: Ok with the parsed duration, Err otherwise.
+fn parse_duration(value: &str) -> Result<Duration, &'static str> {
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    let amount = amount.parse::<u64>().map_err(|_e| "Invalid duration")?;
+
+    let seconds = |factor: u64| {
+        amount
+            .checked_mul(factor)
+            .map(Duration::from_secs)
+            .ok_or("Duration too large")
+    };
+    match unit {
+        "ms" => Ok(Duration::from_millis(amount)),
+        "" | "s" => Ok(Duration::from_secs(amount)),
+        "m" => seconds(60),
+        "h" => seconds(3600),
+        "d" => seconds(86400),
+        _ => Err("Invalid duration unit"),
+    }
+}
+
 /// Sets the action field in the Config struct.
 ///
 /// ### Parameters
@@ -142,11 +928,14 @@ fn add_param(mut config: Config, param: String, next_param: &str) -> Result<Conf
 /// ### Returns
 /// - `Result<Config, &'static str>`: Ok with updated Config, Err otherwise.
 fn set_action(mut config: Config, param: String) -> Result<Config, &'static str> {
-    if !config.action.is_empty() {
-        Err("Action set more than one time")
-    } else {
+    if config.action.is_empty() {
         config.action = param;
         Ok(config)
+    } else if matches!(config.action.as_str(), "cluster" | "admin") && config.command.is_empty() {
+        config.command = param;
+        Ok(config)
+    } else {
+        Err("Action set more than one time")
     }
 }
 
@@ -162,16 +951,41 @@ fn config_valid(config: Config) -> Result<Config, &'static str> {
         return Err("No action provided");
     }
 
-    if !config.action.contains("serve") && config.url.is_empty() {
-        return Err("No url specified");
+    if config.action == "token" {
+        if config.identity.is_empty() {
+            return Err("No identity specified");
+        }
+        return Ok(config);
     }
 
-    if !config.action.contains("serve") && config.realm.is_empty() {
-        return Err("No realm specified");
+    if config.action == "admin" && config.command == "import" && config.snapshot.is_none() {
+        return Err("No snapshot file specified, see --snapshot");
+    }
+
+    if !config.action.contains("serve") && config.url.is_empty() {
+        let failover = matches!(config.action.as_str(), "send" | "list" | "stale" | "agent");
+        if !failover || config.srv.is_none() {
+            return Err("No url specified");
+        }
     }
 
-    if config.action.contains("serve") && config.principal.is_empty() {
-        return Err("No kerberos principal specified");
+    if config.action.contains("serve") {
+        if config.auth.contains(&AuthMethod::Kerberos) && config.principals.is_empty() {
+            return Err("No kerberos principal specified");
+        }
+
+        if config.auth.contains(&AuthMethod::Mtls) && config.tls_ca.is_none() {
+            return Err("Client certificates need a CA, see --tls-ca");
+        }
+
+        if !config.cluster.principals.is_empty() && !config.auth.contains(&AuthMethod::Kerberos) {
+            return Err("Cluster peers authenticate with Kerberos, see --auth");
+        }
+
+        let tls = config.tls_cert.is_some() || config.tls_key.is_some() || config.tls_ca.is_some();
+        if tls && (config.tls_cert.is_none() || config.tls_key.is_none()) {
+            return Err("TLS needs both --tls-cert and --tls-key");
+        }
     }
 
     Ok(config)
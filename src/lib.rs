@@ -0,0 +1,21 @@
+#[macro_use]
+extern crate rocket;
+
+pub mod admin;
+pub mod audit;
+pub mod auth;
+pub mod cluster;
+pub mod events;
+pub mod expiry;
+pub mod hooks;
+pub mod launcher;
+pub mod limits;
+pub mod logging;
+pub mod metrics;
+pub mod routes;
+pub mod store;
+pub mod types;
+pub mod webhooks;
+
+#[cfg(test)]
+mod testing;
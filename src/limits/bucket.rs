@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Number of buckets above which the full ones are forgotten, a full bucket being
+/// the same as a missing one.
+const PRUNE_THRESHOLD: usize = 4096;
+
+/// Rate of requests allowed to a client, which may also spend the whole allowance at once.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rate {
+    /// Number of requests allowed per period.
+    pub count: u32,
+    /// Period over which the requests are counted.
+    pub period: Duration,
+}
+
+impl Rate {
+    /// Parses a rate as given on the command line.
+    ///
+    /// ### Parameters
+    /// - `value`: A number of requests per second, minute or hour, such as `60/m`.
+    ///
+    /// ### Returns
+    /// - `Result<Rate, &'static str>`: Ok with the rate, Err otherwise.
+    ///
+    /// ### Example
+    /// ```rust
+    /// let rate = Rate::parse("10/s").unwrap();
+    /// assert_eq!(rate.count, 10);
+    /// ```
+    pub fn parse(value: &str) -> Result<Rate, &'static str> {
+        let (count, unit) = value.split_once('/').ok_or("Rate is not count/unit")?;
+        let count = count
+            .parse::<u32>()
+            .ok()
+            .filter(|c| *c > 0)
+            .ok_or("Invalid rate count")?;
+        let period = match unit {
+            "s" => Duration::from_secs(1),
+            "m" => Duration::from_secs(60),
+            "h" => Duration::from_secs(3600),
+            _ => return Err("Invalid rate unit"),
+        };
+        Ok(Rate { count, period })
+    }
+
+    fn per_second(&self) -> f64 {
+        f64::from(self.count) / self.period.as_secs_f64()
+    }
+}
+
+/// Tokens left to a client and when they were last counted.
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token buckets limiting the requests of each client, identified by a key such as its
+/// address or principal.
+pub struct RateLimiter {
+    rate: Rate,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl RateLimiter {
+    /// Creates a limiter allowing each client the given rate.
+    ///
+    /// ### Parameters
+    /// - `rate`: The rate allowed to each client.
+    ///
+    /// ### Returns
+    /// - `RateLimiter`: The limiter, every bucket being full.
+    pub fn new(rate: Rate) -> RateLimiter {
+        RateLimiter {
+            rate,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes a token from the bucket of a client.
+    ///
+    /// ### Parameters
+    /// - `key`: The client, such as its address or principal.
+    ///
+    /// ### Returns
+    /// - `Result<(), Duration>`: Ok if the request is allowed, Err with the time until the
+    ///   next token otherwise.
+    ///
+    /// ### Example
+    /// ```rust
+    /// if let Err(retry_after) = limiter.check("203.0.113.7") {
+    ///     return Err(ApiError::new(Status::TooManyRequests, "Too many requests").retry_after(retry_after));
+    /// }
+    /// ```
+    pub fn check(&self, key: &str) -> Result<(), Duration> {
+        self.check_at(key, Instant::now())
+    }
+
+    fn check_at(&self, key: &str, now: Instant) -> Result<(), Duration> {
+        let capacity = f64::from(self.rate.count);
+        let per_second = self.rate.per_second();
+        let level = |bucket: &Bucket| {
+            let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+            (bucket.tokens + elapsed * per_second).min(capacity)
+        };
+
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if buckets.len() >= PRUNE_THRESHOLD {
+            buckets.retain(|_, bucket| level(bucket) < capacity);
+        }
+
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        bucket.tokens = level(bucket);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / per_second))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{Rate, RateLimiter};
+
+    #[test]
+    fn buckets_refill_over_time() {
+        let limiter = RateLimiter::new(Rate::parse("2/s").unwrap());
+        let start = Instant::now();
+
+        assert_eq!(limiter.check_at("a", start), Ok(()));
+        assert_eq!(limiter.check_at("a", start), Ok(()));
+        assert_eq!(
+            limiter.check_at("a", start),
+            Err(Duration::from_millis(500))
+        );
+        assert_eq!(limiter.check_at("b", start), Ok(()));
+        assert_eq!(
+            limiter.check_at("a", start + Duration::from_millis(500)),
+            Ok(())
+        );
+        assert_eq!(Rate::parse("0/s"), Err("Invalid rate count"));
+        assert_eq!(Rate::parse("5/d"), Err("Invalid rate unit"));
+    }
+}
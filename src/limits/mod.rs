@@ -0,0 +1,5 @@
+mod bucket;
+mod throttle;
+
+pub use bucket::*;
+pub use throttle::*;
@@ -0,0 +1,219 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use rocket::Request;
+use rocket::http::Status;
+use rocket::tokio::sync::{Semaphore, SemaphorePermit};
+
+use crate::limits::{Rate, RateLimiter};
+use crate::routes::{note_guard_error, note_retry_after};
+
+/// Time after which a client refused for lack of negotiation slots is asked to retry.
+const NEGOTIATION_RETRY: Duration = Duration::from_secs(1);
+
+/// Limits protecting the server from clients sending too many requests, none being
+/// enforced by default.
+#[derive(Clone, Debug, Default)]
+pub struct LimitOptions {
+    /// Rate of the requests each client address may send before authenticating.
+    pub per_ip: Option<Rate>,
+    /// Rate of the requests each authenticated principal may send.
+    pub per_principal: Option<Rate>,
+    /// Number of Kerberos negotiations allowed to run at the same time.
+    pub negotiations: Option<usize>,
+}
+
+/// Rate limiters and negotiation slots of the server, managed as Rocket state.
+pub struct RateLimits {
+    per_ip: Option<RateLimiter>,
+    per_principal: Option<RateLimiter>,
+    negotiations: Option<Arc<Semaphore>>,
+}
+
+impl RateLimits {
+    /// Creates the limiters enabled in the options.
+    ///
+    /// ### Parameters
+    /// - `options`: The limits to enforce.
+    ///
+    /// ### Returns
+    /// - `RateLimits`: The limiters.
+    pub fn new(options: &LimitOptions) -> RateLimits {
+        RateLimits {
+            per_ip: options.per_ip.map(RateLimiter::new),
+            per_principal: options.per_principal.map(RateLimiter::new),
+            negotiations: options
+                .negotiations
+                .map(|n| Arc::new(Semaphore::new(n.max(1)))),
+        }
+    }
+
+    /// Counts a request against the allowance of its client address.
+    ///
+    /// ### Parameters
+    /// - `request`: The incoming request.
+    ///
+    /// ### Returns
+    /// - `Result<(), (Status, String)>`: Ok if the request may go on, Err with the too many
+    ///   requests status otherwise, the catcher answering with `Retry-After`.
+    pub fn check_ip(&self, request: &Request<'_>) -> Result<(), (Status, String)> {
+        let Some(ip) = request.client_ip() else {
+            return Ok(());
+        };
+        self.ip_allowance(&ip.to_string())
+            .map_err(|retry_after| throttled(request, retry_after, "Too many requests"))
+    }
+
+    /// Counts a request against the allowance of the principal it authenticated as.
+    ///
+    /// ### Parameters
+    /// - `request`: The incoming request.
+    /// - `principal`: The authenticated principal.
+    ///
+    /// ### Returns
+    /// - `Result<(), (Status, String)>`: Ok if the request may go on, Err with the too many
+    ///   requests status otherwise, the catcher answering with `Retry-After`.
+    pub fn check_principal(
+        &self,
+        request: &Request<'_>,
+        principal: &str,
+    ) -> Result<(), (Status, String)> {
+        self.principal_allowance(principal).map_err(|retry_after| {
+            throttled(
+                request,
+                retry_after,
+                &format!("Too many requests from {}", principal),
+            )
+        })
+    }
+
+    /// Takes a token from the bucket of a client address, for the routes answering in their
+    /// own format.
+    ///
+    /// ### Parameters
+    /// - `ip`: The client address.
+    ///
+    /// ### Returns
+    /// - `Result<(), Duration>`: Ok if the request is allowed, Err with the time to wait otherwise.
+    ///
+    /// ### Example
+    /// ```rust
+    /// if let Err(retry_after) = limits.ip_allowance(&client_info.ip) {
+    ///     return DynDnsAnswer::Abuse("abuse", retry_after_header(retry_after));
+    /// }
+    /// ```
+    pub fn ip_allowance(&self, ip: &str) -> Result<(), Duration> {
+        self.per_ip
+            .as_ref()
+            .map_or(Ok(()), |limiter| limiter.check(ip))
+    }
+
+    /// Takes a token from the bucket of a principal, for the routes answering in their own
+    /// format.
+    ///
+    /// ### Parameters
+    /// - `principal`: The authenticated principal.
+    ///
+    /// ### Returns
+    /// - `Result<(), Duration>`: Ok if the request is allowed, Err with the time to wait otherwise.
+    pub fn principal_allowance(&self, principal: &str) -> Result<(), Duration> {
+        self.per_principal
+            .as_ref()
+            .map_or(Ok(()), |limiter| limiter.check(principal))
+    }
+
+    /// Takes one of the negotiation slots, if they are limited.
+    ///
+    /// ### Parameters
+    /// - `request`: The incoming request.
+    ///
+    /// ### Returns
+    /// - `Result<Option<SemaphorePermit>, (Status, String)>`: Ok with the slot to hold during
+    ///   the negotiation, Err with the service unavailable status if every slot is taken.
+    pub fn negotiation(
+        &self,
+        request: &Request<'_>,
+    ) -> Result<Option<SemaphorePermit<'_>>, (Status, String)> {
+        let Some(slots) = &self.negotiations else {
+            return Ok(None);
+        };
+        match slots.try_acquire() {
+            Ok(permit) => Ok(Some(permit)),
+            Err(_) => {
+                let message = "Too many negotiations in progress";
+                note_guard_error(request, message);
+                note_retry_after(request, NEGOTIATION_RETRY);
+                Err((Status::ServiceUnavailable, message.to_string()))
+            }
+        }
+    }
+}
+
+/// Notes why and for how long a request is throttled, for the catcher to answer.
+fn throttled(request: &Request<'_>, retry_after: Duration, message: &str) -> (Status, String) {
+    log::warn!(
+        target: "rping::limits",
+        request_id = crate::logging::RequestId::of(request);
+        "{}, retry in {}ms",
+        message,
+        retry_after.as_millis()
+    );
+    note_guard_error(request, message);
+    note_retry_after(request, retry_after);
+    (Status::TooManyRequests, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+
+    use rocket::http::Status;
+    use rocket_krb5::FakeAcceptor;
+
+    use super::LimitOptions;
+    use crate::auth::Authenticators;
+    use crate::limits::Rate;
+    use crate::routes::ErrorBody;
+    use crate::testing::{negotiate, server};
+    use crate::types::ServerOptions;
+
+    #[rocket::async_test]
+    async fn principals_over_their_rate_are_throttled() {
+        let client = server(
+            Authenticators::kerberos(Arc::new(FakeAcceptor::default())),
+            ServerOptions {
+                limits: LimitOptions {
+                    per_ip: Some(Rate::parse("2/m").unwrap()),
+                    per_principal: Some(Rate::parse("2/h").unwrap()),
+                    negotiations: Some(4),
+                },
+                ..Default::default()
+            },
+        )
+        .await;
+        let list = |principal: &str, remote: &str| {
+            client
+                .get("/api/v1/hosts")
+                .header(negotiate(principal))
+                .remote(remote.parse::<SocketAddr>().unwrap())
+                .dispatch()
+        };
+
+        let first = list("alice@EXAMPLE.COM", "10.0.0.1:4242").await;
+        let second = list("alice@EXAMPLE.COM", "10.0.0.2:4242").await;
+        let third = list("alice@EXAMPLE.COM", "10.0.0.3:4242").await;
+        let other = list("bob@EXAMPLE.COM", "10.0.0.1:4242").await;
+        let flood = list("carol@EXAMPLE.COM", "10.0.0.1:4242").await;
+
+        assert_eq!(first.status(), Status::Ok);
+        assert_eq!(second.status(), Status::Ok);
+        assert_eq!(third.status(), Status::TooManyRequests);
+        assert_eq!(third.headers().get_one("Retry-After"), Some("1800"));
+        let body: ErrorBody = third.into_json().await.unwrap();
+        assert_eq!(body.message, "Too many requests from alice@EXAMPLE.COM");
+        assert_eq!(other.status(), Status::Ok);
+        assert_eq!(flood.status(), Status::TooManyRequests);
+        assert_eq!(flood.headers().get_one("Retry-After"), Some("30"));
+    }
+}
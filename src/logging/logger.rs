@@ -0,0 +1,159 @@
+use std::io::Write;
+use std::time::SystemTime;
+
+use log::kv::{Key, Value, VisitSource};
+use log::{LevelFilter, Record};
+use rocket::serde::json::serde_json;
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+
+/// Format of the server log lines.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum LogFormat {
+    /// `timestamp LEVEL target: message key=value...`, for humans.
+    #[default]
+    Text,
+    /// One JSON object per line, for log shippers.
+    Json,
+}
+
+impl LogFormat {
+    /// Parses a log format name as given on the command line.
+    ///
+    /// ### Parameters
+    /// - `value`: Either `text` or `json`.
+    ///
+    /// ### Returns
+    /// - `Result<LogFormat, &'static str>`: Ok with the format, Err otherwise.
+    ///
+    /// ### Example
+    /// ```rust
+    /// assert_eq!(LogFormat::parse("json"), Ok(LogFormat::Json));
+    /// ```
+    pub fn parse(value: &str) -> Result<LogFormat, &'static str> {
+        match value {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err("Unknown log format"),
+        }
+    }
+}
+
+/// Collects the key-value fields of a record, such as the request identifier.
+struct Fields(Vec<(String, String)>);
+
+impl<'kvs> VisitSource<'kvs> for Fields {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), log::kv::Error> {
+        self.0.push((key.to_string(), value.to_string()));
+        Ok(())
+    }
+}
+
+/// Formats a record as one log line.
+fn format_record(format: LogFormat, record: &Record, fields: &[(String, String)]) -> String {
+    match format {
+        LogFormat::Text => {
+            let mut line = format!(
+                "{} {:<5} {}: {}",
+                timestamp(SystemTime::now()),
+                record.level(),
+                record.target(),
+                record.args()
+            );
+            for (key, value) in fields {
+                line.push_str(&format!(" {}={}", key, value));
+            }
+            line
+        }
+        LogFormat::Json => {
+            let mut line = serde_json::json!({
+                "timestamp": timestamp(SystemTime::now()),
+                "level": record.level().as_str(),
+                "target": record.target(),
+                "message": record.args().to_string(),
+            });
+            for (key, value) in fields {
+                line[key] = serde_json::Value::String(value.clone());
+            }
+            line.to_string()
+        }
+    }
+}
+
+/// Installs the server logger writing to the standard error, which Rocket then uses for
+/// its own messages as well.
+///
+/// ### Parameters
+/// - `level`: The most verbose level to write.
+/// - `format`: The format of the log lines.
+///
+/// ### Returns
+/// - `Result<(), &'static str>`: Ok if the logger is installed, Err if one already was.
+///
+/// ### Example
+/// ```rust
+/// init_logging(LevelFilter::Info, LogFormat::Json).unwrap();
+/// log::info!(request_id = "4f2a"; "Listing hosts");
+/// ```
+pub fn init_logging(level: LevelFilter, format: LogFormat) -> Result<(), &'static str> {
+    env_logger::Builder::new()
+        .filter_level(level)
+        .format(move |out, record| {
+            let mut fields = Fields(Vec::new());
+            let _ = record.key_values().visit(&mut fields);
+            writeln!(out, "{}", format_record(format, record, &fields.0))
+        })
+        .try_init()
+        .map_err(|_e| "A logger is already installed")
+}
+
+/// Formats a time as an RFC 3339 UTC timestamp, down to the millisecond.
+///
+/// ### Parameters
+/// - `time`: The time to format.
+///
+/// ### Returns
+/// - `String`: The timestamp, such as `2024-05-01T12:00:00.123Z`.
+pub fn timestamp(time: SystemTime) -> String {
+    let time = OffsetDateTime::from(time);
+    time.replace_millisecond(time.millisecond())
+        .unwrap_or(time)
+        .format(&Rfc3339)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use log::{Level, Record};
+
+    use super::{LogFormat, format_record, timestamp};
+
+    #[test]
+    fn timestamps_are_rfc3339() {
+        assert_eq!(timestamp(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        assert_eq!(
+            timestamp(UNIX_EPOCH + Duration::from_micros(1709210096789999)),
+            "2024-02-29T12:34:56.789Z"
+        );
+    }
+
+    #[test]
+    fn json_lines_carry_the_fields() {
+        let fields = vec![("request_id".to_string(), "4f2a".to_string())];
+        let record = Record::builder()
+            .level(Level::Warn)
+            .target("rping::auth")
+            .args(format_args!("Unknown API token"))
+            .build();
+
+        let line = format_record(LogFormat::Json, &record, &fields);
+
+        assert!(
+            line.contains(r#""level":"WARN","message":"Unknown API token","request_id":"4f2a","target":"rping::auth","#),
+            "{}",
+            line
+        );
+    }
+}
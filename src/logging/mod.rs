@@ -0,0 +1,5 @@
+mod logger;
+mod requests;
+
+pub use logger::*;
+pub use requests::*;
@@ -0,0 +1,131 @@
+use std::convert::Infallible;
+use std::time::Instant;
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Header;
+use rocket::request::{FromRequest, Outcome};
+use rocket::{Data, Request, Response};
+
+/// Header carrying the identifier of a request, taken from the client when it sends one.
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// Identifier of a request, cached in the request and echoed in the response so that
+/// the log lines and audit entries of a request can be tied to what the client saw.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    /// Returns the identifier of a request, assigning one on the first call.
+    ///
+    /// ### Parameters
+    /// - `request`: The request to identify.
+    ///
+    /// ### Returns
+    /// - `&str`: The identifier of the request.
+    ///
+    /// ### Example
+    /// ```rust
+    /// log::warn!(request_id = RequestId::of(request); "Unknown API token");
+    /// ```
+    pub fn of<'r>(request: &'r Request<'_>) -> &'r str {
+        &request
+            .local_cache(|| {
+                let incoming = request
+                    .headers()
+                    .get_one(REQUEST_ID_HEADER)
+                    .filter(|id| valid_request_id(id));
+                RequestId(incoming.map_or_else(new_request_id, String::from))
+            })
+            .0
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RequestId {
+    type Error = Infallible;
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(RequestId(RequestId::of(request).to_string()))
+    }
+}
+
+/// Checks that an identifier sent by a client is safe to log and echo.
+fn valid_request_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= 64 && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+/// Generates a random request identifier of 16 hexadecimal digits.
+fn new_request_id() -> String {
+    format!("{:016x}", rand::random::<u64>())
+}
+
+/// Fairing identifying each request and logging one access line per response.
+pub struct RequestLogger;
+
+/// Time a request was received, cached in the request.
+struct Started(Option<Instant>);
+
+#[rocket::async_trait]
+impl Fairing for RequestLogger {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request logger",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        RequestId::of(request);
+        request.local_cache(|| Started(Some(Instant::now())));
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let request_id = RequestId::of(request);
+        response.set_header(Header::new(REQUEST_ID_HEADER, request_id.to_string()));
+
+        let elapsed = match request.local_cache(|| Started(None)) {
+            Started(Some(started)) => started.elapsed().as_millis(),
+            Started(None) => 0,
+        };
+        let client_ip = request
+            .client_ip()
+            .map_or(String::from("-"), |ip| ip.to_string());
+        log::info!(
+            target: "rping::access",
+            request_id = request_id,
+            client_ip = client_ip.as_str(),
+            elapsed_ms = elapsed;
+            "{} {} {}",
+            request.method(),
+            request.uri().path(),
+            response.status().code
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rocket::http::Header;
+
+    use crate::testing::client;
+
+    #[rocket::async_test]
+    async fn responses_carry_the_request_id() {
+        let client = client(vec![]).await;
+
+        let echoed = client
+            .get("/healthz")
+            .header(Header::new("X-Request-Id", "lb-7f3a-01"))
+            .dispatch()
+            .await;
+        let replaced = client
+            .get("/healthz")
+            .header(Header::new("X-Request-Id", "bad id!"))
+            .dispatch()
+            .await;
+
+        assert_eq!(echoed.headers().get_one("X-Request-Id"), Some("lb-7f3a-01"));
+        let generated = replaced.headers().get_one("X-Request-Id").unwrap();
+        assert_eq!(generated.len(), 16);
+        assert!(generated.chars().all(|c| c.is_ascii_hexdigit()));
+    }
+}
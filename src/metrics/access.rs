@@ -0,0 +1,145 @@
+use std::net::IpAddr;
+
+use crate::auth::hash_token;
+use crate::routes::note_guard_error;
+use crate::types::ServerOptions;
+use rocket::Request;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome};
+
+/// Network given in CIDR notation, a bare address standing for itself.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Network {
+    address: IpAddr,
+    prefix: u8,
+}
+
+impl Network {
+    /// Parses a network as given on the command line.
+    ///
+    /// ### Parameters
+    /// - `value`: A network such as `10.0.0.0/8`, or an address.
+    ///
+    /// ### Returns
+    /// - `Result<Network, &'static str>`: Ok with the network, Err otherwise.
+    ///
+    /// ### Example
+    /// ```rust
+    /// let network = Network::parse("10.0.0.0/8").unwrap();
+    /// assert!(network.contains("10.1.2.3".parse().unwrap()));
+    /// ```
+    pub fn parse(value: &str) -> Result<Network, &'static str> {
+        let (address, prefix) = value.split_once('/').unwrap_or((value, ""));
+        let address = address
+            .parse::<IpAddr>()
+            .map_err(|_e| "Invalid network address")?;
+        let width = if address.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            "" => width,
+            p => p
+                .parse::<u8>()
+                .ok()
+                .filter(|p| *p <= width)
+                .ok_or("Invalid network prefix")?,
+        };
+        Ok(Network { address, prefix })
+    }
+
+    /// Tells whether an address belongs to the network.
+    ///
+    /// ### Parameters
+    /// - `ip`: The address to check.
+    ///
+    /// ### Returns
+    /// - `bool`: true if the address is in the network.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let (network, ip, width) = match (self.address, ip.to_canonical()) {
+            (IpAddr::V4(n), IpAddr::V4(i)) => (u32::from(n) as u128, u32::from(i) as u128, 32),
+            (IpAddr::V6(n), IpAddr::V6(i)) => (u128::from(n), u128::from(i), 128),
+            _ => return false,
+        };
+        let shift = width - u32::from(self.prefix);
+        shift == width || (network >> shift) == (ip >> shift)
+    }
+}
+
+/// Access control of the metrics endpoint, independent of the authentication of the
+/// other routes so that scrapers need no Kerberos credentials.
+#[derive(Clone, Debug)]
+pub struct MetricsOptions {
+    /// Networks allowed to scrape the metrics.
+    pub allow: Vec<Network>,
+    /// SHA-256 of the bearer token also allowing to scrape the metrics, if any.
+    pub token_hash: Option<String>,
+}
+
+impl Default for MetricsOptions {
+    fn default() -> Self {
+        MetricsOptions {
+            allow: vec![
+                Network {
+                    address: IpAddr::from([127, 0, 0, 0]),
+                    prefix: 8,
+                },
+                Network {
+                    address: IpAddr::from([0u16, 0, 0, 0, 0, 0, 0, 1]),
+                    prefix: 128,
+                },
+            ],
+            token_hash: None,
+        }
+    }
+}
+
+/// Guard letting a scraper through when it comes from an allowed network or presents
+/// the metrics bearer token.
+pub struct MetricsScraper;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for MetricsScraper {
+    type Error = &'static str;
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let Some(options) = request.rocket().state::<ServerOptions>() else {
+            return Outcome::Error((Status::InternalServerError, "No server options set."));
+        };
+        let options = &options.metrics;
+
+        let allowed_network = request
+            .client_ip()
+            .is_some_and(|ip| options.allow.iter().any(|n| n.contains(ip)));
+        let allowed_token = options.token_hash.as_ref().is_some_and(|hash| {
+            request
+                .headers()
+                .get_one("Authorization")
+                .and_then(|h| h.strip_prefix("Bearer "))
+                .is_some_and(|token| hash_token(token.trim()) == *hash)
+        });
+
+        if allowed_network || allowed_token {
+            Outcome::Success(MetricsScraper)
+        } else {
+            note_guard_error(request, "Metrics access denied");
+            Outcome::Error((Status::Forbidden, "Metrics access denied"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Network;
+
+    #[test]
+    fn networks_contain_their_addresses() {
+        let lan = Network::parse("192.168.0.0/16").unwrap();
+        let host = Network::parse("2001:db8::1").unwrap();
+        let any = Network::parse("0.0.0.0/0").unwrap();
+
+        assert!(lan.contains("192.168.1.5".parse().unwrap()));
+        assert!(lan.contains("::ffff:192.168.1.5".parse().unwrap()));
+        assert!(!lan.contains("192.169.0.1".parse().unwrap()));
+        assert!(host.contains("2001:db8::1".parse().unwrap()));
+        assert!(!host.contains("2001:db8::2".parse().unwrap()));
+        assert!(any.contains("203.0.113.7".parse().unwrap()));
+        assert_eq!(Network::parse("10.0.0.0/33"), Err("Invalid network prefix"));
+    }
+}
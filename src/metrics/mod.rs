@@ -0,0 +1,7 @@
+mod access;
+mod registry;
+mod timer;
+
+pub use access::*;
+pub use registry::*;
+pub use timer::*;
@@ -0,0 +1,306 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::store::HostStore;
+
+/// Upper bounds of the buckets of the latency histograms, in seconds.
+const LATENCY_BUCKETS: &[f64] = &[
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+/// Upper bounds of the buckets of the record age histogram, in seconds.
+const AGE_BUCKETS: &[f64] = &[60.0, 300.0, 900.0, 3600.0, 21600.0, 86400.0, 604800.0];
+
+/// Cumulative histogram in the Prometheus sense.
+#[derive(Clone, Debug)]
+struct Histogram {
+    bounds: &'static [f64],
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Histogram {
+        Histogram {
+            bounds,
+            counts: vec![0; bounds.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        for (bound, count) in self.bounds.iter().zip(self.counts.iter_mut()) {
+            if value <= *bound {
+                *count += 1;
+            }
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let separator = if labels.is_empty() { "" } else { "," };
+        for (bound, count) in self.bounds.iter().zip(&self.counts) {
+            let _ = writeln!(
+                out,
+                "{}_bucket{{{}{}le=\"{}\"}} {}",
+                name, labels, separator, bound, count
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{}_bucket{{{}{}le=\"+Inf\"}} {}",
+            name, labels, separator, self.count
+        );
+        let braces = |labels: &str| {
+            if labels.is_empty() {
+                String::new()
+            } else {
+                format!("{{{}}}", labels)
+            }
+        };
+        let _ = writeln!(out, "{}_sum{} {}", name, braces(labels), self.sum);
+        let _ = writeln!(out, "{}_count{} {}", name, braces(labels), self.count);
+    }
+}
+
+/// Counters and histograms of the server, managed as Rocket state and rendered in the
+/// Prometheus text format.
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Arc<Mutex<Registry>>,
+}
+
+struct Registry {
+    registrations: BTreeMap<String, u64>,
+    address_changes: u64,
+    spnego: BTreeMap<(&'static str, &'static str), u64>,
+    spnego_seconds: Histogram,
+    requests: BTreeMap<String, Histogram>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics {
+            registry: Arc::new(Mutex::new(Registry {
+                registrations: BTreeMap::new(),
+                address_changes: 0,
+                spnego: BTreeMap::new(),
+                spnego_seconds: Histogram::new(LATENCY_BUCKETS),
+                requests: BTreeMap::new(),
+            })),
+        }
+    }
+}
+
+impl Metrics {
+    fn registry(&self) -> std::sync::MutexGuard<'_, Registry> {
+        self.registry.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Counts a registration.
+    ///
+    /// ### Parameters
+    /// - `principal`: The principal that registered a host.
+    /// - `changed`: Whether the addresses of the host changed.
+    pub fn registered(&self, principal: &str, changed: bool) {
+        let mut registry = self.registry();
+        *registry
+            .registrations
+            .entry(principal.to_string())
+            .or_default() += 1;
+        if changed {
+            registry.address_changes += 1;
+        }
+    }
+
+    /// Counts the outcome of a SPNEGO negotiation step and records its duration.
+    ///
+    /// ### Parameters
+    /// - `result`: Either `success` or `failure`.
+    /// - `reason`: Why the step ended this way, such as `complete` or `rejected`.
+    /// - `elapsed`: The duration of the step.
+    pub fn spnego(&self, result: &'static str, reason: &'static str, elapsed: Duration) {
+        let mut registry = self.registry();
+        *registry.spnego.entry((result, reason)).or_default() += 1;
+        registry.spnego_seconds.observe(elapsed.as_secs_f64());
+    }
+
+    /// Records the time taken to answer a request.
+    ///
+    /// ### Parameters
+    /// - `route`: The URI of the route that answered, such as `/api/v1/hosts/<hostname>`.
+    /// - `elapsed`: The time taken by the request.
+    pub fn request(&self, route: &str, elapsed: Duration) {
+        self.registry()
+            .requests
+            .entry(route.to_string())
+            .or_insert_with(|| Histogram::new(LATENCY_BUCKETS))
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Renders the metrics in the Prometheus text format, along with the gauges computed
+    /// from the current host records.
+    ///
+    /// ### Parameters
+    /// - `hosts`: The current host records.
+    /// - `now`: The current Unix timestamp, to compute the record ages.
+    ///
+    /// ### Returns
+    /// - `String`: The exposition of every metric.
+    ///
+    /// ### Example
+    /// ```rust
+    /// let body = metrics.render(&map, unix_now());
+    /// ```
+    pub fn render(&self, hosts: &HostStore, now: u64) -> String {
+        let registry = self.registry();
+        let mut out = String::new();
+
+        header(&mut out, "rping_hosts", "gauge", "Registered hosts.");
+        let _ = writeln!(out, "rping_hosts {}", hosts.len());
+
+        header(
+            &mut out,
+            "rping_host_age_seconds",
+            "histogram",
+            "Time since the hosts last registered.",
+        );
+        let mut ages = Histogram::new(AGE_BUCKETS);
+        hosts.for_each(|_, record| ages.observe(now.saturating_sub(record.last_seen) as f64));
+        ages.render(&mut out, "rping_host_age_seconds", "");
+
+        header(
+            &mut out,
+            "rping_registrations_total",
+            "counter",
+            "Registrations by principal.",
+        );
+        for (principal, count) in &registry.registrations {
+            let _ = writeln!(
+                out,
+                "rping_registrations_total{{principal=\"{}\"}} {}",
+                escape(principal),
+                count
+            );
+        }
+
+        header(
+            &mut out,
+            "rping_address_changes_total",
+            "counter",
+            "Registrations that changed the addresses of a host.",
+        );
+        let _ = writeln!(
+            out,
+            "rping_address_changes_total {}",
+            registry.address_changes
+        );
+
+        header(
+            &mut out,
+            "rping_spnego_total",
+            "counter",
+            "SPNEGO negotiation steps by result and reason.",
+        );
+        for ((result, reason), count) in &registry.spnego {
+            let _ = writeln!(
+                out,
+                "rping_spnego_total{{result=\"{}\",reason=\"{}\"}} {}",
+                result, reason, count
+            );
+        }
+
+        header(
+            &mut out,
+            "rping_spnego_step_seconds",
+            "histogram",
+            "Duration of the SPNEGO negotiation steps.",
+        );
+        registry
+            .spnego_seconds
+            .render(&mut out, "rping_spnego_step_seconds", "");
+
+        header(
+            &mut out,
+            "rping_request_duration_seconds",
+            "histogram",
+            "Duration of the requests by route.",
+        );
+        for (route, histogram) in &registry.requests {
+            histogram.render(
+                &mut out,
+                "rping_request_duration_seconds",
+                &format!("route=\"{}\"", escape(route)),
+            );
+        }
+
+        out
+    }
+}
+
+/// Writes the help and type lines of a metric.
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// Escapes a label value.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::Metrics;
+    use crate::store::HostStore;
+    use crate::types::HostRecord;
+
+    #[test]
+    fn metrics_are_rendered_in_the_text_format() {
+        let metrics = Metrics::default();
+        metrics.registered("host/h1@EXAMPLE.COM", true);
+        metrics.registered("host/h1@EXAMPLE.COM", false);
+        metrics.registered("router\"1", true);
+        metrics.spnego("failure", "rejected", Duration::from_millis(3));
+        metrics.request("/add", Duration::from_millis(20));
+        let hosts = HostStore::from_iter([(
+            "h1".to_string(),
+            HostRecord {
+                last_seen: 1000,
+                ..Default::default()
+            },
+        )]);
+
+        let body = metrics.render(&hosts, 1120);
+
+        for line in [
+            "rping_hosts 1",
+            "rping_host_age_seconds_bucket{le=\"60\"} 0",
+            "rping_host_age_seconds_bucket{le=\"300\"} 1",
+            "rping_host_age_seconds_sum 120",
+            "rping_registrations_total{principal=\"host/h1@EXAMPLE.COM\"} 2",
+            "rping_registrations_total{principal=\"router\\\"1\"} 1",
+            "rping_address_changes_total 2",
+            "rping_spnego_total{result=\"failure\",reason=\"rejected\"} 1",
+            "rping_spnego_step_seconds_bucket{le=\"0.005\"} 1",
+            "rping_request_duration_seconds_bucket{route=\"/add\",le=\"0.025\"} 1",
+            "rping_request_duration_seconds_count{route=\"/add\"} 1",
+        ] {
+            assert!(
+                body.lines().any(|l| l == line),
+                "{} missing in\n{}",
+                line,
+                body
+            );
+        }
+    }
+}
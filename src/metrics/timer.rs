@@ -0,0 +1,40 @@
+use std::time::Instant;
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::{Data, Request, Response};
+
+use crate::metrics::Metrics;
+
+/// Fairing recording the time taken to answer each request in the [`Metrics`] state.
+pub struct RequestTimer;
+
+/// Time a request was received, cached in the request.
+struct Received(Option<Instant>);
+
+#[rocket::async_trait]
+impl Fairing for RequestTimer {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request timer",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        request.local_cache(|| Received(Some(Instant::now())));
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, _response: &mut Response<'r>) {
+        let Some(metrics) = request.rocket().state::<Metrics>() else {
+            return;
+        };
+        let Received(Some(received)) = request.local_cache(|| Received(None)) else {
+            return;
+        };
+
+        let route = request
+            .route()
+            .map_or(String::from("unmatched"), |r| r.uri.to_string());
+        metrics.request(&route, received.elapsed());
+    }
+}
@@ -0,0 +1,264 @@
+use rocket::State;
+use rocket::data::{Data, ToByteUnit};
+use rocket::http::Status;
+use rocket::serde::json::{Json, Value, serde_json};
+
+use crate::admin::{Admin, ImportMode, ImportReport, Snapshot};
+use crate::events::Events;
+use crate::routes::ApiError;
+use crate::types::HostMap;
+
+/// Largest snapshot accepted, well above the default limit of JSON bodies.
+const SNAPSHOT_LIMIT_MIB: u64 = 64;
+
+/// Handles GET requests exporting a snapshot of the host records.
+///
+/// ### Parameters
+/// - `map`: Shared state containing DNS records.
+/// - `events`: The event bus keeping the recent history.
+/// - `admin`: The authenticated admin.
+///
+/// ### Returns
+/// - `Json<Snapshot>`: Every record along with the recent history.
+///
+/// ### Example
+/// ```rust
+/// // GET /api/v1/admin/snapshot
+/// // -> {"version":1,"exported_at":1714564800,"hosts":{"h1":{...}},"history":[{"id":1,"event":"created",...}]}
+/// ```
+#[get("/snapshot")]
+pub async fn export_snapshot(
+    map: &State<HostMap>,
+    events: &State<Events>,
+    admin: Admin,
+) -> Json<Snapshot> {
+    let snapshot = Snapshot::export(map, events);
+    log::info!(
+        "Snapshot of {} hosts exported by {}",
+        snapshot.hosts.len(),
+        admin.0.principal
+    );
+    Json(snapshot)
+}
+
+/// Handles POST requests importing a snapshot, once validated against its schema.
+///
+/// ### Parameters
+/// - `mode`: Either `merge`, the default, or `replace`.
+/// - `data`: The snapshot as JSON.
+/// - `map`: Shared state for DNS records.
+/// - `events`: The event bus notified of the changes.
+/// - `admin`: The authenticated admin.
+///
+/// ### Returns
+/// - `Result<Json<ImportReport>, ApiError>`: The changes made, or why the snapshot was refused.
+///
+/// ### Example
+/// ```rust
+/// // POST /api/v1/admin/snapshot?mode=replace
+/// // {"version":1,"exported_at":1714564800,"hosts":{...},"history":[...]}
+/// // -> {"created":42,"updated":0,"kept":0,"removed":3,"history":120}
+/// ```
+#[post("/snapshot?<mode>", data = "<data>")]
+pub async fn import_snapshot(
+    mode: Option<&str>,
+    data: Data<'_>,
+    map: &State<HostMap>,
+    events: &State<Events>,
+    admin: Admin,
+) -> Result<Json<ImportReport>, ApiError> {
+    let mode = ImportMode::parse(mode.unwrap_or("merge"))
+        .map_err(|e| ApiError::new(Status::BadRequest, e))?;
+    let body = data
+        .open(SNAPSHOT_LIMIT_MIB.mebibytes())
+        .into_string()
+        .await
+        .map_err(|e| ApiError::new(Status::BadRequest, format!("Unreadable snapshot: {}", e)))?;
+    if !body.is_complete() {
+        return Err(ApiError::new(
+            Status::PayloadTooLarge,
+            format!("Snapshot larger than {} MiB", SNAPSHOT_LIMIT_MIB),
+        ));
+    }
+    let document: Value = serde_json::from_str(&body)
+        .map_err(|e| ApiError::new(Status::BadRequest, format!("Malformed snapshot: {}", e)))?;
+    let snapshot =
+        Snapshot::parse(&document).map_err(|e| ApiError::new(Status::UnprocessableEntity, e))?;
+
+    let report = snapshot.import(mode, map, events, &admin.0.principal);
+    log::info!(
+        "Snapshot imported by {}: {} created, {} updated, {} kept, {} removed",
+        admin.0.principal,
+        report.created,
+        report.updated,
+        report.kept,
+        report.removed
+    );
+    Ok(Json(report))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+
+    use rocket::http::{ContentType, Status};
+    use rocket::local::asynchronous::Client;
+    use rocket::serde::json::{Value, json};
+    use rocket_krb5::{FakeAcceptor, SharedAcceptor};
+
+    use crate::admin::ImportReport;
+    use crate::auth::Authenticators;
+    use crate::events::{EventKind, Events};
+    use crate::testing::{negotiate, server};
+    use crate::types::{HostMap, ServerOptions};
+
+    const ADMIN: &str = "admin@EXAMPLE.COM";
+
+    async fn admin_server() -> Client {
+        let acceptor: SharedAcceptor = Arc::new(FakeAcceptor::new(Vec::new()));
+        let options = ServerOptions {
+            admins: vec![ADMIN.to_string()],
+            ..Default::default()
+        };
+        server(Authenticators::kerberos(acceptor), options).await
+    }
+
+    async fn register(client: &Client, hostname: &str, ip: &str) {
+        client
+            .post("/api/v1/hosts")
+            .header(ContentType::JSON)
+            .header(negotiate(&format!("host/{}@EXAMPLE.COM", hostname)))
+            .remote(format!("{}:4242", ip).parse::<SocketAddr>().unwrap())
+            .body(json!({ "hostname": hostname }).to_string())
+            .dispatch()
+            .await;
+    }
+
+    async fn import(client: &Client, mode: &str, snapshot: &Value) -> (Status, Value) {
+        let response = client
+            .post(format!("/api/v1/admin/snapshot?mode={}", mode))
+            .header(ContentType::JSON)
+            .header(negotiate(ADMIN))
+            .body(snapshot.to_string())
+            .dispatch()
+            .await;
+        (response.status(), response.into_json().await.unwrap())
+    }
+
+    #[rocket::async_test]
+    async fn snapshots_are_restored_on_another_server() {
+        let source = admin_server().await;
+        register(&source, "h1", "10.0.0.1").await;
+        register(&source, "h2", "10.0.0.2").await;
+
+        let refused = source
+            .get("/api/v1/admin/snapshot")
+            .header(negotiate("alice@EXAMPLE.COM"))
+            .dispatch()
+            .await;
+        let response = source
+            .get("/api/v1/admin/snapshot")
+            .header(negotiate(ADMIN))
+            .dispatch()
+            .await;
+
+        assert_eq!(refused.status(), Status::Forbidden);
+        assert_eq!(response.status(), Status::Ok);
+        let snapshot: Value = response.into_json().await.unwrap();
+        assert_eq!(snapshot["version"], 1);
+        assert_eq!(
+            snapshot["hosts"]["h2"]["record"]["addresses"][0]["ip"],
+            "10.0.0.2"
+        );
+        assert_eq!(snapshot["hosts"]["h2"]["owner"], "host/h2@EXAMPLE.COM");
+        assert_eq!(snapshot["history"].as_array().unwrap().len(), 2);
+
+        let target = admin_server().await;
+        register(&target, "h3", "10.0.0.3").await;
+        let (status, report) = import(&target, "replace", &snapshot).await;
+
+        assert_eq!(status, Status::Ok);
+        let report: ImportReport = rocket::serde::json::serde_json::from_value(report).unwrap();
+        assert_eq!((report.created, report.removed, report.history), (2, 1, 0));
+        let map = target.rocket().state::<HostMap>().unwrap();
+        assert_eq!(map.get("h1").unwrap().ips(), "10.0.0.1");
+        assert!(map.get("h3").is_none());
+        let owner = target
+            .delete("/api/v1/hosts/h1")
+            .header(negotiate("host/h1@EXAMPLE.COM"))
+            .dispatch()
+            .await;
+        assert_eq!(owner.status(), Status::NoContent);
+
+        let fresh = admin_server().await;
+        let (_, report) = import(&fresh, "merge", &snapshot).await;
+        let history = fresh.rocket().state::<Events>().unwrap().history();
+
+        assert_eq!(report["history"], 2);
+        assert_eq!(history[0].hostname, "h1");
+        assert_eq!(history.last().unwrap().kind, EventKind::Created);
+        assert_eq!(history.last().unwrap().id, 4);
+    }
+
+    #[rocket::async_test]
+    async fn merges_keep_the_records_seen_last() {
+        let client = admin_server().await;
+        register(&client, "h1", "10.0.0.1").await;
+        let snapshot = json!({
+            "version": 1,
+            "exported_at": 0,
+            "hosts": {
+                "h1": {
+                    "record": {"addresses": [{"ip": "10.0.0.9", "source": "observed"}], "last_seen": 1},
+                    "owner": "alice@EXAMPLE.COM",
+                },
+                "h2": {
+                    "record": {"addresses": [{"ip": "10.0.0.2", "source": "observed"}], "last_seen": 1},
+                    "owner": null,
+                },
+            },
+            "history": [],
+        });
+
+        let (status, report) = import(&client, "merge", &snapshot).await;
+
+        assert_eq!(status, Status::Ok);
+        assert_eq!(report["created"], 1);
+        assert_eq!(report["kept"], 1);
+        let map = client.rocket().state::<HostMap>().unwrap();
+        assert_eq!(map.get("h1").unwrap().ips(), "10.0.0.1");
+        assert_eq!(map.get("h2").unwrap().ips(), "10.0.0.2");
+    }
+
+    #[rocket::async_test]
+    async fn invalid_snapshots_are_refused() {
+        let client = admin_server().await;
+        let valid = json!({"version": 1, "exported_at": 0, "hosts": {}, "history": []});
+        let mut newer = valid.clone();
+        newer["version"] = json!(2);
+        let mut unknown = valid.clone();
+        unknown["owner"] = json!("alice");
+        let mut bad_ip = valid.clone();
+        bad_ip["hosts"]["h1"] = json!({
+            "record": {"addresses": [{"ip": "not-an-ip", "source": "observed"}]},
+            "owner": null,
+        });
+
+        let (status, body) = import(&client, "merge", &newer).await;
+        assert_eq!(status, Status::UnprocessableEntity);
+        assert_eq!(
+            body["message"],
+            "Unsupported snapshot version 2, expected 1"
+        );
+        let (status, body) = import(&client, "merge", &unknown).await;
+        assert_eq!(status, Status::UnprocessableEntity);
+        assert!(body["message"].as_str().unwrap().contains("owner"));
+        let (status, body) = import(&client, "merge", &bad_ip).await;
+        assert_eq!(status, Status::UnprocessableEntity);
+        assert!(body["message"].as_str().unwrap().contains("not-an-ip"));
+        let (status, _) = import(&client, "overwrite", &valid).await;
+        assert_eq!(status, Status::BadRequest);
+        assert!(client.rocket().state::<HostMap>().unwrap().is_empty());
+    }
+}
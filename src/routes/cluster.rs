@@ -0,0 +1,59 @@
+use rocket::State;
+use rocket::http::Status;
+use rocket::serde::json::Json;
+
+use crate::auth::Identity;
+use crate::cluster::{Cluster, ClusterPeer, ClusterStatus};
+use crate::routes::ApiError;
+use crate::store::Replica;
+use crate::types::HostMap;
+
+/// Handles GET requests of the peers pulling the state of every host.
+///
+/// ### Parameters
+/// - `map`: Shared state containing DNS records.
+/// - `_peer`: The authenticated peer.
+///
+/// ### Returns
+/// - `Json<Vec<Replica>>`: The state of each host, removed ones included.
+///
+/// ### Example
+/// ```rust
+/// // GET /api/v1/cluster/replicas
+/// // -> [{"hostname":"h1","record":{...},"version":{"timestamp":1714564800123,"origin":"rping-a"},"principal":"host/h1@EXAMPLE.COM"}]
+/// ```
+#[get("/replicas")]
+pub async fn get_replicas(map: &State<HostMap>, _peer: ClusterPeer) -> Json<Vec<Replica>> {
+    Json(map.replicas())
+}
+
+/// Handles GET requests for the replication state of the server.
+///
+/// ### Parameters
+/// - `map`: Shared state containing DNS records.
+/// - `cluster`: The cluster state.
+/// - `_identity`: Authenticated identity of the client.
+///
+/// ### Returns
+/// - `Result<Json<ClusterStatus>, ApiError>`: The state of the pulls of each peer, not found
+///   if the server is not part of a cluster.
+///
+/// ### Example
+/// ```rust
+/// // GET /api/v1/cluster
+/// // -> {"node":"rping-a","hosts":42,"peers":[{"url":"https://rping-b.example.com","last_success":1714564800,"last_error":null,"applied":3}]}
+/// ```
+#[get("/")]
+pub async fn get_cluster_status(
+    map: &State<HostMap>,
+    cluster: &State<Cluster>,
+    _identity: Identity,
+) -> Result<Json<ClusterStatus>, ApiError> {
+    if !cluster.options.enabled() {
+        return Err(ApiError::new(
+            Status::NotFound,
+            "Replication is not enabled",
+        ));
+    }
+    Ok(Json(cluster.status(map.len())))
+}
@@ -0,0 +1,209 @@
+use rocket::State;
+use rocket::http::Status;
+
+use crate::auth::Identity;
+use crate::events::{EventKind, Events};
+use crate::routes::ApiError;
+use crate::types::{HostMap, ServerOptions};
+
+/// Handles DELETE requests removing a DNS record. Only the principal that registered the
+/// host first, authenticated with the same method, and the admins may remove it.
+///
+/// ### Parameters
+/// - `hostname`: The hostname to remove.
+/// - `map`: Shared state for DNS records.
+/// - `events`: The event bus notified of the removal.
+/// - `options`: Server options holding the admin principals.
+/// - `identity`: Authenticated identity of the client.
+///
+/// ### Returns
+/// - `Result<Status, ApiError>`: No content if the record was removed, forbidden if it
+///   belongs to another principal, not found otherwise.
+///
+/// ### Example
+/// ```rust
+/// // DELETE /api/v1/hosts/h1
+/// // -> 204 No Content
+/// ```
+#[delete("/<hostname>")]
+pub async fn delete_host(
+    hostname: &str,
+    map: &State<HostMap>,
+    events: &State<Events>,
+    options: &State<ServerOptions>,
+    identity: Identity,
+) -> Result<Status, ApiError> {
+    let principal = identity.principal.as_str();
+    let observe =
+        |record: &_| events.removed(EventKind::Deleted, hostname, record, Some(principal));
+    let removed = if identity.is_admin(&options.admins) {
+        map.remove(hostname, Some(principal), observe)
+    } else {
+        map.remove_owned(hostname, principal, &identity.owner(), observe)
+            .map_err(|e| ApiError::new(Status::Forbidden, e))?
+    };
+    match removed {
+        Some(_) => {
+            log::info!("{} deleted by {}", hostname, identity.principal);
+            Ok(Status::NoContent)
+        }
+        None => Err(ApiError::new(
+            Status::NotFound,
+            format!("Unknown host: {}", hostname),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+
+    use rocket::http::{ContentType, Status};
+    use rocket::local::asynchronous::Client;
+    use rocket_krb5::{FakeAcceptor, SharedAcceptor};
+
+    use crate::auth::{Authenticators, TokenStore, hash_token};
+    use crate::events::{EventKind, Events};
+    use crate::testing::{bearer, client, negotiate, server};
+    use crate::types::{HostMap, ServerOptions};
+
+    async fn register(client: &Client) {
+        client
+            .post("/add")
+            .header(ContentType::JSON)
+            .header(negotiate("host/h1@EXAMPLE.COM"))
+            .remote("10.0.0.1:4242".parse::<SocketAddr>().unwrap())
+            .body(r#"{"hostname":"h1"}"#)
+            .dispatch()
+            .await;
+    }
+
+    #[rocket::async_test]
+    async fn delete_host_removes_record() {
+        let client = client(vec![]).await;
+        let mut events = client.rocket().state::<Events>().unwrap().subscribe();
+        register(&client).await;
+
+        let deleted = client
+            .delete("/api/v1/hosts/h1")
+            .header(negotiate("host/h1@EXAMPLE.COM"))
+            .dispatch()
+            .await;
+        let missing = client
+            .delete("/api/v1/hosts/h1")
+            .header(negotiate("host/h1@EXAMPLE.COM"))
+            .dispatch()
+            .await;
+
+        assert_eq!(deleted.status(), Status::NoContent);
+        assert_eq!(missing.status(), Status::NotFound);
+        assert!(client.rocket().state::<HostMap>().unwrap().is_empty());
+        assert_eq!(events.try_recv().unwrap().kind, EventKind::Created);
+        let event = events.try_recv().unwrap();
+        assert_eq!(event.kind, EventKind::Deleted);
+        assert_eq!(event.principal.as_deref(), Some("host/h1@EXAMPLE.COM"));
+    }
+
+    #[rocket::async_test]
+    async fn delete_host_is_refused_to_other_principals() {
+        let client = client(vec![]).await;
+        register(&client).await;
+
+        let refused = client
+            .delete("/api/v1/hosts/h1")
+            .header(negotiate("alice@EXAMPLE.COM"))
+            .dispatch()
+            .await;
+
+        assert_eq!(refused.status(), Status::Forbidden);
+        let map = client.rocket().state::<HostMap>().unwrap();
+        assert_eq!(map.get("h1").map(|r| r.ips()), Some("10.0.0.1".to_string()));
+    }
+
+    #[rocket::async_test]
+    async fn re_registering_a_host_does_not_take_it_over() {
+        let client = client(vec![]).await;
+        register(&client).await;
+
+        let taken = client
+            .post("/api/v1/hosts")
+            .header(ContentType::JSON)
+            .header(negotiate("bob@EXAMPLE.COM"))
+            .remote("10.0.0.9:4242".parse::<SocketAddr>().unwrap())
+            .body(r#"{"hostname":"h1"}"#)
+            .dispatch()
+            .await;
+        let refused = client
+            .delete("/api/v1/hosts/h1")
+            .header(negotiate("bob@EXAMPLE.COM"))
+            .dispatch()
+            .await;
+        let deleted = client
+            .delete("/api/v1/hosts/h1")
+            .header(negotiate("host/h1@EXAMPLE.COM"))
+            .dispatch()
+            .await;
+
+        assert_eq!(taken.status(), Status::Ok);
+        assert_eq!(refused.status(), Status::Forbidden);
+        assert_eq!(deleted.status(), Status::NoContent);
+    }
+
+    #[rocket::async_test]
+    async fn delete_host_is_allowed_to_admins() {
+        let acceptor: SharedAcceptor = Arc::new(FakeAcceptor::new(Vec::new()));
+        let options = ServerOptions {
+            admins: vec!["admin@EXAMPLE.COM".to_string()],
+            ..Default::default()
+        };
+        let client = server(Authenticators::kerberos(acceptor), options).await;
+        register(&client).await;
+
+        let deleted = client
+            .delete("/api/v1/hosts/h1")
+            .header(negotiate("admin@EXAMPLE.COM"))
+            .dispatch()
+            .await;
+
+        assert_eq!(deleted.status(), Status::NoContent);
+    }
+
+    #[rocket::async_test]
+    async fn tokens_named_after_a_principal_do_not_own_its_hosts() {
+        let acceptor: SharedAcceptor = Arc::new(FakeAcceptor::new(Vec::new()));
+        let tokens = TokenStore::parse(&format!(
+            "host/h1@EXAMPLE.COM:{}\nadmin@EXAMPLE.COM:{} h2\n",
+            hash_token("s3cret"),
+            hash_token("0ther")
+        ))
+        .unwrap();
+        let options = ServerOptions {
+            admins: vec!["admin@EXAMPLE.COM".to_string()],
+            ..Default::default()
+        };
+        let auth = Authenticators {
+            acceptor: Some(acceptor),
+            tokens: Some(tokens),
+            ..Default::default()
+        };
+        let client = server(auth, options).await;
+        register(&client).await;
+
+        let owner = client
+            .delete("/api/v1/hosts/h1")
+            .header(bearer("s3cret"))
+            .dispatch()
+            .await;
+        let admin = client
+            .delete("/api/v1/hosts/h1")
+            .header(bearer("0ther"))
+            .dispatch()
+            .await;
+
+        assert_eq!(owner.status(), Status::Forbidden);
+        assert_eq!(admin.status(), Status::Forbidden);
+        let map = client.rocket().state::<HostMap>().unwrap();
+        assert_eq!(map.get("h1").map(|r| r.ips()), Some("10.0.0.1".to_string()));
+    }
+}
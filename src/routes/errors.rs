@@ -0,0 +1,279 @@
+use std::time::Duration;
+
+use rocket::http::{Header, Status};
+use rocket::response::{self, Responder};
+use rocket::serde::{Deserialize, Serialize, json::Json};
+use rocket::{Request, Response};
+
+use crate::logging::RequestId;
+
+#[doc = "Response struct for every error, whether answered by a route or a catcher."]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ErrorBody {
+    /// HTTP status code.
+    pub code: u16,
+    /// Human readable reason of the error.
+    pub message: String,
+    /// Identifier of the request, as found in the server and audit logs.
+    pub request_id: String,
+}
+
+/// Error answered with an [`ErrorBody`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ApiError {
+    pub status: Status,
+    pub message: String,
+    /// Time after which the client may try again, sent as `Retry-After`.
+    pub retry_after: Option<Duration>,
+}
+
+impl ApiError {
+    /// Creates an error.
+    ///
+    /// ### Parameters
+    /// - `status`: The status of the response.
+    /// - `message`: The reason of the error.
+    ///
+    /// ### Returns
+    /// - `ApiError`: The error.
+    ///
+    /// ### Example
+    /// ```rust
+    /// return Err(ApiError::new(Status::NotFound, format!("Unknown host: {}", hostname)));
+    /// ```
+    pub fn new(status: Status, message: impl Into<String>) -> ApiError {
+        ApiError {
+            status,
+            message: message.into(),
+            retry_after: None,
+        }
+    }
+
+    /// Asks the client to wait before trying again.
+    ///
+    /// ### Parameters
+    /// - `retry_after`: The time to wait, rounded up to the second.
+    ///
+    /// ### Returns
+    /// - `ApiError`: The error, answered with a `Retry-After` header.
+    ///
+    /// ### Example
+    /// ```rust
+    /// return Err(ApiError::new(Status::TooManyRequests, "Too many requests").retry_after(retry_after));
+    /// ```
+    pub fn retry_after(mut self, retry_after: Duration) -> ApiError {
+        self.retry_after = Some(retry_after);
+        self
+    }
+}
+
+/// Builds the `Retry-After` header asking to wait the given time, rounded up to the second.
+///
+/// ### Parameters
+/// - `retry_after`: The time to wait.
+///
+/// ### Returns
+/// - `Header<'static>`: The header, in seconds.
+pub fn retry_after_header(retry_after: Duration) -> Header<'static> {
+    let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    Header::new("Retry-After", seconds.max(1).to_string())
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let body = ErrorBody {
+            code: self.status.code,
+            message: self.message,
+            request_id: RequestId::of(request).to_string(),
+        };
+        let mut response = Response::build_from(Json(body).respond_to(request)?);
+        if let Some(retry_after) = self.retry_after {
+            response.header(retry_after_header(retry_after));
+        }
+        response.status(self.status).ok()
+    }
+}
+
+/// Message of the request guard that failed, cached in the request for the catchers.
+struct GuardError(Option<String>);
+
+/// Notes why a request guard failed, the catcher then answering with this message rather
+/// than the generic reason of the status.
+///
+/// ### Parameters
+/// - `request`: The request the guard failed on.
+/// - `message`: The reason of the failure.
+///
+/// ### Example
+/// ```rust
+/// note_guard_error(request, "Metrics access denied");
+/// return Outcome::Error((Status::Forbidden, "Metrics access denied"));
+/// ```
+pub fn note_guard_error(request: &Request<'_>, message: &str) {
+    request.local_cache(|| GuardError(Some(message.to_string())));
+}
+
+/// Time after which a throttled request may be retried, cached in the request for the catchers.
+struct RetryAfter(Option<Duration>);
+
+/// Notes how long the client of a throttled request should wait, the catcher then answering
+/// with a `Retry-After` header.
+///
+/// ### Parameters
+/// - `request`: The throttled request.
+/// - `retry_after`: The time to wait.
+///
+/// ### Example
+/// ```rust
+/// note_retry_after(request, Duration::from_secs(1));
+/// return Outcome::Error((Status::ServiceUnavailable, "Too many negotiations in progress"));
+/// ```
+pub fn note_retry_after(request: &Request<'_>, retry_after: Duration) {
+    request.local_cache(|| RetryAfter(Some(retry_after)));
+}
+
+/// Builds the answer of a catcher, with the message of the failed guard if any.
+fn caught(status: Status, request: &Request<'_>, reason: &str) -> ApiError {
+    let GuardError(message) = request.local_cache(|| GuardError(None));
+    let error = ApiError::new(status, message.as_deref().unwrap_or(reason));
+    match request.local_cache(|| RetryAfter(None)) {
+        RetryAfter(Some(retry_after)) => error.retry_after(*retry_after),
+        RetryAfter(None) => error,
+    }
+}
+
+/// Answers bad requests, such as a request whose client address is unknown.
+#[catch(400)]
+pub fn bad_request(request: &Request<'_>) -> ApiError {
+    caught(Status::BadRequest, request, "Malformed request")
+}
+
+/// Answers requests lacking credentials, next to the challenges of the enabled methods.
+#[catch(401)]
+pub fn unauthorized(request: &Request<'_>) -> ApiError {
+    caught(Status::Unauthorized, request, "Authentication required")
+}
+
+/// Answers requests whose credentials were refused.
+#[catch(403)]
+pub fn forbidden(request: &Request<'_>) -> ApiError {
+    caught(Status::Forbidden, request, "Access denied")
+}
+
+/// Answers requests for unknown routes.
+#[catch(404)]
+pub fn not_found(request: &Request<'_>) -> ApiError {
+    caught(Status::NotFound, request, "No such resource")
+}
+
+/// Answers requests whose body does not match the expected JSON.
+#[catch(422)]
+pub fn unprocessable_entity(request: &Request<'_>) -> ApiError {
+    caught(
+        Status::UnprocessableEntity,
+        request,
+        "The request body could not be parsed",
+    )
+}
+
+/// Answers requests from clients over their rate, telling them when to retry.
+#[catch(429)]
+pub fn too_many_requests(request: &Request<'_>) -> ApiError {
+    caught(Status::TooManyRequests, request, "Too many requests")
+}
+
+/// Answers requests that failed on the server side.
+#[catch(500)]
+pub fn internal_error(request: &Request<'_>) -> ApiError {
+    caught(
+        Status::InternalServerError,
+        request,
+        "Internal server error",
+    )
+}
+
+/// Answers requests the server is too busy to handle, such as when every negotiation slot
+/// is taken.
+#[catch(503)]
+pub fn service_unavailable(request: &Request<'_>) -> ApiError {
+    caught(Status::ServiceUnavailable, request, "Service unavailable")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use rocket::http::{ContentType, Header, Status};
+
+    use super::ErrorBody;
+    use crate::testing::{client, negotiate};
+
+    #[rocket::async_test]
+    async fn errors_are_answered_in_json() {
+        let client = client(vec!["alice@EXAMPLE.COM"]).await;
+
+        let unauthorized = client
+            .get("/get")
+            .header(Header::new("X-Request-Id", "req-1"))
+            .dispatch()
+            .await;
+        let forbidden = client
+            .get("/get")
+            .header(negotiate("mallory@EXAMPLE.COM"))
+            .dispatch()
+            .await;
+        let unprocessable = client
+            .post("/add")
+            .header(ContentType::JSON)
+            .header(negotiate("alice@EXAMPLE.COM"))
+            .remote("10.0.0.1:4242".parse::<SocketAddr>().unwrap())
+            .body(r#"{"host":"h1"}"#)
+            .dispatch()
+            .await;
+        let unknown_address = client
+            .post("/add")
+            .header(ContentType::JSON)
+            .header(negotiate("alice@EXAMPLE.COM"))
+            .body(r#"{"hostname":"h1"}"#)
+            .dispatch()
+            .await;
+        let missing = client.get("/nowhere").dispatch().await;
+
+        assert_eq!(unauthorized.status(), Status::Unauthorized);
+        assert_eq!(
+            unauthorized
+                .headers()
+                .get_one("WWW-Authenticate")
+                .map(str::trim),
+            Some("Negotiate")
+        );
+        assert_eq!(unauthorized.content_type(), Some(ContentType::JSON));
+        assert_eq!(
+            unauthorized.into_json::<ErrorBody>().await.unwrap(),
+            ErrorBody {
+                code: 401,
+                message: "SPNEGO Authentication required.".to_string(),
+                request_id: "req-1".to_string(),
+            }
+        );
+        let forbidden = forbidden.into_json::<ErrorBody>().await.unwrap();
+        assert_eq!(
+            (forbidden.code, forbidden.message.as_str()),
+            (403, "Principal not allowed")
+        );
+        assert_eq!(forbidden.request_id.len(), 16);
+        let unprocessable = unprocessable.into_json::<ErrorBody>().await.unwrap();
+        assert_eq!(unprocessable.code, 422);
+        let unknown_address = unknown_address.into_json::<ErrorBody>().await.unwrap();
+        assert_eq!(
+            (unknown_address.code, unknown_address.message.as_str()),
+            (400, "Cannot determine the client address")
+        );
+        let missing = missing.into_json::<ErrorBody>().await.unwrap();
+        assert_eq!(
+            (missing.code, missing.message.as_str()),
+            (404, "No such resource")
+        );
+    }
+}
@@ -1,18 +1,20 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
+use crate::auth::Identity;
+use crate::routes::ApiError;
+use crate::store::HostStore;
+use crate::types::{HostMap, HostRecord};
+use rocket::http::Status;
 use rocket::{State, serde::json::Json};
-use rocket_krb5::KrbToken;
-
-use crate::types::HostMap;
 
 #[doc = r"Handles GET requests to retrieve all DNS records."]
 #[doc = r""]
 #[doc = r"### Parameters"]
 #[doc = r#"- `map`: Shared state containing DNS records."#]
-#[doc = r#"- `token`: Kerberos token for authentication."#]
+#[doc = r#"- `_identity`: Authenticated identity of the client."#]
 #[doc = r""]
 #[doc = r"### Returns"]
-#[doc = r#"- `Json<HashMap<String, String>>`: Map of hostnames to IPs."#]
+#[doc = r#"- `Json<&HostStore>`: Map of hostnames to their records, serialized without a copy."#]
 #[doc = r""]
 #[doc = r"### Example"]
 #[doc = r#""#]
@@ -22,16 +24,212 @@ use crate::types::HostMap;
 #[doc = r#""#]
 #[doc = r#"// Usage in Rocket route"#]
 #[doc = r#"#[get("/")] "#]
-#[doc = r#"async fn get_list(map: &State<HostMap>, token: KrbToken) -> Json<HashMap<String, String>> {"#]
+#[doc = r#"async fn get_list(map: &State<HostMap>, identity: Identity) -> Json<&HostStore> {"#]
 #[doc = r#"    // ... "#]
 #[doc = r#" } "#]
 #[doc = r#""#]
 #[doc = r#""#]
 #[get("/")]
-pub async fn get_list(map: &State<HostMap>, token: KrbToken) -> Json<HashMap<String, String>> {
-    let hash_map = map.lock().await.clone();
+pub async fn get_list(map: &State<HostMap>, _identity: Identity) -> Json<&HostStore> {
+    Json(map.inner())
+}
+
+/// Handles GET requests to retrieve all DNS records on the unversioned `/get` path,
+/// answering the `{hostname: "ip"}` map its clients expect.
+///
+/// ### Parameters
+/// - `map`: Shared state containing DNS records.
+/// - `_identity`: Authenticated identity of the client.
+///
+/// ### Returns
+/// - `Json<BTreeMap<String, String>>`: Map of hostnames to their comma separated addresses.
+///
+/// ### Example
+/// ```rust
+/// // GET /get
+/// // -> {"h1":"10.0.0.1"}
+/// ```
+#[get("/")]
+pub async fn get_legacy_list(
+    map: &State<HostMap>,
+    _identity: Identity,
+) -> Json<BTreeMap<String, String>> {
+    let mut hosts = BTreeMap::new();
+    map.for_each(|hostname, record| {
+        hosts.insert(hostname.to_string(), record.ips());
+    });
+    Json(hosts)
+}
+
+/// Handles GET requests retrieving the DNS record of one host.
+///
+/// ### Parameters
+/// - `hostname`: The hostname to look up.
+/// - `map`: Shared state containing DNS records.
+/// - `_identity`: Authenticated identity of the client.
+///
+/// ### Returns
+/// - `Result<Json<HostRecord>, ApiError>`: The record of the host, not found otherwise.
+///
+/// ### Example
+/// ```rust
+/// // GET /api/v1/hosts/h1
+/// // -> {"addresses":[{"ip":"10.0.0.1","source":"observed"}],"metadata":{...},"last_seen":1714564800}
+/// ```
+#[get("/<hostname>")]
+pub async fn get_host(
+    hostname: &str,
+    map: &State<HostMap>,
+    _identity: Identity,
+) -> Result<Json<HostRecord>, ApiError> {
+    match map.get(hostname) {
+        Some(record) => Ok(Json(record)),
+        None => Err(ApiError::new(
+            Status::NotFound,
+            format!("Unknown host: {}", hostname),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::net::SocketAddr;
+
+    use rocket::http::{ContentType, Status};
+
+    use crate::testing::{client, negotiate};
+    use crate::types::HostRecord;
+
+    #[rocket::async_test]
+    async fn get_list_returns_registered_hosts() {
+        let client = client(vec![]).await;
+        client
+            .post("/add")
+            .header(ContentType::JSON)
+            .header(negotiate("host/h1@EXAMPLE.COM"))
+            .remote("10.0.0.1:4242".parse::<SocketAddr>().unwrap())
+            .body(r#"{"hostname":"h1"}"#)
+            .dispatch()
+            .await;
+
+        let response = client
+            .get("/api/v1/hosts")
+            .header(negotiate("alice@EXAMPLE.COM"))
+            .dispatch()
+            .await;
+
+        assert_eq!(response.status(), Status::Ok);
+        let hosts: HashMap<String, HostRecord> = response.into_json().await.unwrap();
+        assert_eq!(
+            hosts.get("h1").map(|r| r.ips()),
+            Some("10.0.0.1".to_string())
+        );
+    }
+
+    #[rocket::async_test]
+    async fn get_list_returns_host_metadata() {
+        let client = client(vec![]).await;
+        client
+            .post("/add")
+            .header(ContentType::JSON)
+            .header(negotiate("host/h1@EXAMPLE.COM"))
+            .remote("10.0.0.1:4242".parse::<SocketAddr>().unwrap())
+            .body(r#"{"hostname":"h1","metadata":{"os":"Debian","uptime":42,"tags":{"site":"paris"}}}"#)
+            .dispatch()
+            .await;
+
+        let response = client
+            .get("/api/v1/hosts")
+            .header(negotiate("alice@EXAMPLE.COM"))
+            .dispatch()
+            .await;
+
+        let hosts: HashMap<String, HostRecord> = response.into_json().await.unwrap();
+        let metadata = &hosts["h1"].metadata;
+        assert_eq!(metadata.os.as_deref(), Some("Debian"));
+        assert_eq!(metadata.uptime, Some(42));
+        assert_eq!(metadata.kernel, None);
+        assert_eq!(metadata.tags.get("site").map(String::as_str), Some("paris"));
+    }
+
+    #[rocket::async_test]
+    async fn legacy_path_answers_addresses_only() {
+        let client = client(vec![]).await;
+        client
+            .post("/add")
+            .header(ContentType::JSON)
+            .header(negotiate("host/h1@EXAMPLE.COM"))
+            .remote("10.0.0.1:4242".parse::<SocketAddr>().unwrap())
+            .body(r#"{"hostname":"h1","metadata":{"os":"Debian"}}"#)
+            .dispatch()
+            .await;
+
+        let response = client
+            .get("/get")
+            .header(negotiate("alice@EXAMPLE.COM"))
+            .dispatch()
+            .await;
+
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.into_string().await.unwrap(),
+            r#"{"h1":"10.0.0.1"}"#
+        );
+    }
+
+    #[rocket::async_test]
+    async fn hosts_are_served_under_the_versioned_api() {
+        let client = client(vec![]).await;
+        let registered = client
+            .post("/api/v1/hosts")
+            .header(ContentType::JSON)
+            .header(negotiate("host/h1@EXAMPLE.COM"))
+            .remote("10.0.0.1:4242".parse::<SocketAddr>().unwrap())
+            .body(r#"{"hostname":"h1"}"#)
+            .dispatch()
+            .await;
+
+        let list = client
+            .get("/api/v1/hosts")
+            .header(negotiate("alice@EXAMPLE.COM"))
+            .dispatch()
+            .await;
+        let one = client
+            .get("/api/v1/hosts/h1")
+            .header(negotiate("alice@EXAMPLE.COM"))
+            .dispatch()
+            .await;
+        let unknown = client
+            .get("/api/v1/hosts/h2")
+            .header(negotiate("alice@EXAMPLE.COM"))
+            .dispatch()
+            .await;
+        let deleted = client
+            .delete("/api/v1/hosts/h1")
+            .header(negotiate("host/h1@EXAMPLE.COM"))
+            .dispatch()
+            .await;
+
+        assert_eq!(registered.status(), Status::Ok);
+        let hosts: HashMap<String, HostRecord> = list.into_json().await.unwrap();
+        assert_eq!(hosts.keys().collect::<Vec<_>>(), vec!["h1"]);
+        let record: HostRecord = one.into_json().await.unwrap();
+        assert_eq!(record.ips(), "10.0.0.1");
+        assert_eq!(unknown.status(), Status::NotFound);
+        assert_eq!(deleted.status(), Status::NoContent);
+    }
+
+    #[rocket::async_test]
+    async fn get_list_rejects_principal_not_allowed() {
+        let client = client(vec!["alice@EXAMPLE.COM"]).await;
 
-    println!("{}", token.principal);
+        let response = client
+            .get("/get")
+            .header(negotiate("mallory@EXAMPLE.COM"))
+            .dispatch()
+            .await;
 
-    Json::from(hash_map)
+        assert_eq!(response.status(), Status::Forbidden);
+    }
 }
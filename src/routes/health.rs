@@ -0,0 +1,146 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use rocket::State;
+use rocket::http::Status;
+use rocket::serde::{Deserialize, Serialize, json::Json};
+
+use crate::auth::Authenticators;
+use crate::types::HostMap;
+
+/// Longest time the readiness probe waits for the host records.
+const STORAGE_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[doc = "Response struct for health probes, with the outcome of each check."]
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct HealthReport {
+    /// `ok` if every check passed, `unavailable` otherwise.
+    status: String,
+    /// Outcome of each check by name, `ok` or the reason of the failure.
+    checks: BTreeMap<String, String>,
+}
+
+impl HealthReport {
+    fn new(checks: BTreeMap<String, String>) -> (Status, Json<HealthReport>) {
+        let healthy = checks.values().all(|c| c == "ok");
+        let status = if healthy { "ok" } else { "unavailable" };
+        let report = HealthReport {
+            status: status.to_string(),
+            checks,
+        };
+
+        if healthy {
+            (Status::Ok, Json(report))
+        } else {
+            (Status::ServiceUnavailable, Json(report))
+        }
+    }
+}
+
+/// Handles liveness probes, answering as long as the server serves requests.
+///
+/// ### Returns
+/// - `(Status, Json<HealthReport>)`: Always ok.
+///
+/// ### Example
+/// ```rust
+/// // GET /healthz
+/// // -> {"status":"ok","checks":{}}
+/// ```
+#[get("/healthz")]
+pub fn healthz() -> (Status, Json<HealthReport>) {
+    HealthReport::new(BTreeMap::new())
+}
+
+/// Handles readiness probes, checking that the Kerberos acceptor credentials are still
+/// valid and that the host records can be reached.
+///
+/// ### Parameters
+/// - `auth`: Authentication methods enabled on the server.
+/// - `map`: Shared state for DNS records.
+///
+/// ### Returns
+/// - `(Status, Json<HealthReport>)`: Ok if every check passed, service unavailable otherwise.
+///
+/// ### Example
+/// ```rust
+/// // GET /readyz
+/// // -> {"status":"ok","checks":{"kerberos":"ok","storage":"ok"}}
+/// ```
+#[get("/readyz")]
+pub async fn readyz(
+    auth: &State<Authenticators>,
+    map: &State<HostMap>,
+) -> (Status, Json<HealthReport>) {
+    let mut checks = BTreeMap::new();
+
+    if let Some(acceptor) = &auth.acceptor {
+        let check = acceptor.ready().await.err();
+        checks.insert(
+            String::from("kerberos"),
+            check.unwrap_or_else(|| String::from("ok")),
+        );
+    }
+
+    // Counting the records visits every shard, off the async workers in case one is stuck.
+    let map = map.inner().clone();
+    let count = rocket::tokio::task::spawn_blocking(move || map.len());
+    let storage = match rocket::tokio::time::timeout(STORAGE_TIMEOUT, count).await {
+        Ok(Ok(_)) => String::from("ok"),
+        Ok(Err(_)) | Err(_) => String::from("Host records are locked"),
+    };
+    checks.insert(String::from("storage"), storage);
+
+    HealthReport::new(checks)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rocket::http::Status;
+    use rocket_krb5::FakeAcceptor;
+
+    use crate::auth::Authenticators;
+    use crate::testing::{client, server};
+    use crate::types::ServerOptions;
+
+    #[rocket::async_test]
+    async fn probes_need_no_authentication() {
+        let client = client(vec![]).await;
+
+        let live = client.get("/healthz").dispatch().await;
+        let ready = client.get("/readyz").dispatch().await;
+
+        assert_eq!(live.status(), Status::Ok);
+        assert_eq!(ready.status(), Status::Ok);
+        assert_eq!(
+            ready.into_string().await.unwrap(),
+            r#"{"status":"ok","checks":{"kerberos":"ok","storage":"ok"}}"#
+        );
+    }
+
+    #[rocket::async_test]
+    async fn readiness_fails_with_unusable_credentials() {
+        let acceptor = FakeAcceptor {
+            unready: Some("Acceptor credentials expired".to_string()),
+            ..Default::default()
+        };
+        let client = server(
+            Authenticators::kerberos(Arc::new(acceptor)),
+            ServerOptions::default(),
+        )
+        .await;
+
+        let live = client.get("/healthz").dispatch().await;
+        let ready = client.get("/readyz").dispatch().await;
+
+        assert_eq!(live.status(), Status::Ok);
+        assert_eq!(ready.status(), Status::ServiceUnavailable);
+        assert_eq!(
+            ready.into_string().await.unwrap(),
+            r#"{"status":"unavailable","checks":{"kerberos":"Acceptor credentials expired","storage":"ok"}}"#
+        );
+    }
+}
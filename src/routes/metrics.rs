@@ -0,0 +1,104 @@
+use rocket::State;
+use rocket::response::content::RawText;
+
+use crate::metrics::{Metrics, MetricsScraper};
+use crate::types::{HostMap, unix_now};
+
+/// Handles GET requests scraping the server metrics in the Prometheus text format.
+///
+/// ### Parameters
+/// - `map`: Shared state for DNS records.
+/// - `metrics`: The metrics of the server.
+/// - `_scraper`: Guard checking the access to the metrics.
+///
+/// ### Returns
+/// - `RawText<String>`: The exposition of every metric.
+///
+/// ### Example
+/// ```rust
+/// // GET /metrics
+/// // -> # HELP rping_hosts Registered hosts.
+/// //    # TYPE rping_hosts gauge
+/// //    rping_hosts 42
+/// ```
+#[get("/")]
+pub async fn get_metrics(
+    map: &State<HostMap>,
+    metrics: &State<Metrics>,
+    _scraper: MetricsScraper,
+) -> RawText<String> {
+    RawText(metrics.render(map, unix_now()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+
+    use rocket::http::{ContentType, Status};
+    use rocket_krb5::FakeAcceptor;
+
+    use crate::auth::{Authenticators, hash_token};
+    use crate::metrics::{MetricsOptions, Network};
+    use crate::testing::{bearer, negotiate, server};
+    use crate::types::ServerOptions;
+
+    #[rocket::async_test]
+    async fn metrics_are_restricted_to_scrapers() {
+        let client = server(
+            Authenticators::kerberos(Arc::new(FakeAcceptor::default())),
+            ServerOptions {
+                metrics: MetricsOptions {
+                    allow: vec![Network::parse("10.1.0.0/16").unwrap()],
+                    token_hash: Some(hash_token("scrape")),
+                },
+                ..Default::default()
+            },
+        )
+        .await;
+        client
+            .post("/add")
+            .header(ContentType::JSON)
+            .header(negotiate("host/h1@EXAMPLE.COM"))
+            .remote("10.0.0.1:4242".parse::<SocketAddr>().unwrap())
+            .body(r#"{"hostname":"h1"}"#)
+            .dispatch()
+            .await;
+
+        let scrape = |remote: &'static str| {
+            client
+                .get("/metrics")
+                .remote(remote.parse::<SocketAddr>().unwrap())
+        };
+        let allowed = scrape("10.1.2.3:9100").dispatch().await;
+        let with_token = scrape("192.0.2.1:9100")
+            .header(bearer("scrape"))
+            .dispatch()
+            .await;
+        let denied = scrape("192.0.2.1:9100")
+            .header(negotiate("alice@EXAMPLE.COM"))
+            .dispatch()
+            .await;
+
+        assert_eq!(with_token.status(), Status::Ok);
+        assert_eq!(denied.status(), Status::Forbidden);
+        assert_eq!(allowed.status(), Status::Ok);
+        let body = allowed.into_string().await.unwrap();
+        assert!(body.contains("\nrping_hosts 1\n"), "{}", body);
+        assert!(
+            body.contains("rping_registrations_total{principal=\"host/h1@EXAMPLE.COM\"} 1"),
+            "{}",
+            body
+        );
+        assert!(
+            body.contains("rping_spnego_total{result=\"success\",reason=\"complete\"} 1"),
+            "{}",
+            body
+        );
+        assert!(
+            body.contains("rping_request_duration_seconds_count{route=\"/add\"} 1"),
+            "{}",
+            body
+        );
+    }
+}
@@ -1,5 +1,23 @@
+mod admin;
+mod cluster;
+mod delete;
+mod dyndns;
+mod errors;
 mod get;
+mod health;
+mod metrics;
+mod openapi;
 mod post;
+mod stream;
 
+pub use admin::*;
+pub use cluster::*;
+pub use delete::*;
+pub use dyndns::*;
+pub use errors::*;
 pub use get::*;
+pub use health::*;
+pub use metrics::*;
+pub use openapi::*;
 pub use post::*;
+pub use stream::*;
@@ -0,0 +1,672 @@
+use rocket::State;
+use rocket::serde::json::{Json, Value, json, serde_json::Map};
+use rocket::{Build, Rocket, Route};
+
+/// Base of the routes of the first version of the API.
+pub const API_V1: &str = "/api/v1";
+
+/// OpenAPI description of the versioned API, generated once the routes are mounted and
+/// managed as Rocket state.
+pub struct OpenApiDocument(pub Value);
+
+impl OpenApiDocument {
+    /// Generates the document from the routes mounted on a Rocket instance and manages it.
+    ///
+    /// ### Parameters
+    /// - `rocket`: The Rocket instance, every route being mounted.
+    ///
+    /// ### Returns
+    /// - `Rocket<Build>`: The Rocket instance managing the document.
+    ///
+    /// ### Example
+    /// ```rust
+    /// let rocket = rocket.attach(AdHoc::on_ignite("OpenAPI", |rocket| async { OpenApiDocument::manage(rocket) }));
+    /// ```
+    pub fn manage(rocket: Rocket<Build>) -> Rocket<Build> {
+        let document = openapi_document(rocket.routes());
+        rocket.manage(OpenApiDocument(document))
+    }
+}
+
+/// Handles GET requests for the OpenAPI description of the versioned API.
+///
+/// ### Parameters
+/// - `document`: The document generated from the mounted routes.
+///
+/// ### Returns
+/// - `Json<&Value>`: The OpenAPI 3.1 document.
+///
+/// ### Example
+/// ```rust
+/// // GET /api/v1/openapi.json
+/// // -> {"openapi":"3.1.0","info":{"title":"rping",...},"paths":{"/api/v1/hosts":{...}},...}
+/// ```
+#[get("/openapi.json")]
+pub fn get_openapi(document: &State<OpenApiDocument>) -> Json<&Value> {
+    Json(&document.0)
+}
+
+/// Builds the OpenAPI document describing the routes of the versioned API among the given
+/// ones, the compatibility aliases being left out.
+///
+/// ### Parameters
+/// - `routes`: The mounted routes.
+///
+/// ### Returns
+/// - `Value`: The OpenAPI 3.1 document.
+pub fn openapi_document<'a>(routes: impl Iterator<Item = &'a Route>) -> Value {
+    let mut paths = Map::new();
+    for route in routes {
+        let path = openapi_path(route);
+        let Some(operation) = route.name.as_deref().and_then(operation) else {
+            continue;
+        };
+        if !path.starts_with(API_V1) {
+            continue;
+        }
+        paths.entry(path).or_insert_with(|| json!({}))[route.method.as_str().to_lowercase()] =
+            operation;
+    }
+
+    json!({
+        "openapi": "3.1.0",
+        "info": {
+            "title": "rping",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Registry of the addresses of hosts, authenticated with Kerberos, \
+                API tokens or client certificates.",
+        },
+        "paths": paths,
+        "security": [{"negotiate": []}, {"bearer": []}, {"mutualTLS": []}],
+        "components": {
+            "securitySchemes": {
+                "negotiate": {"type": "http", "scheme": "negotiate"},
+                "bearer": {"type": "http", "scheme": "bearer"},
+                "mutualTLS": {"type": "mutualTLS"},
+            },
+            "schemas": schemas(),
+        },
+    })
+}
+
+/// Turns the URI of a route into an OpenAPI path, such as `/api/v1/hosts/{hostname}`.
+fn openapi_path(route: &Route) -> String {
+    let uri = route.uri.to_string();
+    let path = uri.split_once('?').map_or(uri.as_str(), |(path, _)| path);
+    let path = path
+        .split('/')
+        .map(|segment| match segment.strip_prefix('<') {
+            Some(name) => format!("{{{}}}", name.trim_end_matches('>').trim_end_matches("..")),
+            None => segment.to_string(),
+        })
+        .collect::<Vec<String>>()
+        .join("/");
+
+    match path.trim_end_matches('/') {
+        "" => String::from("/"),
+        trimmed => trimmed.to_string(),
+    }
+}
+
+/// Describes the operation of a route by the name of its handler, None for the routes that
+/// are not part of the API.
+fn operation(name: &str) -> Option<Value> {
+    let mut operation = match name {
+        "get_list" => json!({
+            "operationId": "listHosts",
+            "summary": "List the registered hosts.",
+            "responses": {
+                "200": json_response("Records by hostname.", json!({
+                    "type": "object",
+                    "additionalProperties": schema_ref("HostRecord"),
+                })),
+                "401": error_response("Missing credentials."),
+                "403": error_response("Refused credentials."),
+            },
+        }),
+        "post_address" => json!({
+            "operationId": "registerHost",
+            "summary": "Register a host with the address it connects from and the addresses it asserts.",
+            "requestBody": {
+                "required": true,
+                "content": {"application/json": {"schema": schema_ref("Registration")}},
+            },
+            "responses": {
+                "200": json_response("Saved addresses.", schema_ref("RegistrationResult")),
+                "400": error_response("Invalid asserted address."),
+                "401": error_response("Missing credentials."),
+                "403": error_response("Refused credentials, or API token not bound to the host."),
+                "422": error_response("Malformed registration."),
+            },
+        }),
+        "get_host" => json!({
+            "operationId": "getHost",
+            "summary": "Get the record of a host.",
+            "parameters": [hostname_parameter()],
+            "responses": {
+                "200": json_response("Record of the host.", schema_ref("HostRecord")),
+                "401": error_response("Missing credentials."),
+                "403": error_response("Refused credentials."),
+                "404": error_response("Unknown host."),
+            },
+        }),
+        "delete_host" => json!({
+            "operationId": "deleteHost",
+            "summary": "Remove the record of a host. Only the principal that registered it first \
+                and the admin principals are allowed.",
+            "parameters": [hostname_parameter()],
+            "responses": {
+                "204": {"description": "Record removed."},
+                "401": error_response("Missing credentials."),
+                "403": error_response("Refused credentials, or host registered by another principal."),
+                "404": error_response("Unknown host."),
+            },
+        }),
+        "stream_events" => json!({
+            "operationId": "streamEvents",
+            "summary": "Follow the changes made to host records as Server-Sent Events.",
+            "parameters": [{
+                "name": "Last-Event-ID",
+                "in": "header",
+                "description": "Identifier of the last event received, to resume after it.",
+                "schema": {"type": "integer", "minimum": 0},
+            }],
+            "responses": {
+                "200": {
+                    "description": "Events named after their kind, with the change as data. \
+                        A `reset` event means that changes were missed.",
+                    "content": {"text/event-stream": {"schema": schema_ref("HostEvent")}},
+                },
+                "401": error_response("Missing credentials."),
+                "403": error_response("Refused credentials."),
+            },
+        }),
+        "get_cluster_status" => json!({
+            "operationId": "getClusterStatus",
+            "summary": "Get the replication state of the server with each of its peers.",
+            "responses": {
+                "200": json_response("Replication state.", schema_ref("ClusterStatus")),
+                "401": error_response("Missing credentials."),
+                "403": error_response("Refused credentials."),
+                "404": error_response("Replication is not enabled."),
+            },
+        }),
+        "get_replicas" => json!({
+            "operationId": "getReplicas",
+            "summary": "Get the state of every host, removed ones included, for the peers to merge. \
+                Only the service principals of the peers are allowed.",
+            "responses": {
+                "200": json_response("State of each host.", json!({
+                    "type": "array",
+                    "items": schema_ref("Replica"),
+                })),
+                "401": error_response("Missing credentials."),
+                "403": error_response("Not a cluster peer."),
+            },
+        }),
+        "export_snapshot" => json!({
+            "operationId": "exportSnapshot",
+            "summary": "Export every host record along with the recent history. Only the admin \
+                principals are allowed.",
+            "responses": {
+                "200": json_response("Snapshot of the records.", schema_ref("Snapshot")),
+                "401": error_response("Missing credentials."),
+                "403": error_response("Not an admin."),
+            },
+        }),
+        "import_snapshot" => json!({
+            "operationId": "importSnapshot",
+            "summary": "Import a snapshot, merging its records with the current ones or replacing \
+                them. Only the admin principals are allowed.",
+            "parameters": [{
+                "name": "mode",
+                "in": "query",
+                "description": "Whether the records of the snapshot are merged, a host known on \
+                    both sides keeping the record seen last, or replace every record.",
+                "schema": {"type": "string", "enum": ["merge", "replace"], "default": "merge"},
+            }],
+            "requestBody": {
+                "required": true,
+                "content": {"application/json": {"schema": schema_ref("Snapshot")}},
+            },
+            "responses": {
+                "200": json_response("Changes made.", schema_ref("ImportReport")),
+                "400": error_response("Unknown import mode or malformed JSON."),
+                "401": error_response("Missing credentials."),
+                "403": error_response("Not an admin."),
+                "413": error_response("Snapshot too large."),
+                "422": error_response("Snapshot not matching its schema."),
+            },
+        }),
+        "get_openapi" => json!({
+            "operationId": "getOpenApi",
+            "summary": "Get this document.",
+            "security": [],
+            "responses": {"200": {"description": "The OpenAPI document."}},
+        }),
+        _ => return None,
+    };
+    if operation.get("security").is_none() {
+        // Every authenticated route may be throttled.
+        operation["responses"]["429"] = error_response("Too many requests.");
+    }
+    Some(operation)
+}
+
+fn schema_ref(name: &str) -> Value {
+    json!({"$ref": format!("#/components/schemas/{}", name)})
+}
+
+fn json_response(description: &str, schema: Value) -> Value {
+    json!({
+        "description": description,
+        "content": {"application/json": {"schema": schema}},
+    })
+}
+
+fn error_response(description: &str) -> Value {
+    json_response(description, schema_ref("Error"))
+}
+
+fn hostname_parameter() -> Value {
+    json!({
+        "name": "hostname",
+        "in": "path",
+        "required": true,
+        "schema": {"type": "string"},
+    })
+}
+
+/// JSON schema of a snapshot, the schemas it refers to being found under the same
+/// `components` as in the OpenAPI document.
+///
+/// ### Returns
+/// - `Value`: The schema, for the validation of the imported snapshots.
+pub fn snapshot_schema() -> Value {
+    json!({
+        "$ref": "#/components/schemas/Snapshot",
+        "components": {"schemas": schemas()},
+    })
+}
+
+/// Schemas of the bodies exchanged with the API.
+fn schemas() -> Value {
+    let nullable_string = json!({"type": ["string", "null"]});
+    json!({
+        "Address": {
+            "type": "object",
+            "required": ["ip", "source"],
+            "properties": {
+                "ip": {"type": "string"},
+                "source": {"type": "string", "enum": ["observed", "asserted"]},
+            },
+        },
+        "Interface": {
+            "type": "object",
+            "required": ["name", "ip"],
+            "properties": {
+                "name": {"type": "string"},
+                "ip": {"type": "string"},
+            },
+        },
+        "HostMetadata": {
+            "type": "object",
+            "properties": {
+                "os": nullable_string,
+                "kernel": nullable_string,
+                "version": nullable_string,
+                "uptime": {"type": ["integer", "null"], "minimum": 0},
+                "interfaces": {"type": "array", "items": schema_ref("Interface")},
+                "tags": {"type": "object", "additionalProperties": {"type": "string"}},
+            },
+        },
+        "HostRecord": {
+            "type": "object",
+            "required": ["addresses"],
+            "properties": {
+                "addresses": {"type": "array", "items": schema_ref("Address")},
+                "metadata": schema_ref("HostMetadata"),
+                "last_seen": {
+                    "type": "integer",
+                    "description": "Time of the last registration, in seconds since the Unix epoch.",
+                },
+            },
+        },
+        "Registration": {
+            "type": "object",
+            "required": ["hostname"],
+            "properties": {
+                "hostname": {"type": "string"},
+                "addresses": {
+                    "type": "array",
+                    "items": {"type": "string"},
+                    "description": "Addresses the host asserts, recorded according to the server address policy.",
+                },
+                "metadata": schema_ref("HostMetadata"),
+            },
+        },
+        "RegistrationResult": {
+            "type": "object",
+            "required": ["saved_ip", "previous_ip", "changed"],
+            "properties": {
+                "saved_ip": {"type": "string"},
+                "previous_ip": nullable_string,
+                "changed": {"type": "boolean"},
+            },
+        },
+        "HostEvent": {
+            "type": "object",
+            "required": ["id", "event", "hostname", "timestamp"],
+            "properties": {
+                "id": {"type": "integer", "minimum": 0},
+                "event": {"type": "string", "enum": ["created", "updated", "expired", "deleted"]},
+                "hostname": {"type": "string"},
+                "old_ip": nullable_string,
+                "new_ip": nullable_string,
+                "principal": nullable_string,
+                "timestamp": {"type": "integer", "minimum": 0},
+            },
+        },
+        "Replica": {
+            "type": "object",
+            "required": ["hostname", "record", "version", "principal"],
+            "properties": {
+                "hostname": {"type": "string"},
+                "record": {"oneOf": [schema_ref("HostRecord"), {"type": "null"}]},
+                "version": {
+                    "type": "object",
+                    "required": ["timestamp", "origin"],
+                    "properties": {
+                        "timestamp": {
+                            "type": "integer",
+                            "description": "Time of the change, in milliseconds since the Unix epoch.",
+                        },
+                        "origin": {"type": "string"},
+                    },
+                },
+                "principal": nullable_string,
+                "owner": nullable_string,
+            },
+        },
+        "ClusterStatus": {
+            "type": "object",
+            "required": ["node", "hosts", "peers"],
+            "properties": {
+                "node": {"type": "string"},
+                "hosts": {"type": "integer", "minimum": 0},
+                "peers": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "required": ["url", "applied"],
+                        "properties": {
+                            "url": {"type": "string"},
+                            "last_success": {"type": ["integer", "null"], "minimum": 0},
+                            "last_error": nullable_string,
+                            "applied": {"type": "integer", "minimum": 0},
+                        },
+                    },
+                },
+            },
+        },
+        "Snapshot": {
+            "type": "object",
+            "required": ["version", "exported_at", "hosts", "history"],
+            "additionalProperties": false,
+            "properties": {
+                "version": {"const": 1, "description": "Version of the snapshot format."},
+                "exported_at": {
+                    "type": "integer",
+                    "minimum": 0,
+                    "description": "Time of the export, in seconds since the Unix epoch.",
+                },
+                "hosts": {
+                    "type": "object",
+                    "propertyNames": {"minLength": 1},
+                    "additionalProperties": schema_ref("SnapshotHost"),
+                },
+                "history": {
+                    "type": "array",
+                    "items": schema_ref("HostEvent"),
+                    "description": "Recent changes made to the host records, oldest first.",
+                },
+            },
+        },
+        "SnapshotHost": {
+            "type": "object",
+            "required": ["record", "owner"],
+            "additionalProperties": false,
+            "properties": {
+                "record": schema_ref("HostRecord"),
+                "owner": {
+                    "type": ["string", "null"],
+                    "description": "Principal owning the host, null if it is left to the next registrant.",
+                },
+            },
+        },
+        "ImportReport": {
+            "type": "object",
+            "required": ["created", "updated", "kept", "removed", "history"],
+            "properties": {
+                "created": {"type": "integer", "minimum": 0},
+                "updated": {"type": "integer", "minimum": 0},
+                "kept": {"type": "integer", "minimum": 0},
+                "removed": {"type": "integer", "minimum": 0},
+                "history": {"type": "integer", "minimum": 0},
+            },
+        },
+        "Error": {
+            "type": "object",
+            "required": ["code", "message", "request_id"],
+            "properties": {
+                "code": {"type": "integer"},
+                "message": {"type": "string"},
+                "request_id": {"type": "string"},
+            },
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+
+    use rocket::http::{ContentType, Status};
+    use rocket::local::asynchronous::{Client, LocalResponse};
+    use rocket::serde::json::{Value, json};
+    use rocket_krb5::FakeAcceptor;
+
+    use super::{API_V1, openapi_path, schemas};
+    use crate::auth::Authenticators;
+    use crate::cluster::ClusterOptions;
+    use crate::testing::{client, negotiate, server};
+    use crate::types::ServerOptions;
+
+    /// Makes the object schemas refuse the properties they do not describe, so that a
+    /// field added to a response without its schema is noticed.
+    fn strict(schema: &mut Value) {
+        if let Some(object) = schema.as_object_mut() {
+            if object.contains_key("properties") && !object.contains_key("additionalProperties") {
+                object.insert("additionalProperties".to_string(), Value::Bool(false));
+            }
+            object.values_mut().for_each(strict);
+        } else if let Some(items) = schema.as_array_mut() {
+            items.iter_mut().for_each(strict);
+        }
+    }
+
+    /// Checks a response against the schema the document gives for its route and status.
+    async fn check(
+        document: &Value,
+        method: &str,
+        path: &str,
+        expected: Status,
+        response: LocalResponse<'_>,
+    ) -> Value {
+        assert_eq!(response.status(), expected, "{} {}", method, path);
+        let status = expected.code.to_string();
+        let schema = &document["paths"][path][method]["responses"][&status]["content"]["application/json"]
+            ["schema"];
+        assert!(
+            schema.is_object(),
+            "{} {} answering {} is not described",
+            method,
+            path,
+            status
+        );
+        let mut components = json!({"schemas": schemas()});
+        strict(&mut components);
+        let validator =
+            jsonschema::validator_for(&json!({"allOf": [schema], "components": components}))
+                .unwrap();
+
+        let body: Value = response.into_json().await.unwrap();
+        let errors: Vec<String> = validator
+            .iter_errors(&body)
+            .map(|e| e.to_string())
+            .collect();
+        assert!(
+            errors.is_empty(),
+            "{} {} answering {}: {:?}",
+            method,
+            path,
+            status,
+            errors
+        );
+        body
+    }
+
+    async fn document(client: &Client) -> Value {
+        let response = client.get("/api/v1/openapi.json").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        response.into_json().await.unwrap()
+    }
+
+    #[rocket::async_test]
+    async fn openapi_describes_every_versioned_route() {
+        let client = client(vec![]).await;
+
+        let document = document(&client).await;
+
+        let mut described = Vec::new();
+        for route in client.rocket().routes() {
+            let path = openapi_path(route);
+            if !path.starts_with(API_V1) {
+                continue;
+            }
+            let method = route.method.as_str().to_lowercase();
+            assert!(
+                document["paths"][&path][&method].is_object(),
+                "{} {} is not described",
+                method,
+                path
+            );
+            described.push((path, method));
+        }
+        for (path, operations) in document["paths"].as_object().unwrap() {
+            for method in operations.as_object().unwrap().keys() {
+                assert!(
+                    described.contains(&(path.clone(), method.clone())),
+                    "{} {} is described but not served",
+                    method,
+                    path
+                );
+            }
+        }
+        assert!(document["paths"].get("/get").is_none());
+        assert_eq!(
+            document["paths"]["/api/v1/hosts/{hostname}"]["get"]["responses"]["200"]["content"]["application/json"]
+                ["schema"]["$ref"],
+            "#/components/schemas/HostRecord"
+        );
+    }
+
+    #[rocket::async_test]
+    async fn responses_match_their_schemas() {
+        let client = server(
+            Authenticators::kerberos(Arc::new(FakeAcceptor::new(vec![]))),
+            ServerOptions {
+                admins: vec!["admin@EXAMPLE.COM".to_string()],
+                cluster: ClusterOptions {
+                    node: "a".to_string(),
+                    principals: vec!["HTTP/b@EXAMPLE.COM".to_string()],
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        )
+        .await;
+        let document = document(&client).await;
+        let hosts = "/api/v1/hosts";
+        let host = "/api/v1/hosts/{hostname}";
+        let snapshot = "/api/v1/admin/snapshot";
+
+        let registered = client
+            .post(hosts)
+            .header(ContentType::JSON)
+            .header(negotiate("host/h1@EXAMPLE.COM"))
+            .remote("10.0.0.1:4242".parse::<SocketAddr>().unwrap())
+            .body(
+                r#"{"hostname":"h1","metadata":{"os":"Debian","kernel":"6.1","version":"1.0",
+                "uptime":42,"interfaces":[{"name":"eth0","ip":"10.0.0.1"}],"tags":{"site":"paris"}}}"#,
+            )
+            .dispatch()
+            .await;
+        check(&document, "post", hosts, Status::Ok, registered).await;
+        let alice = negotiate("alice@EXAMPLE.COM");
+        let list = client.get(hosts).header(alice.clone()).dispatch().await;
+        check(&document, "get", hosts, Status::Ok, list).await;
+        let known = client
+            .get("/api/v1/hosts/h1")
+            .header(alice.clone())
+            .dispatch();
+        check(&document, "get", host, Status::Ok, known.await).await;
+        let unknown = client
+            .get("/api/v1/hosts/h2")
+            .header(alice.clone())
+            .dispatch();
+        check(&document, "get", host, Status::NotFound, unknown.await).await;
+        let refused = client
+            .delete("/api/v1/hosts/h1")
+            .header(alice.clone())
+            .dispatch();
+        check(&document, "delete", host, Status::Forbidden, refused.await).await;
+        let status = client
+            .get("/api/v1/cluster")
+            .header(alice.clone())
+            .dispatch();
+        check(
+            &document,
+            "get",
+            "/api/v1/cluster",
+            Status::Ok,
+            status.await,
+        )
+        .await;
+        let replicas = client
+            .get("/api/v1/cluster/replicas")
+            .header(negotiate("HTTP/b@EXAMPLE.COM"))
+            .dispatch();
+        check(
+            &document,
+            "get",
+            "/api/v1/cluster/replicas",
+            Status::Ok,
+            replicas.await,
+        )
+        .await;
+        let export = client
+            .get(snapshot)
+            .header(negotiate("admin@EXAMPLE.COM"))
+            .dispatch();
+        let exported = check(&document, "get", snapshot, Status::Ok, export.await).await;
+        let imported = client
+            .post(snapshot)
+            .header(ContentType::JSON)
+            .header(negotiate("admin@EXAMPLE.COM"))
+            .body(exported.to_string())
+            .dispatch();
+        check(&document, "post", snapshot, Status::Ok, imported.await).await;
+    }
+}
@@ -6,26 +6,46 @@ use rocket::{
     serde::{Deserialize, Serialize, json::Json},
 };
 
-use rocket_krb5::KrbToken;
-
-use crate::types::HostMap;
+use crate::auth::{Authenticators, Identity};
+use crate::events::Events;
+use crate::metrics::Metrics;
+use crate::routes::{ApiError, note_guard_error};
+use crate::types::{HostMap, HostMetadata, ServerOptions, unix_now};
 
 #[doc = "Request struct for DNS info, used in POST requests to add DNS records."]
 #[derive(Serialize, Deserialize)]
 #[serde(crate = "rocket::serde")]
 pub struct DnsInfoRequest {
     hostname: String,
+    /// Addresses the host asserts, recorded according to the server address policy.
+    #[serde(default)]
+    addresses: Vec<String>,
+    /// Optional metadata describing the host.
+    #[serde(default)]
+    metadata: HostMetadata,
 }
 
 /// Guard struct for extracting client IP from requests.
 pub struct ClientGuard {
-    ip: String,
+    pub ip: String,
 }
 
 #[doc = "Response struct for DNS operations, used in POST responses."]
 #[derive(Serialize, Deserialize)]
 #[serde(crate = "rocket::serde")]
 pub struct DnsResponse {
+    /// Addresses saved for the host.
+    saved_ip: String,
+    /// Addresses saved before the registration, `None` for a new host.
+    previous_ip: Option<String>,
+    /// Whether the registration changed the addresses of the host.
+    changed: bool,
+}
+
+#[doc = "Response of the unversioned `/add` path, in the shape its clients expect."]
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct LegacyDnsResponse {
     message: String,
 }
 
@@ -35,7 +55,11 @@ impl<'r> FromRequest<'r> for ClientGuard {
     async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
         match request.client_ip() {
             Some(ip) => Outcome::Success(ClientGuard { ip: ip.to_string() }),
-            None => Outcome::Error((Status::BadRequest, "No ip?? wtf".to_string())),
+            None => {
+                let message = "Cannot determine the client address";
+                note_guard_error(request, message);
+                Outcome::Error((Status::BadRequest, message.to_string()))
+            }
         }
     }
 }
@@ -46,32 +70,334 @@ impl<'r> FromRequest<'r> for ClientGuard {
 /// - `info`: JSON body containing DNS info.
 /// - `client_info`: Guard for extracting client IP.
 /// - `map`: Shared state for DNS records.
-/// - `_token`: Kerberos token for authentication.
+/// - `options`: Server options holding the address policy.
+/// - `events`: The event bus notified of the changes.
+/// - `metrics`: The metrics counting the registrations.
+/// - `auth`: The authentication methods, binding API tokens to their hosts.
+/// - `identity`: Authenticated identity of the client.
 ///
 /// ### Returns
-/// - `Json<DnsResponse>`: Response with operation message.
+/// - `Result<Json<DnsResponse>, ApiError>`: Response with the saved and previous
+///   addresses, bad request if an asserted address is invalid, or forbidden if the API
+///   token of the client is not bound to the host.
 ///
 /// ### Example
 /// ```rust
 /// // Usage in Rocket route
 /// #[post("/", format = "application/json", data = "<info>")]
-/// async fn post_address(info: Json<DnsInfoRequest>, client_info: ClientGuard, map: &State<HostMap>, _token: KrbToken) -> Json<DnsResponse> {
+/// async fn post_address(info: Json<DnsInfoRequest>, client_info: ClientGuard, map: &State<HostMap>, options: &State<ServerOptions>, events: &State<Events>, metrics: &State<Metrics>, auth: &State<Authenticators>, identity: Identity) -> Result<Json<DnsResponse>, ApiError> {
 ///     // ...
 /// }
 /// ```
 #[post("/", format = "application/json", data = "<info>")]
+#[allow(clippy::too_many_arguments)]
 pub async fn post_address(
     info: Json<DnsInfoRequest>,
     client_info: ClientGuard,
     map: &State<HostMap>,
-    _token: KrbToken,
-) -> Json<DnsResponse> {
+    options: &State<ServerOptions>,
+    events: &State<Events>,
+    metrics: &State<Metrics>,
+    auth: &State<Authenticators>,
+    identity: Identity,
+) -> Result<Json<DnsResponse>, ApiError> {
+    register(
+        &info,
+        client_info,
+        map,
+        options,
+        events,
+        metrics,
+        auth,
+        &identity,
+    )
+    .map(Json)
+}
+
+/// Handles POST requests to add a DNS record on the unversioned `/add` path, answering
+/// the `{"message": "Saved ip: <ip>"}` body its clients expect.
+///
+/// ### Parameters
+/// - `info`: JSON body containing DNS info.
+/// - `client_info`: Guard for extracting client IP.
+/// - `map`: Shared state for DNS records.
+/// - `options`: Server options holding the address policy.
+/// - `events`: The event bus notified of the changes.
+/// - `metrics`: The metrics counting the registrations.
+/// - `auth`: The authentication methods, binding API tokens to their hosts.
+/// - `identity`: Authenticated identity of the client.
+///
+/// ### Returns
+/// - `Result<Json<LegacyDnsResponse>, ApiError>`: Response with the saved addresses, bad
+///   request if an asserted address is invalid, or forbidden if the API token of the
+///   client is not bound to the host.
+///
+/// ### Example
+/// ```rust
+/// // POST /add {"hostname":"h1"}
+/// // -> {"message":"Saved ip: 10.0.0.1"}
+/// ```
+#[post("/", format = "application/json", data = "<info>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn post_legacy_address(
+    info: Json<DnsInfoRequest>,
+    client_info: ClientGuard,
+    map: &State<HostMap>,
+    options: &State<ServerOptions>,
+    events: &State<Events>,
+    metrics: &State<Metrics>,
+    auth: &State<Authenticators>,
+    identity: Identity,
+) -> Result<Json<LegacyDnsResponse>, ApiError> {
+    let response = register(
+        &info,
+        client_info,
+        map,
+        options,
+        events,
+        metrics,
+        auth,
+        &identity,
+    )?;
+    Ok(Json(LegacyDnsResponse {
+        message: format!("Saved ip: {}", response.saved_ip),
+    }))
+}
+
+/// Records the registration of a host, whatever the path it came through.
+#[allow(clippy::too_many_arguments)]
+fn register(
+    info: &DnsInfoRequest,
+    client_info: ClientGuard,
+    map: &HostMap,
+    options: &ServerOptions,
+    events: &Events,
+    metrics: &Metrics,
+    auth: &Authenticators,
+    identity: &Identity,
+) -> Result<DnsResponse, ApiError> {
     let hostname = info.hostname.clone();
-    let ip = client_info.ip;
+    if !identity.may_register(auth, &hostname) {
+        log::warn!("{} may not register {}", identity.principal, hostname);
+        return Err(ApiError::new(
+            Status::Forbidden,
+            format!("{} may not register {}", identity.principal, hostname),
+        ));
+    }
+    let mut record = options
+        .address_policy
+        .record(client_info.ip, &info.addresses)
+        .map_err(|e| ApiError::new(Status::BadRequest, e))?;
+    record.metadata = info.metadata.clone();
+    record.last_seen = unix_now();
 
-    map.lock().await.insert(hostname, ip.clone());
+    let previous = map.upsert(
+        &hostname,
+        &identity.principal,
+        &identity.owner(),
+        |_| record.clone(),
+        |old, new| events.registered(&hostname, old, new, &identity.principal),
+    );
+    let changed = previous
+        .as_ref()
+        .is_none_or(|p| p.addresses != record.addresses);
+    metrics.registered(&identity.principal, changed);
 
-    Json::from(DnsResponse {
-        message: format!("Saved ip: {}", ip),
+    Ok(DnsResponse {
+        saved_ip: record.ips(),
+        previous_ip: previous.as_ref().map(|p| p.ips()),
+        changed,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+
+    use rocket::http::{ContentType, Status};
+    use rocket_krb5::FakeAcceptor;
+
+    use crate::auth::{Authenticators, TokenStore, hash_token};
+    use crate::testing::{bearer, client, negotiate, server};
+    use crate::types::{AddressPolicy, AddressSource, HostMap, ServerOptions, unix_now};
+
+    #[rocket::async_test]
+    async fn post_address_saves_client_ip() {
+        let client = client(vec![]).await;
+
+        let response = client
+            .post("/api/v1/hosts")
+            .header(ContentType::JSON)
+            .header(negotiate("host/h1@EXAMPLE.COM"))
+            .remote("10.0.0.1:4242".parse::<SocketAddr>().unwrap())
+            .body(r#"{"hostname":"h1"}"#)
+            .dispatch()
+            .await;
+
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.into_string().await.unwrap(),
+            r#"{"saved_ip":"10.0.0.1","previous_ip":null,"changed":true}"#
+        );
+        let map = client.rocket().state::<HostMap>().unwrap();
+        assert_eq!(map.get("h1").map(|r| r.ips()), Some("10.0.0.1".to_string()));
+        assert!(map.get("h1").unwrap().last_seen.abs_diff(unix_now()) <= 1);
+    }
+
+    #[rocket::async_test]
+    async fn post_address_reports_address_changes() {
+        let client = client(vec![]).await;
+        let register = |remote: &'static str| {
+            client
+                .post("/api/v1/hosts")
+                .header(ContentType::JSON)
+                .header(negotiate("host/h1@EXAMPLE.COM"))
+                .remote(remote.parse::<SocketAddr>().unwrap())
+                .body(r#"{"hostname":"h1"}"#)
+                .dispatch()
+        };
+
+        register("10.0.0.1:4242").await;
+        let same = register("10.0.0.1:4242").await.into_string().await;
+        let moved = register("10.0.0.2:4242").await.into_string().await;
+
+        assert_eq!(
+            same.unwrap(),
+            r#"{"saved_ip":"10.0.0.1","previous_ip":"10.0.0.1","changed":false}"#
+        );
+        assert_eq!(
+            moved.unwrap(),
+            r#"{"saved_ip":"10.0.0.2","previous_ip":"10.0.0.1","changed":true}"#
+        );
+    }
+
+    #[rocket::async_test]
+    async fn legacy_path_answers_a_message() {
+        let client = client(vec![]).await;
+
+        let response = client
+            .post("/add")
+            .header(ContentType::JSON)
+            .header(negotiate("host/h1@EXAMPLE.COM"))
+            .remote("10.0.0.1:4242".parse::<SocketAddr>().unwrap())
+            .body(r#"{"hostname":"h1"}"#)
+            .dispatch()
+            .await;
+
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.into_string().await.unwrap(),
+            r#"{"message":"Saved ip: 10.0.0.1"}"#
+        );
+    }
+
+    #[rocket::async_test]
+    async fn post_address_follows_address_policy() {
+        let client = server(
+            Authenticators::kerberos(Arc::new(FakeAcceptor::default())),
+            ServerOptions {
+                address_policy: AddressPolicy::Both,
+                ..Default::default()
+            },
+        )
+        .await;
+
+        let response = client
+            .post("/add")
+            .header(ContentType::JSON)
+            .header(negotiate("host/h1@EXAMPLE.COM"))
+            .remote("10.0.0.1:4242".parse::<SocketAddr>().unwrap())
+            .body(r#"{"hostname":"h1","addresses":["192.168.1.5"]}"#)
+            .dispatch()
+            .await;
+
+        assert_eq!(response.status(), Status::Ok);
+        let map = client.rocket().state::<HostMap>().unwrap();
+        let record = map.get("h1").unwrap();
+        let sources: Vec<AddressSource> = record.addresses.iter().map(|a| a.source).collect();
+        assert_eq!(record.ips(), "10.0.0.1, 192.168.1.5");
+        assert_eq!(
+            sources,
+            vec![AddressSource::Observed, AddressSource::Asserted]
+        );
+    }
+
+    #[rocket::async_test]
+    async fn post_address_rejects_invalid_assertion() {
+        let both = server(
+            Authenticators::kerberos(Arc::new(FakeAcceptor::default())),
+            ServerOptions {
+                address_policy: AddressPolicy::Both,
+                ..Default::default()
+            },
+        )
+        .await;
+        let observed = client(vec![]).await;
+
+        // Only the policies recording the asserted addresses check them.
+        for (client, expected) in [(&both, Status::BadRequest), (&observed, Status::Ok)] {
+            let response = client
+                .post("/api/v1/hosts")
+                .header(ContentType::JSON)
+                .header(negotiate("host/h1@EXAMPLE.COM"))
+                .remote("10.0.0.1:4242".parse::<SocketAddr>().unwrap())
+                .body(r#"{"hostname":"h1","addresses":["nope"]}"#)
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), expected);
+        }
+
+        assert!(both.rocket().state::<HostMap>().unwrap().is_empty());
+        let map = observed.rocket().state::<HostMap>().unwrap();
+        assert_eq!(map.get("h1").map(|r| r.ips()), Some("10.0.0.1".to_string()));
+    }
+
+    #[rocket::async_test]
+    async fn tokens_only_register_their_hosts() {
+        let entry = format!(
+            "router1:{} router1.example.com\nrouter2:{}",
+            hash_token("s3cret"),
+            hash_token("0ther")
+        );
+        let client = server(
+            Authenticators {
+                tokens: Some(TokenStore::parse(&entry).unwrap()),
+                ..Default::default()
+            },
+            ServerOptions::default(),
+        )
+        .await;
+        let register = |token: &'static str| {
+            client
+                .post("/api/v1/hosts")
+                .header(ContentType::JSON)
+                .header(bearer(token))
+                .remote("10.0.0.1:4242".parse::<SocketAddr>().unwrap())
+                .body(r#"{"hostname":"router1.example.com"}"#)
+                .dispatch()
+        };
+
+        let refused = register("0ther").await;
+        assert_eq!(refused.status(), Status::Forbidden);
+        assert!(client.rocket().state::<HostMap>().unwrap().is_empty());
+        let allowed = register("s3cret").await;
+        assert_eq!(allowed.status(), Status::Ok);
+    }
+
+    #[rocket::async_test]
+    async fn post_address_requires_authentication() {
+        let client = client(vec![]).await;
+
+        let response = client
+            .post("/add")
+            .header(ContentType::JSON)
+            .remote("10.0.0.1:4242".parse::<SocketAddr>().unwrap())
+            .body(r#"{"hostname":"h1"}"#)
+            .dispatch()
+            .await;
+
+        assert_eq!(response.status(), Status::Unauthorized);
+        assert!(client.rocket().state::<HostMap>().unwrap().is_empty());
+    }
+}
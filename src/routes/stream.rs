@@ -0,0 +1,186 @@
+use std::convert::Infallible;
+
+use rocket::request::{FromRequest, Outcome};
+use rocket::response::stream::{Event, EventStream};
+use rocket::tokio::sync::broadcast::error::RecvError;
+use rocket::{Request, Shutdown, State};
+
+use crate::auth::Identity;
+use crate::events::{Events, HostEvent};
+
+/// Identifier of the last event a resuming client received, from the `Last-Event-ID` header.
+pub struct LastEventId(pub Option<u64>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for LastEventId {
+    type Error = Infallible;
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let id = request
+            .headers()
+            .get_one("Last-Event-ID")
+            .and_then(|id| id.trim().parse::<u64>().ok());
+        Outcome::Success(LastEventId(id))
+    }
+}
+
+/// Streams the changes made to host records as Server-Sent Events.
+///
+/// Each event carries the identifier, kind and JSON description of the change. A client
+/// reconnecting with `Last-Event-ID` first receives the events it missed, replayed from the
+/// event log; when some of them already left the log, it receives a `reset` event instead,
+/// telling it to reload the full list.
+///
+/// ### Parameters
+/// - `events`: The event bus.
+/// - `last_event_id`: The last event received by a resuming client, if any.
+/// - `identity`: Authenticated identity of the client.
+/// - `shutdown`: Ends the stream when the server stops.
+///
+/// ### Returns
+/// - `EventStream![]`: The stream of changes.
+///
+/// ### Example
+/// ```rust
+/// // GET /api/v1/events
+/// // Last-Event-ID: 41
+/// // -> id:42
+/// //    event:updated
+/// //    data:{"id":42,"event":"updated","hostname":"h1",...}
+/// ```
+#[get("/")]
+pub fn stream_events(
+    events: &State<Events>,
+    last_event_id: LastEventId,
+    identity: Identity,
+    mut shutdown: Shutdown,
+) -> EventStream![] {
+    log::info!(
+        "{} ({}) watching events",
+        identity.principal,
+        identity.method
+    );
+
+    let mut receiver = events.subscribe();
+    let missed = last_event_id.0.map(|id| events.since(id));
+
+    EventStream! {
+        let mut last_id = last_event_id.0.unwrap_or_default();
+        match missed {
+            Some(Some(missed)) => {
+                for event in missed {
+                    last_id = event.id;
+                    yield to_sse(&event);
+                }
+            }
+            Some(None) => {
+                // The identifier may come from before a restart, newer than the next ones.
+                last_id = 0;
+                yield Event::empty().event("reset");
+            }
+            None => {}
+        }
+
+        loop {
+            let event = rocket::tokio::select! {
+                received = receiver.recv() => match received {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(_)) => {
+                        yield Event::empty().event("reset");
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                _ = &mut shutdown => break,
+            };
+
+            // Events replayed from the log may also be waiting on the bus.
+            if event.id > last_id {
+                last_id = event.id;
+                yield to_sse(&event);
+            }
+        }
+    }
+}
+
+/// Turns a change into a Server-Sent Event.
+fn to_sse(event: &HostEvent) -> Event {
+    Event::json(event)
+        .id(event.id.to_string())
+        .event(event.kind.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use rocket::http::{ContentType, Header, Status};
+    use rocket::local::asynchronous::Client;
+
+    use crate::testing::{client, negotiate};
+
+    async fn register(client: &Client, remote: &str) {
+        client
+            .post("/add")
+            .header(ContentType::JSON)
+            .header(negotiate("host/h1@EXAMPLE.COM"))
+            .remote(remote.parse::<SocketAddr>().unwrap())
+            .body(r#"{"hostname":"h1"}"#)
+            .dispatch()
+            .await;
+    }
+
+    #[rocket::async_test]
+    async fn stream_resumes_after_last_event_id() {
+        let client = client(vec![]).await;
+        register(&client, "10.0.0.1:4242").await;
+        register(&client, "10.0.0.2:4242").await;
+        // Ends the stream once the missed events are sent.
+        client.rocket().shutdown().notify();
+
+        let response = client
+            .get("/api/v1/events")
+            .header(negotiate("alice@EXAMPLE.COM"))
+            .header(Header::new("Last-Event-ID", "1"))
+            .dispatch()
+            .await;
+
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::EventStream));
+        // Heartbeats may come between the fields of an event.
+        let body = response
+            .into_string()
+            .await
+            .unwrap()
+            .lines()
+            .filter(|line| *line != ":")
+            .map(|line| format!("{}\n", line))
+            .collect::<String>();
+        assert!(!body.contains("id:1\n"), "{}", body);
+        assert!(body.contains("id:2\nevent:updated\ndata:{\"id\":2,\"event\":\"updated\",\"hostname\":\"h1\",\"old_ip\":\"10.0.0.1\",\"new_ip\":\"10.0.0.2\""), "{}", body);
+    }
+
+    #[rocket::async_test]
+    async fn stream_resets_clients_resuming_from_before_a_restart() {
+        let client = client(vec![]).await;
+        client.rocket().shutdown().notify();
+
+        let response = client
+            .get("/api/v1/events")
+            .header(negotiate("alice@EXAMPLE.COM"))
+            .header(Header::new("Last-Event-ID", "41"))
+            .dispatch()
+            .await;
+
+        let body = response.into_string().await.unwrap();
+        assert!(body.lines().any(|line| line == "event:reset"), "{}", body);
+    }
+
+    #[rocket::async_test]
+    async fn stream_requires_authentication() {
+        let client = client(vec![]).await;
+
+        let response = client.get("/api/v1/events").dispatch().await;
+
+        assert_eq!(response.status(), Status::Unauthorized);
+    }
+}
@@ -0,0 +1,639 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use dashmap::DashMap;
+use dashmap::mapref::entry::{Entry, OccupiedEntry};
+use rocket::serde::ser::{Serialize, SerializeMap, Serializer};
+use rocket::serde::{Deserialize, Serialize as DeriveSerialize};
+
+use crate::types::HostRecord;
+
+/// Version of a host record, ordering the changes made on the servers of a cluster: the
+/// later change wins, the origin breaking the ties between changes made at the same time.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, DeriveSerialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Version {
+    /// Time of the change, in milliseconds since the Unix epoch.
+    pub timestamp: u64,
+    /// Node the change was made on.
+    pub origin: String,
+}
+
+/// State of a host as exchanged between the servers of a cluster, removed hosts being
+/// told as well so that a removal is not undone by a peer still knowing the host.
+#[derive(Clone, Debug, PartialEq, DeriveSerialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Replica {
+    pub hostname: String,
+    /// The record of the host, `None` once it was removed.
+    pub record: Option<HostRecord>,
+    pub version: Version,
+    /// Principal that made the change, `None` when a server did.
+    pub principal: Option<String>,
+    /// Principal owning the host, the first to register it, `None` once it was removed.
+    #[serde(default)]
+    pub owner: Option<String>,
+}
+
+/// Record of a host along with the version of its last change. The record is kept as a
+/// tombstone once removed from a replicated store.
+#[derive(Clone, Debug)]
+struct Slot {
+    record: Option<HostRecord>,
+    version: Version,
+    principal: Option<String>,
+    owner: Option<String>,
+}
+
+/// Records of the registered hosts, split in shards locked independently so that
+/// registrations of different hosts do not wait for each other and reads of the whole
+/// store only hold one shard at a time.
+///
+/// No lock is held across an await point, every method returning once the shards it
+/// touched are released.
+///
+/// A replicated store versions every change with the name of its node and keeps the
+/// removed hosts as tombstones, to be merged with the stores of the other servers.
+#[derive(Debug, Default)]
+pub struct HostStore {
+    hosts: DashMap<String, Slot>,
+    /// Node the changes are made on, `None` if the store is not replicated.
+    node: Option<String>,
+}
+
+impl HostStore {
+    /// Creates an empty store replicated with the stores of other servers.
+    ///
+    /// ### Parameters
+    /// - `node`: Name of the server, the origin of the changes made on it.
+    ///
+    /// ### Returns
+    /// - `HostStore`: The empty store.
+    ///
+    /// ### Example
+    /// ```rust
+    /// let map: HostMap = Arc::new(HostStore::replicated("rping-a"));
+    /// ```
+    pub fn replicated(node: &str) -> HostStore {
+        HostStore {
+            hosts: DashMap::new(),
+            node: Some(node.to_string()),
+        }
+    }
+
+    /// Number of registered hosts.
+    ///
+    /// ### Returns
+    /// - `usize`: The number of records, tombstones aside.
+    pub fn len(&self) -> usize {
+        match self.node {
+            None => self.hosts.len(),
+            Some(_) => self.hosts.iter().filter(|s| s.record.is_some()).count(),
+        }
+    }
+
+    /// Tells whether no host is registered.
+    ///
+    /// ### Returns
+    /// - `bool`: True if the store is empty.
+    pub fn is_empty(&self) -> bool {
+        !self.hosts.iter().any(|s| s.record.is_some())
+    }
+
+    /// Looks up the record of a host.
+    ///
+    /// ### Parameters
+    /// - `hostname`: The hostname to look up.
+    ///
+    /// ### Returns
+    /// - `Option<HostRecord>`: A copy of the record, None if the host is unknown.
+    ///
+    /// ### Example
+    /// ```rust
+    /// let record = map.get("h1").ok_or("Unknown host")?;
+    /// ```
+    pub fn get(&self, hostname: &str) -> Option<HostRecord> {
+        self.hosts
+            .get(hostname)
+            .and_then(|slot| slot.record.clone())
+    }
+
+    /// Sets the record of a host, built from its previous record if any.
+    ///
+    /// The change is observed while the host is still locked, so that the changes of a
+    /// host are told in the order they are applied.
+    ///
+    /// ### Parameters
+    /// - `hostname`: The registered hostname.
+    /// - `principal`: The principal registering the host.
+    /// - `owner`: The owner of the host if it is new, a known host keeping its owner.
+    /// - `build`: Builds the new record from the previous one.
+    /// - `observe`: Called with the previous and new records once the new one is built.
+    ///
+    /// ### Returns
+    /// - `Option<HostRecord>`: The previous record, None if the host was unknown.
+    ///
+    /// ### Example
+    /// ```rust
+    /// let previous = map.upsert(&hostname, &identity.principal, &identity.owner(), |_| record.clone(), |old, new| {
+    ///     events.registered(&hostname, old, new, &identity.principal)
+    /// });
+    /// ```
+    pub fn upsert(
+        &self,
+        hostname: &str,
+        principal: &str,
+        owner: &str,
+        build: impl FnOnce(Option<&HostRecord>) -> HostRecord,
+        observe: impl FnOnce(Option<&HostRecord>, &HostRecord),
+    ) -> Option<HostRecord> {
+        self.upsert_if(hostname, principal, owner, |old| Some(build(old)), observe)
+            .flatten()
+    }
+
+    /// Sets the record of a host unless the build, given the previous record while the
+    /// host is locked, declines the change.
+    ///
+    /// ### Parameters
+    /// - `hostname`: The registered hostname.
+    /// - `principal`: The principal registering the host.
+    /// - `owner`: The owner of the host if it is new, a known host keeping its owner.
+    /// - `build`: Builds the new record from the previous one, None to leave the host as is.
+    /// - `observe`: Called with the previous and new records once the new one is built.
+    ///
+    /// ### Returns
+    /// - `Option<Option<HostRecord>>`: None if the change was declined, the previous record
+    ///   otherwise.
+    ///
+    /// ### Example
+    /// ```rust
+    /// let previous = map.upsert_if(&hostname, principal, principal, |old| {
+    ///     old.is_none_or(|old| old.last_seen < record.last_seen).then(|| record.clone())
+    /// }, |_, _| {});
+    /// ```
+    pub fn upsert_if(
+        &self,
+        hostname: &str,
+        principal: &str,
+        owner: &str,
+        build: impl FnOnce(Option<&HostRecord>) -> Option<HostRecord>,
+        observe: impl FnOnce(Option<&HostRecord>, &HostRecord),
+    ) -> Option<Option<HostRecord>> {
+        let claim = |current: Option<String>| current.or_else(|| Some(owner.to_string()));
+        self.apply(hostname, principal, claim, build, observe)
+    }
+
+    /// Sets the record of a host along with its owner unless the build, given the previous
+    /// record while the host is locked, declines the change. Used to restore the hosts of
+    /// a snapshot.
+    ///
+    /// ### Parameters
+    /// - `hostname`: The registered hostname.
+    /// - `principal`: The principal restoring the host.
+    /// - `owner`: The owner of the host, `None` to leave it to the next registrant.
+    /// - `build`: Builds the new record from the previous one, None to leave the host as is.
+    /// - `observe`: Called with the previous and new records once the new one is built.
+    ///
+    /// ### Returns
+    /// - `Option<Option<HostRecord>>`: None if the change was declined, the previous record
+    ///   otherwise.
+    ///
+    /// ### Example
+    /// ```rust
+    /// let previous = map.restore(&hostname, principal, host.owner.as_deref(), |_| {
+    ///     Some(host.record.clone())
+    /// }, |_, _| {});
+    /// ```
+    pub fn restore(
+        &self,
+        hostname: &str,
+        principal: &str,
+        owner: Option<&str>,
+        build: impl FnOnce(Option<&HostRecord>) -> Option<HostRecord>,
+        observe: impl FnOnce(Option<&HostRecord>, &HostRecord),
+    ) -> Option<Option<HostRecord>> {
+        let owner = |_| owner.map(str::to_string);
+        self.apply(hostname, principal, owner, build, observe)
+    }
+
+    /// Sets the record of a host unless the build declines the change, the owner being
+    /// derived from the current one, `None` for a host without record.
+    fn apply(
+        &self,
+        hostname: &str,
+        principal: &str,
+        owner: impl FnOnce(Option<String>) -> Option<String>,
+        build: impl FnOnce(Option<&HostRecord>) -> Option<HostRecord>,
+        observe: impl FnOnce(Option<&HostRecord>, &HostRecord),
+    ) -> Option<Option<HostRecord>> {
+        match self.hosts.entry(hostname.to_string()) {
+            Entry::Occupied(mut entry) => {
+                let slot = entry.get_mut();
+                let record = build(slot.record.as_ref())?;
+                observe(slot.record.as_ref(), &record);
+                slot.version = self.next_version(Some(&slot.version));
+                slot.principal = Some(principal.to_string());
+                let current = slot.owner.take().filter(|_| slot.record.is_some());
+                slot.owner = owner(current);
+                Some(slot.record.replace(record))
+            }
+            Entry::Vacant(entry) => {
+                let record = build(None)?;
+                observe(None, &record);
+                entry.insert(Slot {
+                    record: Some(record),
+                    version: self.next_version(None),
+                    principal: Some(principal.to_string()),
+                    owner: owner(None),
+                });
+                Some(None)
+            }
+        }
+    }
+
+    /// Removes the record of a host, observed while the host is still locked.
+    ///
+    /// ### Parameters
+    /// - `hostname`: The hostname to remove.
+    /// - `principal`: The principal removing the host, `None` when the server does.
+    /// - `observe`: Called with the removed record.
+    ///
+    /// ### Returns
+    /// - `Option<HostRecord>`: The removed record, None if the host was unknown.
+    ///
+    /// ### Example
+    /// ```rust
+    /// map.remove("h1", Some(principal), |record| events.removed(EventKind::Deleted, "h1", record, Some(principal)));
+    /// ```
+    pub fn remove(
+        &self,
+        hostname: &str,
+        principal: Option<&str>,
+        observe: impl FnOnce(&HostRecord),
+    ) -> Option<HostRecord> {
+        let Entry::Occupied(entry) = self.hosts.entry(hostname.to_string()) else {
+            return None;
+        };
+        self.take(entry, principal, observe)
+    }
+
+    /// Removes the record of a host on behalf of a principal, provided the principal owns
+    /// the host. The ownership is checked while the host is locked.
+    ///
+    /// ### Parameters
+    /// - `hostname`: The hostname to remove.
+    /// - `principal`: The principal removing the host.
+    /// - `owner`: The owner name of the principal, as given when registering.
+    /// - `observe`: Called with the removed record.
+    ///
+    /// ### Returns
+    /// - `Result<Option<HostRecord>, String>`: Ok with the removed record, None if the host
+    ///   was unknown, Err if the host belongs to another principal.
+    ///
+    /// ### Example
+    /// ```rust
+    /// let removed = map.remove_owned("h1", &identity.principal, &identity.owner(), |_| {})?;
+    /// ```
+    pub fn remove_owned(
+        &self,
+        hostname: &str,
+        principal: &str,
+        owner: &str,
+        observe: impl FnOnce(&HostRecord),
+    ) -> Result<Option<HostRecord>, String> {
+        let Entry::Occupied(entry) = self.hosts.entry(hostname.to_string()) else {
+            return Ok(None);
+        };
+        let slot = entry.get();
+        if slot.record.is_some() && slot.owner.as_deref() != Some(owner) {
+            return Err(format!("{} is registered by another principal", hostname));
+        }
+        Ok(self.take(entry, Some(principal), observe))
+    }
+
+    /// Removes the record of a locked host, leaving a tombstone if the store is replicated.
+    fn take(
+        &self,
+        mut entry: OccupiedEntry<'_, String, Slot>,
+        principal: Option<&str>,
+        observe: impl FnOnce(&HostRecord),
+    ) -> Option<HostRecord> {
+        observe(entry.get().record.as_ref()?);
+        if self.node.is_none() {
+            return entry.remove().record;
+        }
+
+        let slot = entry.get_mut();
+        slot.version = self.next_version(Some(&slot.version));
+        slot.principal = principal.map(String::from);
+        slot.owner = None;
+        slot.record.take()
+    }
+
+    /// Keeps the records matching a predicate, the others being removed by the server.
+    /// The predicate runs while the shard of the host is locked.
+    ///
+    /// ### Parameters
+    /// - `keep`: Tells whether a record is kept, given its hostname.
+    ///
+    /// ### Example
+    /// ```rust
+    /// map.retain(|_, record| record.last_seen >= deadline);
+    /// ```
+    pub fn retain(&self, mut keep: impl FnMut(&str, &HostRecord) -> bool) {
+        self.hosts.retain(|hostname, slot| {
+            let Some(record) = &slot.record else {
+                return true;
+            };
+            if keep(hostname, record) {
+                return true;
+            }
+            if self.node.is_none() {
+                return false;
+            }
+
+            slot.record = None;
+            slot.version = self.next_version(Some(&slot.version));
+            slot.principal = None;
+            slot.owner = None;
+            true
+        });
+    }
+
+    /// Visits every record, one shard being locked at a time.
+    ///
+    /// ### Parameters
+    /// - `visit`: Called with each hostname and record.
+    pub fn for_each(&self, mut visit: impl FnMut(&str, &HostRecord)) {
+        for entry in self.hosts.iter() {
+            if let Some(record) = &entry.record {
+                visit(entry.key(), record);
+            }
+        }
+    }
+
+    /// Lists the state of every host, removed ones included, for the other servers of
+    /// the cluster to merge.
+    ///
+    /// ### Returns
+    /// - `Vec<Replica>`: The state of each host.
+    pub fn replicas(&self) -> Vec<Replica> {
+        self.hosts
+            .iter()
+            .map(|entry| Replica {
+                hostname: entry.key().clone(),
+                record: entry.record.clone(),
+                version: entry.version.clone(),
+                principal: entry.principal.clone(),
+                owner: entry.owner.clone(),
+            })
+            .collect()
+    }
+
+    /// Applies the state of a host received from another server if it is newer than the
+    /// local one, observed while the host is still locked.
+    ///
+    /// ### Parameters
+    /// - `replica`: The state of the host on the other server.
+    /// - `observe`: Called with the local record and the applied state.
+    ///
+    /// ### Returns
+    /// - `bool`: True if the state was applied, false if the local one is as recent.
+    ///
+    /// ### Example
+    /// ```rust
+    /// for replica in replicas {
+    ///     map.merge(replica, |old, replica| println!("{} was {:?}", replica.hostname, old));
+    /// }
+    /// ```
+    pub fn merge(
+        &self,
+        replica: Replica,
+        observe: impl FnOnce(Option<&HostRecord>, &Replica),
+    ) -> bool {
+        let entry = self.hosts.entry(replica.hostname.clone());
+        if let Entry::Occupied(entry) = &entry
+            && entry.get().version >= replica.version
+        {
+            return false;
+        }
+
+        let old = match &entry {
+            Entry::Occupied(entry) => entry.get().record.as_ref(),
+            Entry::Vacant(_) => None,
+        };
+        observe(old, &replica);
+        entry.insert(Slot {
+            record: replica.record,
+            version: replica.version,
+            principal: replica.principal,
+            owner: replica.owner,
+        });
+        true
+    }
+
+    /// Forgets the hosts removed for longer than the given time. A server that did not
+    /// hear from the cluster for longer than that may bring them back.
+    ///
+    /// ### Parameters
+    /// - `older_than`: Age above which a tombstone is forgotten.
+    ///
+    /// ### Returns
+    /// - `usize`: The number of tombstones forgotten.
+    pub fn prune(&self, older_than: Duration) -> usize {
+        let deadline = now_millis().saturating_sub(older_than.as_millis() as u64);
+        let before = self.hosts.len();
+        self.hosts
+            .retain(|_, slot| slot.record.is_some() || slot.version.timestamp >= deadline);
+        before.saturating_sub(self.hosts.len())
+    }
+
+    /// Versions a change made on this server, after the previous version of the host even
+    /// if the clock of the server that made it was ahead.
+    fn next_version(&self, previous: Option<&Version>) -> Version {
+        Version {
+            timestamp: now_millis().max(previous.map_or(0, |v| v.timestamp + 1)),
+            origin: self.node.clone().unwrap_or_default(),
+        }
+    }
+}
+
+/// Current time in milliseconds since the Unix epoch.
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+impl FromIterator<(String, HostRecord)> for HostStore {
+    fn from_iter<I: IntoIterator<Item = (String, HostRecord)>>(iter: I) -> HostStore {
+        HostStore {
+            hosts: iter
+                .into_iter()
+                .map(|(hostname, record)| {
+                    let slot = Slot {
+                        record: Some(record),
+                        version: Version::default(),
+                        principal: None,
+                        owner: None,
+                    };
+                    (hostname, slot)
+                })
+                .collect(),
+            node: None,
+        }
+    }
+}
+
+/// Serializes the store as a map of hostnames to records, straight from the shards
+/// rather than from a copy.
+impl Serialize for HostStore {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        for entry in self.hosts.iter() {
+            if let Some(record) = &entry.record {
+                map.serialize_entry(entry.key(), record)?;
+            }
+        }
+        map.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use rocket::serde::json::{Value, serde_json};
+
+    use super::HostStore;
+    use crate::types::HostRecord;
+
+    fn record(last_seen: u64) -> HostRecord {
+        HostRecord {
+            last_seen,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn concurrent_registrations_are_all_kept() {
+        let store = Arc::new(HostStore::default());
+        let writers: Vec<_> = (0..8)
+            .map(|w| {
+                let store = store.clone();
+                std::thread::spawn(move || {
+                    for i in 0..500 {
+                        store.upsert(
+                            &format!("h{}", i),
+                            "alice",
+                            "alice",
+                            |_| record(w),
+                            |_, _| {},
+                        );
+                        store.upsert(
+                            &format!("w{}-{}", w, i),
+                            "alice",
+                            "alice",
+                            |_| record(w),
+                            |_, _| {},
+                        );
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        assert_eq!(store.len(), 500 + 8 * 500);
+        let mut observed = None;
+        let previous = store.upsert(
+            "h1",
+            "alice",
+            "alice",
+            |old| record(old.unwrap().last_seen + 100),
+            |old, new| observed = Some((old.cloned(), new.clone())),
+        );
+        assert_eq!(
+            observed,
+            Some((previous, record(store.get("h1").unwrap().last_seen)))
+        );
+        store.retain(|hostname, _| hostname.starts_with('h'));
+        let listed: Value = serde_json::to_value(&*store).unwrap();
+        assert_eq!(listed.as_object().unwrap().len(), 500);
+        assert_eq!(
+            store.remove("h1", None, |_| {}).map(|r| r.last_seen >= 100),
+            Some(true)
+        );
+        assert!(store.get("h1").is_none());
+    }
+
+    #[test]
+    fn declined_upserts_leave_the_host_untouched() {
+        let store = HostStore::default();
+        store.upsert("h1", "alice", "alice", |_| record(5), |_, _| {});
+        let newer = |old: Option<&HostRecord>, last_seen| {
+            old.is_none_or(|o| o.last_seen < last_seen)
+                .then(|| record(last_seen))
+        };
+
+        let mut observed = false;
+        let declined = store.upsert_if(
+            "h1",
+            "bob",
+            "bob",
+            |old| newer(old, 3),
+            |_, _| observed = true,
+        );
+        let missing = store.upsert_if("h2", "bob", "bob", |_| None, |_, _| observed = true);
+        let accepted = store.upsert_if("h1", "bob", "bob", |old| newer(old, 7), |_, _| {});
+
+        assert_eq!((declined, missing, observed), (None, None, false));
+        assert_eq!(accepted, Some(Some(record(5))));
+        assert_eq!(store.get("h1"), Some(record(7)));
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    fn later_changes_win_across_replicas() {
+        let a = HostStore::replicated("a");
+        let b = HostStore::replicated("b");
+        a.upsert("h1", "alice", "alice", |_| record(1), |_, _| {});
+        a.upsert("h2", "alice", "alice", |_| record(1), |_, _| {});
+        for replica in a.replicas() {
+            assert!(b.merge(replica, |_, _| {}));
+        }
+        assert_eq!(b.get("h1"), Some(record(1)));
+
+        // A local change made after the merge wins over the older state of the peer, and
+        // a removal is kept as a tombstone so that merging the peer does not undo it.
+        b.upsert("h1", "bob", "bob", |_| record(2), |_, _| {});
+        b.remove("h2", Some("bob"), |_| {});
+        for replica in a.replicas() {
+            assert!(!b.merge(replica, |_, _| {}));
+        }
+        assert_eq!(b.get("h1"), Some(record(2)));
+        assert!(b.get("h2").is_none());
+        assert_eq!(b.len(), 1);
+
+        let mut removed = None;
+        for replica in b.replicas() {
+            a.merge(replica, |old, replica| {
+                if replica.record.is_none() {
+                    removed = old.cloned();
+                }
+            });
+        }
+        assert_eq!(removed, Some(record(1)));
+        assert_eq!(a.get("h1"), Some(record(2)));
+        assert_eq!(
+            serde_json::to_value(&a).unwrap().as_object().unwrap().len(),
+            1
+        );
+        assert_eq!(a.prune(Duration::from_secs(3600)), 0);
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(a.prune(Duration::from_millis(1)), 1);
+        assert_eq!(a.replicas().len(), 1);
+    }
+}
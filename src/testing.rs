@@ -0,0 +1,105 @@
+use std::sync::Arc;
+
+use rocket::config::LogLevel;
+use rocket::fairing::AdHoc;
+use rocket::http::Header;
+use rocket::local::asynchronous::Client;
+use rocket::tokio::sync::oneshot;
+use rocket::{Build, Config, Rocket, Shutdown};
+use rocket_krb5::{FakeAcceptor, SharedAcceptor};
+
+use crate::auth::Authenticators;
+use crate::launcher::build_server;
+use crate::types::ServerOptions;
+
+/// Creates a local client on the rping server, authenticating with the fake acceptor.
+///
+/// ### Parameters
+/// - `allowed`: The client principals allowed to authenticate, anybody when empty.
+///
+/// ### Returns
+/// - `Client`: A local client dispatching requests to the server.
+pub async fn client(allowed: Vec<&str>) -> Client {
+    let acceptor: SharedAcceptor = Arc::new(FakeAcceptor::new(
+        allowed.into_iter().map(String::from).collect(),
+    ));
+
+    server(Authenticators::kerberos(acceptor), ServerOptions::default()).await
+}
+
+/// Creates a local client on the rping server, authenticating with the given methods.
+///
+/// ### Parameters
+/// - `auth`: The authentication methods enabled on the server.
+/// - `options`: The server options.
+///
+/// ### Returns
+/// - `Client`: A local client dispatching requests to the server.
+pub async fn server(auth: Authenticators, options: ServerOptions) -> Client {
+    Client::tracked(build_server(rocket::build(), auth, options))
+        .await
+        .expect("valid rocket instance")
+}
+
+/// Builds the Authorization header a fake client sends to authenticate as a principal.
+///
+/// ### Parameters
+/// - `principal`: The client principal.
+///
+/// ### Returns
+/// - `Header<'static>`: The Authorization header.
+pub fn negotiate(principal: &str) -> Header<'static> {
+    use base64::Engine;
+
+    let token = format!("fake:{}", principal);
+    Header::new(
+        "Authorization",
+        format!(
+            "Negotiate {}",
+            base64::engine::general_purpose::STANDARD.encode(token)
+        ),
+    )
+}
+
+/// Builds the Authorization header of a client presenting an API token.
+///
+/// ### Parameters
+/// - `token`: The API token in clear.
+///
+/// ### Returns
+/// - `Header<'static>`: The Authorization header.
+pub fn bearer(token: &str) -> Header<'static> {
+    Header::new("Authorization", format!("Bearer {}", token))
+}
+
+/// Launches a Rocket instance on an ephemeral port of the loopback, for the tests needing
+/// a real server.
+///
+/// ### Parameters
+/// - `rocket`: The Rocket instance, its port and log level being overridden.
+///
+/// ### Returns
+/// - `(String, Shutdown)`: The base URL of the server and the handle stopping it.
+pub async fn launch(rocket: Rocket<Build>) -> (String, Shutdown) {
+    let config = Config {
+        port: 0,
+        log_level: LogLevel::Off,
+        ..Config::debug_default()
+    };
+    let (tx, rx) = oneshot::channel();
+    let rocket = rocket
+        .configure(config)
+        .attach(AdHoc::on_liftoff("Port", |rocket| {
+            Box::pin(async move {
+                let _ = tx.send(rocket.config().port);
+            })
+        }))
+        .ignite()
+        .await
+        .expect("valid rocket instance");
+    let shutdown = rocket.shutdown();
+    rocket::tokio::spawn(rocket.launch());
+
+    let port = rx.await.expect("server launched");
+    (format!("http://127.0.0.1:{}", port), shutdown)
+}
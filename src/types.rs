@@ -1,5 +1,238 @@
-use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use rocket::futures::lock::Mutex;
+use rocket::serde::{Deserialize, Serialize};
 
-pub type HostMap = Mutex<HashMap<String, String>>;
+use crate::audit::AuditLog;
+use crate::cluster::ClusterOptions;
+use crate::hooks::HookOptions;
+use crate::limits::LimitOptions;
+use crate::metrics::MetricsOptions;
+use crate::store::HostStore;
+use crate::webhooks::WebhookOptions;
+
+/// Metadata of the hosts, shared with the client so that both sides agree on its fields.
+pub use librping::{HostMetadata, Interface};
+
+/// DNS records by hostname, shared between the routes and the background tasks.
+pub type HostMap = Arc<HostStore>;
+
+/// Where an address of a host comes from.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum AddressSource {
+    /// The peer address of the connection the host registered over.
+    Observed,
+    /// An address the host claims, such as a LAN or VPN address.
+    Asserted,
+}
+
+/// An address of a host, tagged with its source.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Address {
+    pub ip: String,
+    pub source: AddressSource,
+}
+
+/// Record kept for each registered host.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct HostRecord {
+    pub addresses: Vec<Address>,
+    #[serde(default)]
+    pub metadata: HostMetadata,
+    /// Time of the last registration, in seconds since the Unix epoch.
+    #[serde(default)]
+    pub last_seen: u64,
+}
+
+impl HostRecord {
+    /// Lists the addresses of the record, for logs and responses.
+    ///
+    /// ### Returns
+    /// - `String`: The comma separated addresses.
+    pub fn ips(&self) -> String {
+        self.addresses
+            .iter()
+            .map(|a| a.ip.as_str())
+            .collect::<Vec<&str>>()
+            .join(", ")
+    }
+}
+
+/// Current time in seconds since the Unix epoch.
+///
+/// ### Returns
+/// - `u64`: The current Unix timestamp.
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Server policy deciding which addresses of a registration are recorded.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AddressPolicy {
+    /// Only the observed peer address, asserted addresses being ignored.
+    #[default]
+    Observed,
+    /// The asserted addresses, the observed one when the host asserts none.
+    Asserted,
+    /// The observed address followed by the asserted ones.
+    Both,
+}
+
+impl AddressPolicy {
+    /// Parses an address policy name as given on the command line.
+    ///
+    /// ### Parameters
+    /// - `value`: One of `observed`, `asserted` or `both`.
+    ///
+    /// ### Returns
+    /// - `Result<AddressPolicy, &'static str>`: Ok with the policy, Err otherwise.
+    ///
+    /// ### Example
+    /// ```rust
+    /// assert_eq!(AddressPolicy::parse("both"), Ok(AddressPolicy::Both));
+    /// ```
+    pub fn parse(value: &str) -> Result<AddressPolicy, &'static str> {
+        match value {
+            "observed" => Ok(AddressPolicy::Observed),
+            "asserted" => Ok(AddressPolicy::Asserted),
+            "both" => Ok(AddressPolicy::Both),
+            _ => Err("Unknown address policy"),
+        }
+    }
+
+    /// Builds the record of a registration according to the policy, the asserted addresses
+    /// being ignored, and left unchecked, when the policy does not record them.
+    ///
+    /// ### Parameters
+    /// - `observed`: The peer address of the connection.
+    /// - `asserted`: The addresses claimed by the host.
+    ///
+    /// ### Returns
+    /// - `Result<HostRecord, String>`: Ok with the record, Err if an asserted address the
+    ///   policy records is invalid.
+    ///
+    /// ### Example
+    /// ```rust
+    /// let record = AddressPolicy::Both.record("203.0.113.7".to_string(), &["192.168.1.5".to_string()]).unwrap();
+    /// assert_eq!(record.ips(), "203.0.113.7, 192.168.1.5");
+    /// ```
+    pub fn record(&self, observed: String, asserted: &[String]) -> Result<HostRecord, String> {
+        let asserted = match self {
+            AddressPolicy::Observed => Vec::new(),
+            AddressPolicy::Asserted | AddressPolicy::Both => asserted
+                .iter()
+                .map(|ip| {
+                    ip.parse::<IpAddr>()
+                        .map(|ip| ip.to_string())
+                        .map_err(|_e| format!("Invalid address: {}", ip))
+                })
+                .collect::<Result<Vec<String>, String>>()?,
+        };
+
+        let observed = Address {
+            ip: observed,
+            source: AddressSource::Observed,
+        };
+        let mut addresses = match self {
+            AddressPolicy::Observed => vec![observed],
+            AddressPolicy::Asserted if !asserted.is_empty() => Vec::new(),
+            AddressPolicy::Asserted | AddressPolicy::Both => vec![observed],
+        };
+
+        for ip in asserted {
+            if !addresses.iter().any(|a| a.ip == ip) {
+                addresses.push(Address {
+                    ip,
+                    source: AddressSource::Asserted,
+                });
+            }
+        }
+
+        Ok(HostRecord {
+            addresses,
+            ..Default::default()
+        })
+    }
+}
+
+/// Server settings that are not related to authentication.
+#[derive(Clone, Debug, Default)]
+pub struct ServerOptions {
+    /// Which addresses of a registration are recorded.
+    pub address_policy: AddressPolicy,
+    /// Age above which the record of a host that stopped reporting is removed, never when `None`.
+    pub expire_after: Option<Duration>,
+    /// Webhooks notified of the changes made to host records.
+    pub webhooks: WebhookOptions,
+    /// Local command run on the changes made to host records.
+    pub hooks: HookOptions,
+    /// Access control of the metrics endpoint.
+    pub metrics: MetricsOptions,
+    /// Audit log of the reads, writes, deletes and authentication failures.
+    pub audit: AuditLog,
+    /// Rate limits of the clients and cap on the concurrent negotiations.
+    pub limits: LimitOptions,
+    /// Replication of the records with the other servers of a cluster.
+    pub cluster: ClusterOptions,
+    /// Principals allowed to export and import snapshots of the records.
+    pub admins: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AddressPolicy, AddressSource};
+
+    fn sources(policy: AddressPolicy, asserted: &[&str]) -> Vec<(String, AddressSource)> {
+        let asserted: Vec<String> = asserted.iter().map(|a| a.to_string()).collect();
+        policy
+            .record("203.0.113.7".to_string(), &asserted)
+            .unwrap()
+            .addresses
+            .into_iter()
+            .map(|a| (a.ip, a.source))
+            .collect()
+    }
+
+    #[test]
+    fn policies_select_addresses() {
+        let observed = ("203.0.113.7".to_string(), AddressSource::Observed);
+        let lan = ("192.168.1.5".to_string(), AddressSource::Asserted);
+
+        assert_eq!(
+            sources(AddressPolicy::Observed, &["192.168.1.5"]),
+            vec![observed.clone()]
+        );
+        assert_eq!(
+            sources(AddressPolicy::Asserted, &["192.168.1.5"]),
+            vec![lan.clone()]
+        );
+        assert_eq!(
+            sources(AddressPolicy::Asserted, &[]),
+            vec![observed.clone()]
+        );
+        assert_eq!(
+            sources(AddressPolicy::Both, &["192.168.1.5", "203.0.113.7"]),
+            vec![observed, lan]
+        );
+    }
+
+    #[test]
+    fn invalid_asserted_addresses_are_rejected() {
+        let error = AddressPolicy::Both
+            .record("203.0.113.7".to_string(), &["not-an-ip".to_string()])
+            .unwrap_err();
+
+        assert_eq!(error, "Invalid address: not-an-ip");
+        assert_eq!(
+            sources(AddressPolicy::Observed, &["not-an-ip"]),
+            vec![("203.0.113.7".to_string(), AddressSource::Observed)]
+        );
+    }
+}
@@ -0,0 +1,5 @@
+mod queue;
+mod worker;
+
+pub use queue::*;
+pub use worker::*;
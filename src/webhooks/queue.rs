@@ -0,0 +1,137 @@
+use std::path::{Path, PathBuf};
+
+use rocket::serde::json::serde_json;
+use rocket::serde::{Deserialize, Serialize};
+
+use crate::events::EventKind;
+
+/// Pending delivery of an event to a webhook.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Delivery {
+    /// Unique identifier of the delivery, sent to the webhook so it can drop duplicates.
+    pub id: String,
+    pub url: String,
+    pub event: EventKind,
+    /// The JSON payload, signed as is.
+    pub body: String,
+    /// Number of failed attempts so far.
+    pub attempts: usize,
+    /// Time of the next attempt, in milliseconds since the Unix epoch.
+    pub next_attempt: u64,
+}
+
+/// Queue of the webhook deliveries not acknowledged yet, optionally persisted to a file
+/// so that they survive a restart of the server.
+#[derive(Debug, Default)]
+pub struct DeliveryQueue {
+    path: Option<PathBuf>,
+    pub pending: Vec<Delivery>,
+}
+
+impl DeliveryQueue {
+    /// Opens a delivery queue, loading the deliveries left in its file.
+    ///
+    /// ### Parameters
+    /// - `path`: Optional file persisting the queue, the queue living in memory only when `None`.
+    ///
+    /// ### Returns
+    /// - `Result<DeliveryQueue, String>`: Ok with the queue, Err with error message otherwise.
+    ///
+    /// ### Example
+    /// ```rust
+    /// let queue = DeliveryQueue::open(Some(PathBuf::from("/var/lib/rping/webhooks.json"))).unwrap();
+    /// ```
+    pub fn open(path: Option<PathBuf>) -> Result<DeliveryQueue, String> {
+        let pending = match &path {
+            Some(p) if p.exists() => {
+                let content = std::fs::read_to_string(p)
+                    .map_err(|e| format!("Cannot read webhook queue {}: {}", p.display(), e))?;
+                serde_json::from_str(&content)
+                    .map_err(|e| format!("Corrupted webhook queue {}: {}", p.display(), e))?
+            }
+            _ => Vec::new(),
+        };
+
+        Ok(DeliveryQueue { path, pending })
+    }
+
+    /// Writes the queue to its file, if any. The file is replaced atomically so that
+    /// a crash never leaves a truncated queue behind.
+    ///
+    /// ### Returns
+    /// - `Result<(), String>`: Ok if the queue is saved, Err with error message otherwise.
+    pub fn save(&self) -> Result<(), String> {
+        match &self.path {
+            Some(path) => save_deliveries(path, &self.pending),
+            None => Ok(()),
+        }
+    }
+
+    /// File persisting the queue.
+    ///
+    /// ### Returns
+    /// - `Option<&Path>`: The file, `None` if the queue lives in memory only.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Time of the earliest pending attempt.
+    ///
+    /// ### Returns
+    /// - `Option<u64>`: The time in milliseconds since the Unix epoch, `None` if the queue is empty.
+    pub fn next_due(&self) -> Option<u64> {
+        self.pending.iter().map(|d| d.next_attempt).min()
+    }
+}
+
+/// Writes the pending deliveries to the file of a queue, replacing it at once so that a
+/// crash never leaves it half written. The write blocks, so async code runs it apart.
+///
+/// ### Parameters
+/// - `path`: The file persisting the queue.
+/// - `pending`: The deliveries not acknowledged yet.
+///
+/// ### Returns
+/// - `Result<(), String>`: Ok if the queue is saved, Err with error message otherwise.
+///
+/// ### Example
+/// ```rust
+/// let pending = queue.pending.clone();
+/// tokio::task::spawn_blocking(move || save_deliveries(&path, &pending)).await?;
+/// ```
+pub fn save_deliveries(path: &Path, pending: &[Delivery]) -> Result<(), String> {
+    let content = serde_json::to_string(pending).map_err(|e| e.to_string())?;
+    let temporary = path.with_extension("tmp");
+    std::fs::write(&temporary, content)
+        .and_then(|_| std::fs::rename(&temporary, path))
+        .map_err(|e| format!("Cannot save webhook queue {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Delivery, DeliveryQueue};
+    use crate::events::EventKind;
+
+    #[test]
+    fn queue_survives_reopening() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("webhooks.json");
+        let delivery = Delivery {
+            id: "42".to_string(),
+            url: "http://127.0.0.1:9/hook".to_string(),
+            event: EventKind::Created,
+            body: "{}".to_string(),
+            attempts: 2,
+            next_attempt: 1000,
+        };
+
+        let mut queue = DeliveryQueue::open(Some(path.clone())).unwrap();
+        queue.pending.push(delivery.clone());
+        queue.save().unwrap();
+
+        let reopened = DeliveryQueue::open(Some(path)).unwrap();
+        assert_eq!(reopened.pending, vec![delivery]);
+        assert_eq!(reopened.next_due(), Some(1000));
+    }
+}
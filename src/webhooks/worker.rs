@@ -0,0 +1,491 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use librping::RetryPolicy;
+use rocket::serde::json::serde_json;
+use rocket::tokio;
+use rocket::tokio::sync::Notify;
+use rocket::tokio::sync::broadcast::{self, error::RecvError};
+use rocket::tokio::task::JoinSet;
+use sha2::Sha256;
+
+use crate::events::HostEvent;
+use crate::webhooks::{Delivery, DeliveryQueue, save_deliveries};
+
+/// Longest time the worker sleeps without looking at the queue.
+const IDLE_PERIOD: Duration = Duration::from_secs(60);
+/// Time allowed to a webhook to answer a delivery.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+/// Number of deliveries posted at the same time.
+const CONCURRENT_DELIVERIES: usize = 8;
+
+/// Webhooks notified of the changes made to host records.
+#[derive(Clone, Debug)]
+pub struct WebhookOptions {
+    /// URLs every event is posted to.
+    pub urls: Vec<String>,
+    /// Secret signing the payloads, sent unsigned when `None`.
+    pub secret: Option<String>,
+    /// File persisting the pending deliveries, kept in memory only when `None`.
+    pub queue: Option<PathBuf>,
+    /// Retry policy of each delivery, the deadline being ignored.
+    pub retry: RetryPolicy,
+}
+
+impl Default for WebhookOptions {
+    fn default() -> Self {
+        WebhookOptions {
+            urls: Vec::new(),
+            secret: None,
+            queue: None,
+            retry: RetryPolicy {
+                max_attempts: 10,
+                base_delay: Duration::from_secs(1),
+                max_delay: Duration::from_secs(300),
+                deadline: None,
+            },
+        }
+    }
+}
+
+/// Spawns the background tasks posting the events to the webhooks.
+///
+/// Each event is queued once per webhook and posted with the headers `X-Rping-Event`,
+/// `X-Rping-Delivery` and, when a secret is set, `X-Rping-Signature: sha256=<hex>` holding
+/// the HMAC-SHA256 of the body. Failed deliveries are retried with an exponential backoff
+/// until the retry policy gives up on them.
+///
+/// Events are queued by a task of their own as soon as they are received, another task
+/// posting the due deliveries, a few at a time, so that slow webhooks never hold the events
+/// back. A third task writes the queue to its file off the async workers, the changes made
+/// during a write being saved together by the next one.
+///
+/// ### Parameters
+/// - `options`: The webhooks to notify.
+/// - `events`: Subscription to the event bus.
+///
+/// ### Returns
+/// - `Result<(), String>`: Ok if the worker started, Err if the queue cannot be opened.
+///
+/// ### Example
+/// ```rust
+/// let options = WebhookOptions { urls: vec!["https://cmdb.example.com/rping".to_string()], ..Default::default() };
+/// spawn_webhooks(options, events.subscribe()).unwrap();
+/// ```
+pub fn spawn_webhooks(
+    options: WebhookOptions,
+    events: broadcast::Receiver<HostEvent>,
+) -> Result<(), String> {
+    let queue = DeliveryQueue::open(options.queue.clone())?;
+    let client = reqwest::Client::builder()
+        .timeout(DELIVERY_TIMEOUT)
+        .build()
+        .map_err(|e| format!("Cannot create webhook client: {}", e))?;
+
+    if !queue.pending.is_empty() {
+        log::info!("Resuming {} webhook deliveries", queue.pending.len());
+    }
+
+    let path = queue.path().map(PathBuf::from);
+    let queue = Arc::new(Mutex::new(queue));
+    let queued = Arc::new(Notify::new());
+    let changed = Arc::new(Notify::new());
+
+    if let Some(path) = path {
+        tokio::spawn(persist(queue.clone(), changed.clone(), path));
+    }
+    tokio::spawn(intake(
+        queue.clone(),
+        queued.clone(),
+        changed.clone(),
+        options.clone(),
+        events,
+    ));
+    tokio::spawn(deliver(queue, queued, changed, options, client));
+
+    Ok(())
+}
+
+/// Queues every event received, waking the delivery and persistence tasks up.
+async fn intake(
+    queue: Arc<Mutex<DeliveryQueue>>,
+    queued: Arc<Notify>,
+    changed: Arc<Notify>,
+    options: WebhookOptions,
+    mut events: broadcast::Receiver<HostEvent>,
+) {
+    loop {
+        match events.recv().await {
+            Ok(event) => {
+                enqueue(&mut lock(&queue), &options, &event);
+                queued.notify_one();
+                changed.notify_one();
+            }
+            Err(RecvError::Lagged(missed)) => {
+                log::warn!("Webhooks fell behind and missed {} events", missed)
+            }
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+/// Posts the due deliveries as they come, at most [`CONCURRENT_DELIVERIES`] at a time,
+/// rescheduling or dropping the failed ones.
+async fn deliver(
+    queue: Arc<Mutex<DeliveryQueue>>,
+    queued: Arc<Notify>,
+    changed: Arc<Notify>,
+    options: WebhookOptions,
+    client: reqwest::Client,
+) {
+    let mut sending = HashSet::new();
+    let mut in_flight = JoinSet::new();
+
+    loop {
+        let due: Vec<Delivery> = {
+            let now = now_millis();
+            let queue = lock(&queue);
+            queue
+                .pending
+                .iter()
+                .filter(|d| d.next_attempt <= now && !sending.contains(&d.id))
+                .take(CONCURRENT_DELIVERIES.saturating_sub(in_flight.len()))
+                .cloned()
+                .collect()
+        };
+        for delivery in due {
+            sending.insert(delivery.id.clone());
+            let (client, secret) = (client.clone(), options.secret.clone());
+            in_flight.spawn(async move {
+                let outcome = post(&client, &delivery, secret.as_deref()).await;
+                (delivery.id, outcome)
+            });
+        }
+
+        let wait = if in_flight.len() >= CONCURRENT_DELIVERIES {
+            IDLE_PERIOD
+        } else {
+            lock(&queue)
+                .pending
+                .iter()
+                .filter(|d| !sending.contains(&d.id))
+                .map(|d| Duration::from_millis(d.next_attempt.saturating_sub(now_millis())))
+                .min()
+                .unwrap_or(IDLE_PERIOD)
+                .min(IDLE_PERIOD)
+        };
+
+        tokio::select! {
+            Some(done) = in_flight.join_next(), if !in_flight.is_empty() => match done {
+                Ok((id, outcome)) => {
+                    sending.remove(&id);
+                    settle(&mut lock(&queue), &options, &id, outcome);
+                    changed.notify_one();
+                }
+                Err(e) => log::error!("Webhook delivery task failed: {}", e),
+            },
+            _ = queued.notified() => {}
+            _ = tokio::time::sleep(wait) => {}
+        }
+    }
+}
+
+/// Writes the queue to its file whenever it changed, from a copy taken under the lock and
+/// written by a blocking thread.
+async fn persist(queue: Arc<Mutex<DeliveryQueue>>, changed: Arc<Notify>, path: PathBuf) {
+    loop {
+        changed.notified().await;
+        let pending = lock(&queue).pending.clone();
+        let path = path.clone();
+        let saved = tokio::task::spawn_blocking(move || save_deliveries(&path, &pending))
+            .await
+            .unwrap_or_else(|e| Err(format!("Webhook queue task failed: {}", e)));
+        if let Err(e) = saved {
+            log::error!("{}", e);
+        }
+    }
+}
+
+/// Locks the queue, shared by the worker tasks but never across an await.
+fn lock(queue: &Mutex<DeliveryQueue>) -> MutexGuard<'_, DeliveryQueue> {
+    queue.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Queues the delivery of an event to every webhook.
+fn enqueue(queue: &mut DeliveryQueue, options: &WebhookOptions, event: &HostEvent) {
+    let body = match serde_json::to_string(event) {
+        Ok(body) => body,
+        Err(e) => {
+            log::error!("Cannot serialize event {}: {}", event.id, e);
+            return;
+        }
+    };
+
+    for url in &options.urls {
+        queue.pending.push(Delivery {
+            id: format!("{:016x}", rand::random::<u64>()),
+            url: url.clone(),
+            event: event.kind,
+            body: body.clone(),
+            attempts: 0,
+            next_attempt: now_millis(),
+        });
+    }
+}
+
+/// Removes an acknowledged delivery from the queue, or reschedules a failed one until the
+/// retry policy gives up on it.
+fn settle(
+    queue: &mut DeliveryQueue,
+    options: &WebhookOptions,
+    id: &str,
+    outcome: Result<(), String>,
+) {
+    let Some(index) = queue.pending.iter().position(|d| d.id == id) else {
+        return;
+    };
+
+    if let Err(e) = outcome {
+        let delivery = &mut queue.pending[index];
+        delivery.attempts += 1;
+        if delivery.attempts < options.retry.max_attempts {
+            let delay = options.retry.backoff(delivery.attempts);
+            log::warn!(
+                "Webhook delivery {} to {} failed, retrying in {}ms: {}",
+                delivery.id,
+                delivery.url,
+                delay.as_millis(),
+                e
+            );
+            delivery.next_attempt = now_millis() + delay.as_millis() as u64;
+            return;
+        }
+        log::error!(
+            "Giving up webhook delivery {} to {} after {} attempts: {}",
+            delivery.id,
+            delivery.url,
+            delivery.attempts,
+            e
+        );
+    }
+
+    queue.pending.remove(index);
+}
+
+/// Posts one delivery, any status but a success being a failure.
+async fn post(
+    client: &reqwest::Client,
+    delivery: &Delivery,
+    secret: Option<&str>,
+) -> Result<(), String> {
+    let mut request = client
+        .post(&delivery.url)
+        .header("Content-Type", "application/json")
+        .header("X-Rping-Event", delivery.event.to_string())
+        .header("X-Rping-Delivery", &delivery.id);
+    if let Some(secret) = secret {
+        request = request.header(
+            "X-Rping-Signature",
+            format!("sha256={}", sign(secret, &delivery.body)),
+        );
+    }
+
+    let response = request
+        .body(delivery.body.clone())
+        .send()
+        .await
+        .map_err(|e| format!("Send error: {}", e))?;
+
+    match response.status() {
+        s if s.is_success() => Ok(()),
+        s => Err(format!("Webhook answered {}", s)),
+    }
+}
+
+/// Signs a payload the way webhooks can check it.
+///
+/// ### Parameters
+/// - `secret`: The shared secret.
+/// - `body`: The payload.
+///
+/// ### Returns
+/// - `String`: The lowercase hexadecimal HMAC-SHA256 of the payload.
+///
+/// ### Example
+/// ```rust
+/// let signature = sign("s3cret", r#"{"event":"created"}"#);
+/// ```
+pub fn sign(secret: &str, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body.as_bytes());
+    format!("{:x}", mac.finalize().into_bytes())
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use librping::RetryPolicy;
+    use rocket::futures::lock::Mutex;
+    use rocket::http::{ContentType, Status};
+    use rocket::request::{FromRequest, Outcome};
+    use rocket::serde::json::serde_json;
+    use rocket::{Request, State};
+    use rocket_krb5::FakeAcceptor;
+
+    use super::{WebhookOptions, sign};
+    use crate::auth::Authenticators;
+    use crate::events::HostEvent;
+    use crate::testing::{launch, negotiate, server};
+    use crate::types::ServerOptions;
+
+    /// A delivery body along with its signature.
+    type Signed = (String, Option<String>);
+
+    /// Deliveries received by the test listener.
+    #[derive(Clone, Default)]
+    struct Received(Arc<Mutex<Vec<Signed>>>);
+
+    /// Number of requests the test listener fails before accepting deliveries.
+    struct Failures(Mutex<usize>);
+
+    struct Signature(Option<String>);
+
+    #[rocket::async_trait]
+    impl<'r> FromRequest<'r> for Signature {
+        type Error = ();
+        async fn from_request(request: &'r Request<'_>) -> Outcome<Self, ()> {
+            let header = request.headers().get_one("X-Rping-Signature");
+            Outcome::Success(Signature(header.map(String::from)))
+        }
+    }
+
+    #[rocket::post("/hook", data = "<body>")]
+    async fn hook(
+        body: String,
+        signature: Signature,
+        received: &State<Received>,
+        failures: &State<Failures>,
+    ) -> Status {
+        let mut failures = failures.0.lock().await;
+        if *failures > 0 {
+            *failures -= 1;
+            return Status::ServiceUnavailable;
+        }
+        received.0.lock().await.push((body, signature.0));
+        Status::NoContent
+    }
+
+    /// Launches the test listener on an ephemeral port.
+    async fn listen(failures: usize) -> (String, Received) {
+        let received = Received::default();
+        let rocket = rocket::build()
+            .mount("/", rocket::routes![hook])
+            .manage(received.clone())
+            .manage(Failures(Mutex::new(failures)));
+
+        let (url, _shutdown) = launch(rocket).await;
+        (url + "/hook", received)
+    }
+
+    async fn wait_for(received: &Received, count: usize) -> Vec<Signed> {
+        for _ in 0..200 {
+            let deliveries = received.0.lock().await.clone();
+            if deliveries.len() >= count {
+                return deliveries;
+            }
+            rocket::tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("webhook not delivered");
+    }
+
+    async fn wait_for_queue(path: &std::path::Path, expected: &str) {
+        for _ in 0..200 {
+            if std::fs::read_to_string(path).is_ok_and(|content| content == expected) {
+                return;
+            }
+            rocket::tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("webhook queue never became {}", expected);
+    }
+
+    async fn register(client: &rocket::local::asynchronous::Client, ip: &str) {
+        client
+            .post("/add")
+            .header(ContentType::JSON)
+            .header(negotiate("host/h1@EXAMPLE.COM"))
+            .remote(format!("{}:4242", ip).parse::<SocketAddr>().unwrap())
+            .body(r#"{"hostname":"h1"}"#)
+            .dispatch()
+            .await;
+    }
+
+    fn options(url: String, queue: Option<std::path::PathBuf>) -> ServerOptions {
+        ServerOptions {
+            webhooks: WebhookOptions {
+                urls: vec![url],
+                secret: Some("s3cret".to_string()),
+                queue,
+                retry: RetryPolicy {
+                    max_attempts: 5,
+                    base_delay: Duration::from_millis(10),
+                    max_delay: Duration::from_millis(50),
+                    deadline: None,
+                },
+            },
+            ..Default::default()
+        }
+    }
+
+    #[rocket::async_test]
+    async fn changes_are_posted_signed() {
+        let (url, received) = listen(0).await;
+        let auth = Authenticators::kerberos(Arc::new(FakeAcceptor::default()));
+        let client = server(auth, options(url, None)).await;
+
+        register(&client, "10.0.0.1").await;
+        register(&client, "10.0.0.1").await;
+        register(&client, "10.0.0.2").await;
+
+        let deliveries = wait_for(&received, 2).await;
+        let events: Vec<HostEvent> = deliveries
+            .iter()
+            .map(|(body, _)| serde_json::from_str(body).unwrap())
+            .collect();
+        assert_eq!(events[0].new_ip.as_deref(), Some("10.0.0.1"));
+        assert_eq!(events[1].old_ip.as_deref(), Some("10.0.0.1"));
+        assert_eq!(events[1].new_ip.as_deref(), Some("10.0.0.2"));
+        assert_eq!(events[1].principal.as_deref(), Some("host/h1@EXAMPLE.COM"));
+        for (body, signature) in deliveries {
+            assert_eq!(signature, Some(format!("sha256={}", sign("s3cret", &body))));
+        }
+    }
+
+    #[rocket::async_test]
+    async fn failed_deliveries_are_retried_from_the_queue() {
+        let (url, received) = listen(2).await;
+        let dir = tempfile::tempdir().unwrap();
+        let queue = dir.path().join("webhooks.json");
+        let auth = Authenticators::kerberos(Arc::new(FakeAcceptor::default()));
+        let client = server(auth, options(url, Some(queue.clone()))).await;
+
+        register(&client, "10.0.0.1").await;
+
+        let deliveries = wait_for(&received, 1).await;
+        assert!(deliveries[0].0.contains(r#""event":"created""#));
+        wait_for_queue(&queue, "[]").await;
+    }
+}
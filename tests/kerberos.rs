@@ -0,0 +1,98 @@
+//! End-to-end tests running `rping serve`, `send` and `list` against a throwaway MIT
+//! Kerberos realm. They are skipped when the KDC binaries are not installed.
+
+mod support;
+
+use std::process::{Command, Stdio};
+
+use librping::{ClientOptions, receive_list, send_dns};
+use support::{Guard, Kdc, REALM, free_port, wait_for_port};
+
+/// Starts `rping serve` on an ephemeral port, authenticating with the service keytab.
+fn serve(kdc: &Kdc) -> (Guard, u16) {
+    let keytab = kdc.add_principal("HTTP/localhost", "service.keytab");
+    let port = free_port();
+
+    let server = Command::new(env!("CARGO_BIN_EXE_rping"))
+        .args(["serve", "--port", &port.to_string()])
+        .args(["--principal", &format!("HTTP/localhost@{}", REALM)])
+        .args(["--keytab", &keytab.display().to_string()])
+        .envs(kdc.env())
+        .env("ROCKET_ADDRESS", "127.0.0.1")
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("spawn rping serve");
+    let server = Guard(server);
+    wait_for_port(port);
+
+    (server, port)
+}
+
+/// Runs the rping binary as a client of the test realm.
+fn rping(kdc: &Kdc, action: &str, url: &str, client_keytab: &str) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_rping"))
+        .args([action, "--url", url, "--realm", REALM])
+        .args(["--client-keytab", client_keytab])
+        .envs(kdc.env())
+        .output()
+        .expect("run rping client");
+
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+#[test]
+fn serve_send_and_list_interoperate() {
+    let Some(kdc) = Kdc::start() else {
+        return;
+    };
+    let client_keytab = kdc.add_principal("alice", "client.keytab");
+    let client_keytab = client_keytab.display().to_string();
+    let (_server, port) = serve(&kdc);
+    let add_url = format!("http://localhost:{}/add", port);
+    let get_url = format!("http://localhost:{}/get", port);
+
+    // `rping send` registers the hostname of this machine.
+    let sent = rping(&kdc, "send", &add_url, &client_keytab);
+
+    // The library reads the realm configuration from the environment of this process,
+    // and this is the only test of the binary touching it.
+    for (key, value) in kdc.env() {
+        unsafe { std::env::set_var(key, value) };
+    }
+    unsafe {
+        std::env::set_var("KRB5_CLIENT_KTNAME", &client_keytab);
+        std::env::set_var("KRB5CCNAME", "MEMORY:rping-test");
+    }
+
+    let options = ClientOptions {
+        url: add_url,
+        realm: REALM.to_string(),
+        ..Default::default()
+    };
+    let runtime = rocket::tokio::runtime::Runtime::new().unwrap();
+    runtime
+        .block_on(send_dns("h1".to_string(), &options))
+        .expect("registration through librping");
+
+    let list_options = ClientOptions {
+        url: get_url.clone(),
+        ..options
+    };
+    let hosts = runtime
+        .block_on(receive_list(&list_options))
+        .expect("listing through librping");
+    assert!(
+        hosts
+            .iter()
+            .any(|d| d.hostname == "h1" && d.ip == "127.0.0.1")
+    );
+    let this_host = hostname::get().unwrap().to_string_lossy().to_string();
+    assert!(
+        hosts.iter().any(|d| d.hostname == this_host),
+        "rping send output: {}",
+        sent
+    );
+
+    let listed = rping(&kdc, "list", &get_url, &client_keytab);
+    assert!(listed.contains("h1"), "rping list output: {}", listed);
+}
@@ -0,0 +1,215 @@
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use tempfile::TempDir;
+
+/// Realm provisioned by the throwaway KDC.
+pub const REALM: &str = "RPING.TEST";
+
+/// Throwaway MIT Kerberos realm living in a temporary directory.
+/// The KDC process is killed and the directory removed when dropped.
+pub struct Kdc {
+    dir: TempDir,
+    process: Option<Child>,
+}
+
+impl Kdc {
+    /// Provisions a realm and starts its KDC, `None` when the MIT KDC binaries are absent.
+    ///
+    /// ### Returns
+    /// - `Option<Kdc>`: The running KDC, or `None` if it cannot be provisioned here.
+    pub fn start() -> Option<Kdc> {
+        let binaries = ["krb5kdc", "kdb5_util", "kadmin.local"];
+        if let Some(missing) = binaries.iter().find(|b| find_binary(b).is_none()) {
+            eprintln!(
+                "skipping: {} not found, cannot provision a local KDC",
+                missing
+            );
+            return None;
+        }
+
+        let dir = tempfile::tempdir().expect("temporary directory");
+        let port = free_port();
+        write_config(dir.path(), port);
+
+        let mut kdc = Kdc { dir, process: None };
+        kdc.run(
+            "kdb5_util",
+            &["create", "-s", "-r", REALM, "-P", "master-password"],
+        );
+        let process = kdc
+            .command("krb5kdc")
+            .args(["-n", "-r", REALM])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("spawn krb5kdc");
+        kdc.process = Some(process);
+        wait_for_port(port);
+
+        Some(kdc)
+    }
+
+    /// Creates a principal with a random key and exports it into a keytab.
+    ///
+    /// ### Parameters
+    /// - `principal`: The principal to create, without realm.
+    /// - `keytab`: The keytab file name, relative to the realm directory.
+    ///
+    /// ### Returns
+    /// - `PathBuf`: The path of the keytab.
+    pub fn add_principal(&self, principal: &str, keytab: &str) -> PathBuf {
+        let path = self.dir.path().join(keytab);
+        self.run(
+            "kadmin.local",
+            &[
+                "-r",
+                REALM,
+                "-q",
+                &format!("addprinc -randkey {}", principal),
+            ],
+        );
+        self.run(
+            "kadmin.local",
+            &[
+                "-r",
+                REALM,
+                "-q",
+                &format!("ktadd -k {} {}", path.display(), principal),
+            ],
+        );
+        path
+    }
+
+    /// Environment variables pointing krb5 programs to this realm.
+    ///
+    /// ### Returns
+    /// - `Vec<(&'static str, PathBuf)>`: The variables and their values.
+    pub fn env(&self) -> Vec<(&'static str, PathBuf)> {
+        vec![
+            ("KRB5_CONFIG", self.dir.path().join("krb5.conf")),
+            ("KRB5_KDC_PROFILE", self.dir.path().join("kdc.conf")),
+            ("KRB5RCACHETYPE", PathBuf::from("none")),
+        ]
+    }
+
+    /// Builds a command running a krb5 binary against this realm.
+    pub fn command(&self, binary: &str) -> Command {
+        let mut command = Command::new(find_binary(binary).expect("krb5 binary"));
+        command.envs(self.env());
+        command
+    }
+
+    fn run(&self, binary: &str, args: &[&str]) {
+        let output = self
+            .command(binary)
+            .args(args)
+            .output()
+            .expect("run krb5 binary");
+        assert!(
+            output.status.success(),
+            "{} {:?} failed: {}",
+            binary,
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+}
+
+impl Drop for Kdc {
+    fn drop(&mut self) {
+        if let Some(process) = self.process.as_mut() {
+            let _ = process.kill();
+            let _ = process.wait();
+        }
+    }
+}
+
+/// Child process killed when dropped, for servers started by the tests.
+pub struct Guard(pub Child);
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// Returns a port nobody listens on right now.
+pub fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .and_then(|l| l.local_addr())
+        .map(|a| a.port())
+        .expect("ephemeral port")
+}
+
+/// Waits until something accepts connections on a local port.
+pub fn wait_for_port(port: u16) {
+    let started = Instant::now();
+    while TcpStream::connect(("127.0.0.1", port)).is_err() {
+        assert!(
+            started.elapsed() < Duration::from_secs(10),
+            "nothing listening on port {}",
+            port
+        );
+        sleep(Duration::from_millis(50));
+    }
+}
+
+/// Looks a binary up in the PATH and the usual sbin directories.
+fn find_binary(name: &str) -> Option<PathBuf> {
+    let path = std::env::var("PATH").unwrap_or_default();
+    path.split(':')
+        .chain(["/usr/sbin", "/usr/local/sbin", "/sbin"])
+        .map(|dir| Path::new(dir).join(name))
+        .find(|candidate| candidate.is_file())
+}
+
+fn write_config(dir: &Path, port: u16) {
+    let krb5_conf = format!(
+        "[libdefaults]
+    default_realm = {realm}
+    dns_lookup_kdc = false
+    dns_lookup_realm = false
+    rdns = false
+    dns_canonicalize_hostname = false
+    udp_preference_limit = 1
+
+[realms]
+    {realm} = {{
+        kdc = 127.0.0.1:{port}
+    }}
+
+[domain_realm]
+    localhost = {realm}
+",
+        realm = REALM,
+        port = port,
+    );
+    let kdc_conf = format!(
+        "[kdcdefaults]
+    kdc_listen = 127.0.0.1:{port}
+    kdc_tcp_listen = 127.0.0.1:{port}
+
+[realms]
+    {realm} = {{
+        database_name = {dir}/principal
+        key_stash_file = {dir}/stash
+        acl_file = {dir}/kadm5.acl
+    }}
+
+[logging]
+    kdc = FILE:{dir}/kdc.log
+",
+        realm = REALM,
+        port = port,
+        dir = dir.display(),
+    );
+
+    std::fs::write(dir.join("krb5.conf"), krb5_conf).expect("write krb5.conf");
+    std::fs::write(dir.join("kdc.conf"), kdc_conf).expect("write kdc.conf");
+    std::fs::write(dir.join("kadm5.acl"), "").expect("write kadm5.acl");
+}